@@ -0,0 +1,138 @@
+// Bridge2H: the C++ declarations expected by the #[bridge] blocks and the #[layout_check]
+// structs of some Rust sources, built by the same code as the macro.
+use std::fs;
+use std::path::{Path, PathBuf};
+use proc_macro2::TokenTree;
+use quote::ToTokens;
+use syn::{Attribute, Item, ItemStruct};
+use directcpp_codegen::buildcode::FFIBuilder;
+use directcpp_codegen::header::{header_guard, CppHeader};
+use directcpp_codegen::layout::{add_layout_check, struct_layout, Layouts};
+use directcpp_codegen::target::Target;
+
+/// Collects Rust sources and generates the header of their `#[bridge]` blocks.
+#[derive(Default)]
+pub struct Bridge2H {
+	files: Vec<PathBuf>,
+	sources: Vec<String>,
+}
+
+/// `#[bridge]`, `#[directcpp::bridge(goon)]`... matched by the last path segment.
+fn find_attr(attrs: &[Attribute], name: &str) -> Option<usize> {
+	attrs.iter().position(|x| x.path().segments.last().map(|s| s.ident == name).unwrap_or(false))
+}
+
+fn has_flag(attr: &Attribute, flag: &str) -> bool {
+	match attr.meta.require_list() {
+		Ok(list) => list.tokens.clone().into_iter().any(|tt| matches!(tt, TokenTree::Ident(id) if id == flag)),
+		Err(_) => false,
+	}
+}
+
+/// Add the bridge blocks to `header`, and collect the structs for the layout checks.
+fn collect_items(items: &[Item], target: Target, header: &mut CppHeader, structs: &mut Vec<(ItemStruct, bool)>) -> Result<(), String> {
+	for item in items {
+		match item {
+			Item::ForeignMod(fm) => {
+				let idx = match find_attr(&fm.attrs, "bridge") {
+					Some(x) => x,
+					None => continue,
+				};
+				let mut fm = fm.clone();
+				let attr = fm.attrs.remove(idx);
+				let mut bb = FFIBuilder::new(!has_flag(&attr, "goon"));
+				bb.set_target(target);
+				bb.build_bridge_code(fm.to_token_stream()).map_err(|e| e.to_string())?;
+				header.append(bb.cpp_header());
+			},
+			Item::Struct(st) => {
+				structs.push((st.clone(), find_attr(&st.attrs, "layout_check").is_some()));
+			},
+			Item::Mod(m) => {
+				if let Some((_, items)) = &m.content {
+					collect_items(items, target, header, structs)?;
+				}
+			},
+			_ => {}
+		}
+	}
+	Ok(())
+}
+
+/// The layouts of all structs whose fields are known, nested ones first.
+fn known_layouts(structs: &[(ItemStruct, bool)], target: Target) -> Layouts {
+	let mut known = Layouts::new();
+	loop {
+		let cnt = known.len();
+		for (st, _) in structs {
+			let name = st.ident.to_string();
+			if known.contains_key(&name) {
+				continue;
+			}
+			if let Ok(Some((size, align, _))) = struct_layout(st, &known, target) {
+				known.insert(name, (size, align));
+			}
+		}
+		if known.len() == cnt {
+			return known;
+		}
+	}
+}
+
+impl Bridge2H {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add a Rust source file.
+	pub fn file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+		self.files.push(path.as_ref().to_path_buf());
+		self
+	}
+
+	/// Add Rust source code directly.
+	pub fn source(&mut self, code: &str) -> &mut Self {
+		self.sources.push(code.to_string());
+		self
+	}
+
+	/// Generate the header. `guard` is the prefix of its macros, e.g. `PROVE_FFI`
+	/// gives `PROVE_FFI_ENABLE()`.
+	pub fn generate(&self, guard: &str) -> Result<String, String> {
+		let mut sources = Vec::new();
+		for path in &self.files {
+			let code = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+			sources.push((path.display().to_string(), code));
+		}
+		for code in &self.sources {
+			sources.push(("<source>".to_string(), code.clone()));
+		}
+		let target = Target::current()?;
+		let mut header = CppHeader::default();
+		let mut structs = Vec::new();
+		for (name, code) in &sources {
+			let file = syn::parse_file(code).map_err(|e| format!("{}: {}", name, e))?;
+			collect_items(&file.items, target, &mut header, &mut structs).map_err(|e| format!("{}: {}", name, e))?;
+		}
+		let known = known_layouts(&structs, target);
+		for (st, _) in structs.iter().filter(|x| x.1) {
+			add_layout_check(st, &mut header, &known, target)?;
+		}
+		Ok(header.render(guard))
+	}
+
+	/// Generate the header and write it to `path` if it changed, the macro prefix comes
+	/// from the file name. Returns whether the file was written.
+	pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<bool, String> {
+		let path = path.as_ref();
+		for file in &self.files {
+			println!("cargo:rerun-if-changed={}", file.display());
+		}
+		let code = self.generate(&header_guard(path))?;
+		if fs::read_to_string(path).map(|x| x == code).unwrap_or(false) {
+			return Ok(false);
+		}
+		fs::write(path, code).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+		Ok(true)
+	}
+}
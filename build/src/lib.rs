@@ -0,0 +1,381 @@
+//! Generate C++ structs keeping the layout of the `#[repr(C)]` structs in Rust sources.
+//!
+//! Call it from `build.rs`:
+//! ```no_run
+//! let out_dir = std::env::var("OUT_DIR").unwrap();
+//! directcpp_build::Rust2H::new()
+//!     .file("src/types.rs")
+//!     .write_to(format!("{out_dir}/types.h"))
+//!     .unwrap();
+//! ```
+//!
+//! [`Bridge2H`] generates the C++ declarations expected by the `#[bridge]` blocks the same way.
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{Attribute, Expr, Fields, GenericArgument, Item, Lit, PathArguments, Type};
+
+mod tests;
+mod bridge;
+pub use bridge::Bridge2H;
+
+/// Pass the target to `#[bridge]` and `#[layout_check]`: cargo gives `CARGO_CFG_TARGET_*`
+/// to build scripts only, and a proc macro runs on the host. Call it from `build.rs` when
+/// cross compiling, without it the macros build for the host. `DIRECTCPP_STDLIB` is
+/// passed on too.
+pub fn forward_target() {
+	println!("cargo:rerun-if-env-changed=DIRECTCPP_STDLIB");
+	for name in ["CARGO_CFG_TARGET_OS", "CARGO_CFG_TARGET_ARCH", "CARGO_CFG_TARGET_POINTER_WIDTH", "DIRECTCPP_STDLIB"] {
+		if let Ok(val) = std::env::var(name) {
+			println!("cargo:rustc-env={}={}", name, val);
+		}
+	}
+}
+
+/// Collects Rust sources and translates their `#[repr(C)]` structs and enums.
+#[derive(Default)]
+pub struct Rust2H {
+	files: Vec<PathBuf>,
+	sources: Vec<String>,
+}
+
+struct CField {
+	name: String,
+	tp: String,      // C++ type, with `{}` where the name goes, e.g. `int {}[4]`.
+	deps: Vec<String>,
+	docs: Vec<String>,
+}
+
+type Variant = (String, Option<String>);  // (NAME, discriminant)
+
+struct CItem {
+	name: String,
+	docs: Vec<String>,
+	fields: Vec<CField>,
+	// for enums: repr type of the tag and the variants.
+	tag: Option<(String, Vec<Variant>)>,
+}
+
+fn docs_of(attrs: &[Attribute]) -> Vec<String> {
+	attrs.iter().filter_map(|attr| match &attr.meta {
+		syn::Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+			Expr::Lit(syn::ExprLit{lit: Lit::Str(s), ..}) => Some(s.value().trim().to_string()),
+			_ => None,
+		},
+		_ => None,
+	}).collect()
+}
+
+/// The idents inside `#[repr(...)]`.
+fn repr_of(attrs: &[Attribute]) -> Vec<String> {
+	let mut out = Vec::new();
+	for attr in attrs.iter().filter(|x| x.path().is_ident("repr")) {
+		let _ = attr.parse_nested_meta(|meta| {
+			if let Some(id) = meta.path.get_ident() {
+				out.push(id.to_string());
+			}
+			Ok(())
+		});
+	}
+	out
+}
+
+fn first_type_arg(args: &PathArguments) -> Option<&Type> {
+	match args {
+		PathArguments::AngleBracketed(a) => a.args.iter().find_map(|g| match g {
+			GenericArgument::Type(t) => Some(t),
+			_ => None,
+		}),
+		_ => None,
+	}
+}
+
+fn map_primitive(name: &str) -> Option<&'static str> {
+	Some(match name {
+		"i8"|"c_schar" => "int8_t",
+		"i16"|"c_short" => "int16_t",
+		"i32"|"c_int" => "int",
+		"i64"|"c_longlong" => "int64_t",
+		"u8"|"c_uchar" => "uint8_t",
+		"u16"|"c_ushort" => "uint16_t",
+		"u32"|"c_uint" => "uint32_t",
+		"u64"|"c_ulonglong" => "uint64_t",
+		"c_char" => "char",
+		"c_long" => "long",
+		"c_ulong" => "unsigned long",
+		"usize" => "size_t",
+		"isize" => "ptrdiff_t",
+		"f32"|"c_float" => "float",
+		"f64"|"c_double" => "double",
+		"bool" => "bool",
+		"char" => "char32_t",
+		"c_void" => "void",
+		_ => return None,
+	})
+}
+
+fn pointer_to(elem: &Type, is_const: bool) -> Result<String, String> {
+	let inner = cpp_type(elem, &mut Vec::new())?;
+	let base = match inner.strip_suffix(" {}") {
+		Some(x) => x,
+		None => return Err(format!("unsupported pointer type `*{}`", quote::quote!(#elem).to_string().replace(' ', ""))),
+	};
+	Ok(match (is_const, base.ends_with('*')) {
+		(false, _) => format!("{}* {{}}", base),
+		(true, false) => format!("const {}* {{}}", base),
+		(true, true) => format!("{} const* {{}}", base),
+	})
+}
+
+/// `Option<T>`: a nullable pointer when `T` is a non-null pointer, `RustOption<T>` when `T` has
+/// no niche (a separate flag, as rustc lays it out) and the `RustString` specialisation. Other
+/// niche types (`bool`, `Vec`, structs...) aren't representable.
+fn option_type(inner: &Type, deps: &mut Vec<String>) -> Option<Result<String, String>> {
+	let seg = match inner {
+		Type::Reference(r) => return Some(pointer_to(&r.elem, r.mutability.is_none())),
+		Type::Ptr(_) => return Some(cpp_type(inner, deps).map(|x| format!("RustOption<{}> {{}}", x.trim_end_matches(" {}")))),
+		Type::Path(p) => p.path.segments.last()?,
+		_ => return None,
+	};
+	let name = seg.ident.to_string();
+	match name.as_str() {
+		"Box" | "NonNull" => Some(pointer_to(first_type_arg(&seg.arguments)?, false)),
+		"String" => Some(Ok("RustOption<RustString> {}".to_string())),
+		"bool" | "char" | "c_void" => None,
+		"CPtr" => Some(cpp_type(inner, deps).map(|x| format!("RustOption<{}> {{}}", x.trim_end_matches(" {}")))),
+		_ => map_primitive(&name).map(|x| Ok(format!("RustOption<{}> {{}}", x))),
+	}
+}
+
+/// Translate a field type into a C++ declarator pattern, `{}` being the field name.
+fn cpp_type(ty: &Type, deps: &mut Vec<String>) -> Result<String, String> {
+	let tp_str = || quote::quote!(#ty).to_string().replace(' ', "");
+	match ty {
+		Type::Array(a) => {
+			let cnt = match &a.len {
+				Expr::Lit(syn::ExprLit{lit: Lit::Int(n), ..}) => n.base10_digits().to_string(),
+				Expr::Path(p) => quote::quote!(#p).to_string().replace(' ', ""),
+				_ => return Err(format!("unsupported array length in `{}`", tp_str())),
+			};
+			let inner = cpp_type(&a.elem, deps)?;
+			Ok(inner.replacen("{}", &format!("{{}}[{}]", cnt), 1))
+		},
+		Type::Ptr(p) => pointer_to(&p.elem, p.mutability.is_none()),
+		// a reference in a #[repr(C)] struct is a non-null pointer.
+		Type::Reference(r) => pointer_to(&r.elem, r.mutability.is_none()),
+		Type::Path(p) => {
+			let seg = p.path.segments.last().ok_or_else(|| "empty type path".to_string())?;
+			let name = seg.ident.to_string();
+			if let Some(x) = map_primitive(&name) {
+				return Ok(format!("{} {{}}", x));
+			}
+			let templ = match name.as_str() {
+				"String" => return Ok("RustString {}".to_string()),
+				"Vec" => "RustVec",
+				"Option" => {
+					let inner = first_type_arg(&seg.arguments).ok_or_else(|| format!("`{}` needs a type argument", tp_str()))?;
+					return option_type(inner, deps).ok_or_else(|| format!("`{}` has no C++ counterpart, RustOption only holds String and types without a niche", tp_str()))?;
+				},
+				"CPtr" => {
+					let inner = first_type_arg(&seg.arguments).ok_or_else(|| format!("`{}` needs a type argument", tp_str()))?;
+					return pointer_to(inner, false);
+				},
+				_ if matches!(seg.arguments, PathArguments::None) => {
+					deps.push(name.clone());
+					return Ok(format!("{} {{}}", name));
+				},
+				_ => return Err(format!("unsupported type `{}`", tp_str())),
+			};
+			let inner = first_type_arg(&seg.arguments).ok_or_else(|| format!("`{}` needs a type argument", tp_str()))?;
+			let inner = cpp_type(inner, deps)?;
+			if !inner.ends_with(" {}") {
+				return Err(format!("unsupported type `{}`", tp_str()));
+			}
+			Ok(format!("{}<{}> {{}}", templ, inner.trim_end_matches(" {}")))
+		},
+		_ => Err(format!("unsupported type `{}`", tp_str())),
+	}
+}
+
+/// `MyVariant` → `MY_VARIANT`, the way enum tags were named by rust2h.py.
+fn upper_snake(name: &str) -> String {
+	let mut out = String::new();
+	for (i, ch) in name.chars().enumerate() {
+		if ch.is_ascii_uppercase() && i > 0 {
+			out.push('_');
+		}
+		out.push(ch.to_ascii_uppercase());
+	}
+	out
+}
+
+fn collect_items(items: &[Item], out: &mut Vec<CItem>) -> Result<(), String> {
+	for item in items {
+		match item {
+			Item::Struct(st) => {
+				if !repr_of(&st.attrs).iter().any(|x| x == "C") {
+					continue;
+				}
+				let name = st.ident.to_string();
+				if !st.generics.params.is_empty() {
+					return Err(format!("struct {}: generic structs can't be translated", name));
+				}
+				let mut fields = Vec::new();
+				for (i, field) in st.fields.iter().enumerate() {
+					let fname = field.ident.as_ref().map(|x| x.to_string()).unwrap_or_else(|| format!("_{}", i));
+					let mut deps = Vec::new();
+					let tp = cpp_type(&field.ty, &mut deps).map_err(|e| format!("struct {} field {}: {}", name, fname, e))?;
+					fields.push(CField{name: fname, tp, deps, docs: docs_of(&field.attrs)});
+				}
+				out.push(CItem{name, docs: docs_of(&st.attrs), fields, tag: None});
+			},
+			Item::Enum(en) => {
+				let repr = repr_of(&en.attrs);
+				let tag_tp = match repr.iter().find_map(|x| map_primitive(x)) {
+					Some(x) => x.to_string(),
+					None if repr.iter().any(|x| x == "C") => "int".to_string(),
+					None => continue,
+				};
+				let name = en.ident.to_string();
+				let mut variants = Vec::new();
+				let mut fields = Vec::new();
+				for var in &en.variants {
+					let disc = var.discriminant.as_ref().map(|(_, e)| quote::quote!(#e).to_string().replace(' ', ""));
+					let tag = upper_snake(&var.ident.to_string());
+					match &var.fields {
+						Fields::Unit => {},
+						// only repr(C) puts the payload in a union after the tag.
+						Fields::Unnamed(_) if !repr.iter().any(|x| x == "C") => {
+							return Err(format!("enum {} variant {}: an enum with data must be #[repr(C)]", name, var.ident));
+						},
+						Fields::Unnamed(f) if f.unnamed.len() == 1 => {
+							let mut deps = Vec::new();
+							let tp = cpp_type(&f.unnamed[0].ty, &mut deps).map_err(|e| format!("enum {} variant {}: {}", name, var.ident, e))?;
+							fields.push(CField{name: tag.to_lowercase(), tp, deps, docs: docs_of(&var.attrs)});
+						},
+						_ => return Err(format!("enum {} variant {}: only variants with at most one unnamed field are supported", name, var.ident)),
+					}
+					variants.push((tag, disc));
+				}
+				out.push(CItem{name, docs: docs_of(&en.attrs), fields, tag: Some((tag_tp, variants))});
+			},
+			Item::Mod(m) => {
+				if let Some((_, items)) = &m.content {
+					collect_items(items, out)?;
+				}
+			},
+			_ => {},
+		}
+	}
+	Ok(())
+}
+
+/// Put every item after the items it holds by value, keeping the source order otherwise.
+fn sort_items(items: Vec<CItem>) -> Vec<CItem> {
+	let mut rest = items;
+	let mut out: Vec<CItem> = Vec::new();
+	while !rest.is_empty() {
+		let ready = rest.iter().position(|it| {
+			it.fields.iter().flat_map(|f| f.deps.iter())
+				.all(|d| *d == it.name || !rest.iter().any(|x| x.name == *d))
+		}).unwrap_or(0);
+		out.push(rest.remove(ready));
+	}
+	out
+}
+
+fn push_docs(outs: &mut String, docs: &[String], indent: &str) {
+	for line in docs {
+		*outs += &format!("{indent}// {line}\n").replace("//  ", "// ");
+	}
+}
+
+fn render_item(it: &CItem) -> String {
+	let mut outs = String::new();
+	push_docs(&mut outs, &it.docs, "");
+	outs += &format!("struct {} {{\n", it.name);
+	let indent = match &it.tag {
+		Some((tag_tp, variants)) => {
+			outs += &format!("\tenum type_t : {} {{\n", tag_tp);
+			for (tag, disc) in variants {
+				match disc {
+					Some(d) => outs += &format!("\t\t{} = {},\n", tag, d),
+					None => outs += &format!("\t\t{},\n", tag),
+				}
+			}
+			outs += "\t};\n\ttype_t type;\n";
+			if it.fields.is_empty() {
+				outs += "};\n";
+				return outs;
+			}
+			outs += "\tunion {\n";
+			"\t\t"
+		},
+		None => "\t",
+	};
+	for field in &it.fields {
+		push_docs(&mut outs, &field.docs, indent);
+		outs += &format!("{}{};\n", indent, field.tp.replacen("{}", &field.name, 1));
+	}
+	if it.tag.is_some() {
+		outs += "\t};\n";
+	}
+	outs += "};\n";
+	outs
+}
+
+impl Rust2H {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add a Rust source file.
+	pub fn file<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+		self.files.push(path.as_ref().to_path_buf());
+		self
+	}
+
+	/// Add Rust source code directly.
+	pub fn source(&mut self, code: &str) -> &mut Self {
+		self.sources.push(code.to_string());
+		self
+	}
+
+	/// Translate all sources into the content of a C++ header.
+	pub fn generate(&self) -> Result<String, String> {
+		let mut items = Vec::new();
+		let mut sources = Vec::new();
+		for path in &self.files {
+			let code = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+			sources.push((path.display().to_string(), code));
+		}
+		for code in &self.sources {
+			sources.push(("<source>".to_string(), code.clone()));
+		}
+		for (name, code) in &sources {
+			let file = syn::parse_file(code).map_err(|e| format!("{}: {}", name, e))?;
+			collect_items(&file.items, &mut items).map_err(|e| format!("{}: {}", name, e))?;
+		}
+		let body: Vec<String> = sort_items(items).iter().map(render_item).collect();
+		let include = if body.iter().any(|x| x.contains("RustOption<")) { "rust/rust-common.h" } else { "rust/rust-spt.h" };
+		Ok(format!("#pragma once\n#include \"{}\"\n\n{}", include, body.join("\n")))
+	}
+
+	/// Write the header to `path` if its content changed, returns whether it was written.
+	/// The source files are reported to cargo with `rerun-if-changed`.
+	pub fn write_to<P: AsRef<Path>>(&self, path: P) -> Result<bool, String> {
+		let path = path.as_ref();
+		for file in &self.files {
+			println!("cargo:rerun-if-changed={}", file.display());
+		}
+		let code = self.generate()?;
+		if let Ok(old) = fs::read_to_string(path) {
+			if old.trim_start_matches('\u{feff}') == code {
+				return Ok(false);
+			}
+		}
+		// msvc reads a file without BOM in the local code page.
+		let code = if code.is_ascii() { code } else { format!("\u{feff}{}", code) };
+		fs::write(path, code).map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+		Ok(true)
+	}
+}
@@ -0,0 +1,207 @@
+#![cfg(test)]
+
+use crate::{Bridge2H, Rust2H};
+
+fn gen(code: &str) -> String {
+	Rust2H::new().source(code).generate().unwrap()
+}
+
+#[test]
+fn test_struct() {
+	let code = r#"
+		/// input of the magic.
+		#[repr(C)]
+		pub struct MagicIn {
+			pub ivalue: i32,
+			fvalue: f32,
+			/// the message
+			svalue: String,
+		}
+		struct NotShared { a: Vec<String> }
+	"#;
+	assert_eq!(gen(code), r#"#pragma once
+#include "rust/rust-spt.h"
+
+// input of the magic.
+struct MagicIn {
+	int ivalue;
+	float fvalue;
+	// the message
+	RustString svalue;
+};
+"#);
+}
+
+#[test]
+fn test_types() {
+	let code = r#"
+		#[repr(C)]
+		struct Outer {
+			inner: Inner,
+			list: Vec<Inner>,
+			names: Vec<String>,
+			title: Option<String>,
+			grid: [[u8; 4]; 2],
+			items: [Inner; 3],
+			raw: *const u8,
+			user: *mut c_void,
+			obj: CPtr<Proof>,
+		}
+		#[repr(C)]
+		struct Inner { id: u64, score: f64 }
+	"#;
+	assert_eq!(gen(code), r#"#pragma once
+#include "rust/rust-common.h"
+
+struct Inner {
+	uint64_t id;
+	double score;
+};
+
+struct Outer {
+	Inner inner;
+	RustVec<Inner> list;
+	RustVec<RustString> names;
+	RustOption<RustString> title;
+	uint8_t grid[2][4];
+	Inner items[3];
+	const uint8_t* raw;
+	void* user;
+	Proof* obj;
+};
+"#);
+}
+
+#[test]
+fn test_enum() {
+	let code = r#"
+		#[repr(C)]
+		enum Event {
+			Started,
+			KeyDown(u32),
+			TextInput(String),
+		}
+		#[repr(u8)]
+		enum Level { Low = 1, High = 8 }
+	"#;
+	assert_eq!(gen(code), r#"#pragma once
+#include "rust/rust-spt.h"
+
+struct Event {
+	enum type_t : int {
+		STARTED,
+		KEY_DOWN,
+		TEXT_INPUT,
+	};
+	type_t type;
+	union {
+		uint32_t key_down;
+		RustString text_input;
+	};
+};
+
+struct Level {
+	enum type_t : uint8_t {
+		LOW = 1,
+		HIGH = 8,
+	};
+	type_t type;
+};
+"#);
+}
+
+#[test]
+fn test_options() {
+	let code = r#"
+		#[repr(C)]
+		struct Opts {
+			name: Option<String>,
+			count: Option<u32>,
+			raw: Option<*mut u8>,
+			peer: Option<&'static Opts>,
+			next: Option<Box<Opts>>,
+			data: Option<NonNull<u8>>,
+		}
+	"#;
+	assert_eq!(gen(code), r#"#pragma once
+#include "rust/rust-common.h"
+
+struct Opts {
+	RustOption<RustString> name;
+	RustOption<uint32_t> count;
+	RustOption<uint8_t*> raw;
+	const Opts* peer;
+	Opts* next;
+	uint8_t* data;
+};
+"#);
+	for tp in ["Vec<u8>", "bool", "Opts"] {
+		let err = Rust2H::new().source(&format!("#[repr(C)] struct Opts {{ m: Option<{}> }}", tp)).generate().unwrap_err();
+		assert!(err.contains("has no C++ counterpart"), "{}", err);
+	}
+}
+
+#[test]
+fn test_errors() {
+	let err = Rust2H::new().source("#[repr(C)] struct A { m: HashMap<i32, i32> }").generate().unwrap_err();
+	assert!(err.contains("struct A field m"), "{}", err);
+	let err = Rust2H::new().source("#[repr(u8)] enum B { X(i32) }").generate().unwrap_err();
+	assert!(err.contains("must be #[repr(C)]"), "{}", err);
+}
+
+#[test]
+fn test_bridge() {
+	let code = r#"
+		mod inner {
+			#[directcpp::bridge]
+			extern "C++" {
+				pub fn magic(v: i32) -> f64;
+			}
+		}
+		#[bridge(goon)]
+		extern "C++" {
+			#[namespace(myns)]
+			pub fn message() -> String;
+		}
+		extern "C" {
+			fn not_bridged();
+		}
+		#[layout_check]
+		#[repr(C)]
+		struct Line { tag: u8, ends: [Point; 2] }
+		#[layout_check]
+		#[repr(C)]
+		struct Point { x: i32, y: f64 }
+	"#;
+	assert_eq!(Bridge2H::new().source(code).generate("PROVE").unwrap(), r#"// Generated by directcpp #[bridge], do not edit.
+#pragma once
+#include "rust/rust-spt.h"
+#include <cstddef>
+
+double magic(int v);
+
+namespace myns {
+	RustString message();
+}
+
+#define PROVE_ENABLE() do { \
+	ffi::force_ref<double (*)(int)>(&magic); \
+	ffi::force_ref<RustString (*)()>(&myns::message); \
+	ffi::enable_class<RustString>(); \
+} while (0)
+
+#define PROVE_LAYOUT_CHECK() \
+	static_assert(sizeof(Line) == 40, "size of Line differs from rust"); \
+	static_assert(alignof(Line) == 8, "align of Line differs from rust"); \
+	static_assert(offsetof(Line, tag) == 0, "offset of Line.tag differs from rust"); \
+	static_assert(offsetof(Line, ends) == 8, "offset of Line.ends differs from rust"); \
+	static_assert(sizeof(Point) == 16, "size of Point differs from rust"); \
+	static_assert(alignof(Point) == 8, "align of Point differs from rust"); \
+	static_assert(offsetof(Point, x) == 0, "offset of Point.x differs from rust"); \
+	static_assert(offsetof(Point, y) == 8, "offset of Point.y differs from rust")
+"#);
+	let err = Bridge2H::new().source("#[bridge] extern \"C++\" { fn f(m: HashMap<i32, i32>); }").generate("X").unwrap_err();
+	assert!(err.contains("<source>"), "{}", err);
+	let err = Bridge2H::new().source("#[layout_check] #[repr(C)] struct A { b: B }").generate("X").unwrap_err();
+	assert!(err.contains("struct A"), "{}", err);
+}
@@ -0,0 +1,813 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use proc_macro2::TokenStream;
+use crate::header::CppHeader;
+use crate::mangle::{class_dtor_name, dtor_name, mangle, set_class_hint, sp_dtor_name, ClassHint, SimpArg, SimpFunc};
+use crate::parse::{map_to_cxx, Functions};
+use crate::target::Target;
+use crate::util::{env_as_bool, move_obj, select_val};
+
+const TYPE_POD:i32 = 0;
+const TYPE_DTOR_TRIVIAL_MOVE:i32 = 1;  // 假定所有类型默认都是trivial move, non-trivial dtor
+const CPP_CLASS_DONE:i32 = 8;  // flag of a class with a CppClass impl from #[constructor]
+const BY_VALUE_CHECKED:i32 = 16;  // flag of a type with ffi::by_value<T>() in the header
+const CXX_ENABLED:i32 = 32;  // flag of a std type with its ffi::enable_cxx_*() in the header
+const BY_VALUE_RET_CHECKED:i32 = 64;  // flag of a type with ffi::by_value_ret<T>() in the header
+
+lazy_static::lazy_static! {
+	static ref TYPE_STRATEGY: Mutex<HashMap<String, i32>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Default)]
+#[allow(dead_code)]
+pub struct FFIBuilder{
+    is_cpp: bool,
+    extc_code: String,
+    norm_code: String,
+    err_str: String,
+    cpp_header: CppHeader,
+    methods: Vec<(String, String, String, String)>,  // (class, access, signature, call)
+    statics: Vec<(String, String, String, String)>,  // the same for associated functions
+    operators: Vec<(String, String, String, Vec<String>, String)>,  // (class, operator, fn_name, args, return type)
+    target: Target,
+}
+
+impl FFIBuilder {
+    pub fn new(reset:bool) -> Self {
+        if reset {
+            TYPE_STRATEGY.lock().unwrap().clear();
+        }
+        Self::default()
+    }
+
+    /// The expected C++ declarations of the block, for `#[bridge(header)]` and directcpp-build.
+    pub fn cpp_header(&self) -> &CppHeader {
+        &self.cpp_header
+    }
+
+    /// Build for `target` instead of the host.
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+    }
+
+    #[cfg(test)]
+    pub fn header_code(&self) -> String {
+        self.cpp_header.render("DIRECTCPP_BRIDGE")
+    }
+
+    fn dtor_code(tp: &str, target: Target) -> Result<String, String> {
+        let tp = map_to_cxx(tp);
+        let dtor_name = dtor_name(tp, target)?;
+        let tp1 = tp.replace("<", "_").replace(">", "_");
+        Ok(format!("\t#[link_name = \"{dtor_name}\"]\n\tfn ffi__free_{tp1}(__o: *mut usize);\n"))
+    }
+    fn sp_dtor_code(tp: &str, target: Target) -> Result<String, String> {
+        let dtor_name = sp_dtor_name(tp, target)?;
+        Ok(format!("\t#[link_name = \"{dtor_name}\"]\n\tfn ffi__freeSP_{tp}(__o: *mut usize);\n"))
+    }
+
+    fn show_dtor(self: &mut Self, tp: &str, rtwrap:&str, tp_cpp: &str)->Result<(), &str> {
+        if tp == "CxxString" {
+            // `ManDtor` is implemented by the runtime crate, see enable_cxx.
+            return Ok(());
+        }
+        let tp_strategy = match rtwrap {
+            "POD" => TYPE_POD,
+            // type can be dtor, and can be trivial move. trivial move is required for Rust.
+            "SharedPtr"|"UniquePtr"|"Vec"|"" => TYPE_DTOR_TRIVIAL_MOVE,
+            _ => {
+                self.err_str = format!("type {} not supported", rtwrap);
+                return Err(&self.err_str);
+            }
+        };
+        let mut mp = TYPE_STRATEGY.lock().unwrap();
+        let mut tp1 = if let Some(x) = mp.get(tp_cpp) {
+            if *x >> 16 != tp_strategy {
+                self.err_str = format!("type {tp} strategy conflict");
+                return Err(&self.err_str);
+            }
+            *x
+        } else {
+            tp_strategy << 16
+        };
+
+        if tp_strategy != TYPE_POD {
+            if (tp1 & 1) == 0 && rtwrap != "SharedPtr" {
+                tp1 |= 1;
+                match Self::dtor_code(tp_cpp, self.target) {
+                    Ok(code) => {
+                        self.extc_code += &code;
+                        self.cpp_header.add_check(&format!("ffi::enable_class<{}>();", map_to_cxx(tp_cpp)));
+                    },
+                    Err(e) => {
+                        self.err_str = e;
+                        return Err(&self.err_str);
+                    }
+                }
+            }
+            if rtwrap == "UniquePtr" && tp1 & 2 == 0 {
+                tp1 |= 2;
+                self.norm_code += &format!("
+impl ManDtor for {tp} {{
+	unsafe fn __dtor(ptr: *mut [u8;0]) {{
+		if ptr as usize != 0 {{
+			ffi__free_{tp}(ptr as *mut usize);
+		}}
+	}}
+}}");
+            }
+            if rtwrap == "SharedPtr" && tp1 & 4 == 0 {
+                tp1 |= 4;
+                match Self::sp_dtor_code(tp, self.target) {
+                    Ok(code) => {
+                        self.extc_code += &code;
+                        self.cpp_header.add_check(&format!("ffi::enable_class_sp<{}>();", map_to_cxx(tp)));
+                    },
+                    Err(e) => {
+                        self.err_str = e;
+                        return Err(&self.err_str);
+                    }
+                }
+                self.norm_code += &format!("
+impl DropSP for {tp} {{
+	unsafe fn __drop_sp(ptr: *mut [u8;0]) {{
+		if ptr as usize != 0 {{
+			ffi__freeSP_{tp}(ptr as *mut usize);
+		}}
+	}}
+}}\n");
+            }
+        }
+        mp.insert(tp_cpp.to_string(), tp1);
+        Ok(())
+    }
+
+    /// `CxxString`, `CxxStringView` and `CxxVector<T>` are implemented by the runtime crate
+    /// through the `extern "C"` helpers of `rust-spt.h`, the C++ side must emit them.
+    fn enable_cxx(&mut self, arg: &SimpArg) {
+        let (key, check) = match (arg.tp.as_str(), arg.tp_wrap.as_str()) {
+            ("CxxString"|"CxxStringView", _) => ("std::string".to_string(), "ffi::enable_cxx_string();".to_string()),
+            (_, "CxxVector") => {
+                let elem = arg.tp_cpp.split(['<', '>']).nth(1).unwrap_or_default();
+                (format!("std::vector<{elem}>"), format!("ffi::enable_cxx_vector<{elem}>();"))
+            }
+            _ => return,
+        };
+        let mut mp = TYPE_STRATEGY.lock().unwrap();
+        let flags = mp.entry(key).or_insert(0);
+        if *flags & CXX_ENABLED == 0 {
+            *flags |= CXX_ENABLED;
+            self.cpp_header.add_check(&check);
+        }
+    }
+
+    /// A `POD<T>` argument or return value is passed the way C does, C++ must agree it's
+    /// trivially copyable. A returned one must be an aggregate too, or MSVC returns it through
+    /// a hidden pointer.
+    fn check_by_value(&mut self, arg: &SimpArg, is_ret: bool) -> Result<(), &str> {
+        if self.show_dtor(&arg.tp, "POD", &arg.tp_cpp).is_err() {
+            return Err(&self.err_str);
+        }
+        let (flag, check) = select_val(is_ret, (BY_VALUE_RET_CHECKED, "by_value_ret"), (BY_VALUE_CHECKED, "by_value"));
+        let mut mp = TYPE_STRATEGY.lock().unwrap();
+        if let Some(x) = mp.get_mut(&arg.tp_cpp) {
+            if *x & flag == 0 {
+                *x |= flag;
+                self.cpp_header.add_check(&format!("ffi::{check}<{}>();", map_to_cxx(&arg.tp_cpp)));
+            }
+        }
+        Ok(())
+    }
+
+    fn get_link_name(self: &Self, func: &SimpFunc, is_cpp: bool)
+                     -> Result<String, String>
+    {
+        if ! is_cpp {
+            Ok(func.fn_name.to_string())
+        } else {
+            mangle(func, self.target)
+        }
+    }
+
+    /// The rust name of the class of a member function, without its namespace.
+    fn class_ident(func: &SimpFunc) -> String {
+        func.klsname.rsplit("::").next().unwrap_or_default().to_string()
+    }
+
+    /// The return type a C++ function constructing a `tp` in place is declared with. C returns
+    /// a struct bigger than two words through a hidden pointer, the same register or stack slot
+    /// C++ uses for a class with a destructor.
+    fn sret_struct(fn_name: &str, tp: &str) -> String {
+        format!("#[repr(C)]\n#[allow(non_camel_case_types)]\n\
+            struct __Sret_{fn_name}([usize; (std::mem::size_of::<{tp}>() + 16) / 8 + 1]);\n")
+    }
+
+    /// `char` is passed as the `char32_t` it is, but rustc warns it has no C equivalent.
+    fn allow_lints(func: &SimpFunc) -> &'static str {
+        let uses_char = func.ret.tp == "char" || func.arg_list.iter().any(|x| x.tp == "char");
+        select_val(uses_char, "#[allow(improper_ctypes)]\n\t", "")
+    }
+
+    fn arg_names(args_r: &[String]) -> Vec<&str> {
+        args_r.iter()
+            .map(|x| x.split(':').next().unwrap_or_default().trim().trim_start_matches("mut "))
+            .collect()
+    }
+
+    /// Also make `fn_name` a method of the `{Class}Methods` trait on `CPtr<Class>`.
+    /// A returned reference is a pointer for `fn_name`, it borrows `self` for the method.
+    /// The receiver is always `&self`: a `CPtr` is only an address, the constness of the
+    /// member function is in its mangled name, and smart pointers only deref to `&CPtr`.
+    fn add_method(&mut self, func: &SimpFunc, fn_name: &str, args_r: &[String], ret: &str) {
+        let mut attr = "";
+        // args_r[0] is `this__`.
+        let names = Self::arg_names(&args_r[1..]);
+        let mut call = format!("{}(self.clone(){})", fn_name,
+                           names.iter().map(|x| format!(", {x}")).collect::<String>());
+        let mut ret = ret.to_string();
+        if func.ret.tp_full.starts_with('&') {
+            ret = format!(" -> {}", &func.ret.tp_full);
+            call = format!("unsafe {{ {}*{call} }}", select_val(func.ret.is_const, "&", "&mut "));
+            if !func.ret.is_const {
+                attr = "#[allow(clippy::mut_from_ref)] ";
+            }
+        }
+        let sig = format!("{attr}fn {}({}){}", &func.rust_name,
+                          [vec!["&self".to_string()], args_r[1..].to_vec()].concat().join(", "), ret);
+        self.methods.push((Self::class_ident(func), func.access.clone(), sig, call));
+        if !func.operator.is_empty() {
+            let args = args_r[1..].iter().map(|x| x.split(':').nth(1).unwrap_or_default().trim().to_string()).collect();
+            self.operators.push((Self::class_ident(func), func.operator.clone(), func.rust_name.clone(), args, func.ret.tp_full.clone()));
+        }
+    }
+
+    /// The operators of `kls` for the std traits directcpp implements on `CPtr<kls>` and `CppBox<kls>`:
+    /// `CppEq` from `==`, `CppOrd` from `<`, `CppIndex` from a `[]` returning `&T`.
+    fn operator_traits(&self, kls: &str) -> String {
+        let find = |op: &str| self.operators.iter().find(|x| x.0 == kls && x.1 == op);
+        let is_cmp = |x: &&(String, String, String, Vec<String>, String)| {
+            x.3.len() == 1 && x.3[0] == format!("&{kls}") && x.4 == "bool"
+        };
+        let mut outs = String::new();
+        let other = format!("unsafe {{ &*(b.addr as *const {kls}) }}");
+        let eq = find("==").filter(is_cmp);
+        if let Some(eq) = eq {
+            outs += &format!("impl CppEq for {kls} {{\n\tfn __eq(a: &CPtr<Self>, b: &CPtr<Self>) -> bool {{ {kls}Methods::{}(a, {other}) }}\n}}\n", eq.2);
+        }
+        if let (Some(_), Some(lt)) = (eq, find("<").filter(is_cmp)) {
+            outs += &format!("impl CppOrd for {kls} {{\n\tfn __lt(a: &CPtr<Self>, b: &CPtr<Self>) -> bool {{ {kls}Methods::{}(a, {other}) }}\n}}\n", lt.2);
+        }
+        let is_index = |x: &&(String, String, String, Vec<String>, String)| {
+            x.3.len() == 1 && !x.3[0].starts_with('&') && x.4.starts_with('&') && !x.4.starts_with("&mut")
+        };
+        if let Some(ix) = find("[]").filter(is_index) {
+            let (idx, output) = (&ix.3[0], ix.4.trim_start_matches('&'));
+            outs += &format!("impl CppIndex<{idx}> for {kls} {{\n\ttype Output = {output};\n\
+                \tfn __index(a: &CPtr<Self>, idx: {idx}) -> &{output} {{ {kls}Methods::{}(a, idx) }}\n}}\n", ix.2);
+        }
+        outs
+    }
+
+    /// Also make `fn_name` of a `#[static_of]` function an associated function of the class.
+    fn add_static(&mut self, func: &SimpFunc, fn_name: &str, args_r: &[String], ret: &str) {
+        let sig = format!("fn {}({}){}", &func.rust_name, args_r.join(", "), ret);
+        let call = format!("{}({})", fn_name, Self::arg_names(args_r).join(", "));
+        self.statics.push((Self::class_ident(func), func.access.clone(), sig, call));
+    }
+
+    fn method_traits(&mut self) -> String {
+        let mut outs = String::new();
+        let mut kls_list: Vec<(String, String)> = Vec::new();
+        for (kls, access, _, _) in &self.methods {
+            if !kls_list.iter().any(|x| &x.0 == kls) {
+                kls_list.push((kls.clone(), access.clone()));
+            }
+        }
+        for (kls, access) in &kls_list {
+            let methods = self.methods.iter().filter(|x| &x.0 == kls);
+            let decls: String = methods.clone().map(|x| format!("\t{};\n", x.2)).collect();
+            let impls: String = methods.map(|x| format!("\t{} {{ {} }}\n", x.2, x.3)).collect();
+            outs += &format!("{access} trait {kls}Methods {{\n{decls}}}\nimpl {kls}Methods for CPtr<{kls}> {{\n{impls}}}\n");
+            outs += &self.operator_traits(kls);
+        }
+        let mut kls_list: Vec<&String> = Vec::new();
+        for (kls, _, _, _) in &self.statics {
+            if !kls_list.contains(&kls) {
+                kls_list.push(kls);
+            }
+        }
+        for kls in kls_list {
+            let impls: String = self.statics.iter().filter(|x| &x.0 == kls)
+                .map(|x| format!("\t{} {} {{ {} }}\n", x.1, x.2, x.3)).collect();
+            outs += &format!("impl {kls} {{\n{impls}}}\n");
+        }
+        outs
+    }
+
+    fn build_one_func(self:&mut Self, func: &SimpFunc, is_cpp: bool) -> Result<(), &str>{
+        let mut args_c = Vec::new();
+        let mut args_r = Vec::new();
+        let mut args_usage = Vec::new();
+        let mut fn_name = func.rust_name.clone();
+        if !func.klsname.is_empty() {
+            let kls = Self::class_ident(func);
+            if func.is_ctor {
+                args_c.push("this__: *mut u8".to_string());
+                args_usage.push("__rtp".to_string());
+            } else if !func.is_static {
+                args_c.push("this__: *const u8".to_string());
+                args_r.push(format!("this__: CPtr<{kls}>"));
+                args_usage.push("this__.addr as *const u8".to_string());
+            }
+            fn_name = format!("{kls}__{}", &func.rust_name);
+        }
+        if func.is_result && (func.is_async || !is_cpp) {
+            self.err_str = format!("function {}: Result<T, CppError> is only supported by non-async C++ functions", func.rust_name);
+            return Err(&self.err_str);
+        }
+        // a returned reference is a pointer, see add_method.
+        let is_ref_ret = func.ret.tp_full.starts_with('&');
+        let return_code_r = if func.is_async {
+            if func.ret.tp.is_empty() {
+                return Err("async function must have a return type");
+            }
+            if ! func.ret.tp_wrap.is_empty() {
+                self.err_str = format!("currently async function should only return non-generic types. wrap your type into a struct if need. unsupported return type {}", &func.ret.raw_str);
+                return Err(&self.err_str);
+            }
+            args_c.push("addr: usize".to_string());
+            args_usage.push("dyn_fv_addr".to_string());
+            format!(" -> {}", func.ret.tp_full)
+        } else {
+            match &func.ret.tp_wrap as &str {
+                "" if func.ret.tp.is_empty() => String::new(),
+                _ if is_ref_ret => format!(" -> {}", func.ret.tp_asc),
+                "POD" => format!(" -> {}", func.ret.tp),
+                _ => format!(" -> {}", func.ret.tp_full),
+            }
+        };
+
+        enum RetKind {
+            RtPrimitive,
+            RtPod,
+            RtCPtr,
+            RtSharedPtr,
+            RtObject,
+        }
+        // MSVC passes `this` before the hidden return pointer, so it's an explicit argument
+        // there. Elsewhere it is where C returns a big struct too, see sret_struct.
+        let by_sret = !self.target.windows;
+        let mut ret_kind = RetKind::RtPrimitive;
+        let return_code_c = match &func.ret.tp_wrap as &str {
+            _ if func.is_result => String::new(),  // see build_catch_func
+            _ if is_ref_ret => format!(" -> {}", func.ret.tp_asc),
+            "CPtr" => {
+                ret_kind = RetKind::RtCPtr;
+                " -> *const u8".to_string()
+            },
+            "SharedPtr"|"UniquePtr" => {
+                ret_kind = RetKind::RtSharedPtr;
+                if by_sret {
+                    self.norm_code += &Self::sret_struct(&fn_name, &func.ret.tp_full);
+                    format!(" -> __Sret_{fn_name}")
+                } else {
+                    args_c.push("__rto: * mut u8".to_string());
+                    args_usage.push(format!("&mut __rto as *mut {} as *mut u8", &func.ret.tp_full));
+                    "".to_string()
+                }
+            },
+            "" if func.is_async => String::new(),
+            "" if func.ret.tp.is_empty() => String::new(),
+            "" if func.ret.is_primitive => format!(" -> {}", func.ret.tp),
+            "Ptr" => format!(" -> {}", func.ret.tp_full),
+            // a trivially copyable struct comes back in registers as in C, except that MSVC
+            // returns any class from a member function through a hidden pointer.
+            "POD" if !(self.target.windows && !func.klsname.is_empty() && !func.is_static) => {
+                if self.check_by_value(&func.ret, true).is_err() {
+                    return Err(&self.err_str);
+                }
+                ret_kind = RetKind::RtPod;
+                format!(" -> {}", func.ret.tp)
+            }
+            ""|"POD"|"Vec" => {
+                ret_kind = RetKind::RtObject;
+                if by_sret {
+                    let ret_type = select_val(func.ret.tp_wrap == "POD", &func.ret.tp, &func.ret.tp_full);
+                    self.norm_code += &Self::sret_struct(&fn_name, ret_type);
+                    format!(" -> __Sret_{fn_name}")
+                } else {
+                    args_c.push("__rto: * mut usize".to_string());
+                    args_usage.push("&mut __rta as *mut usize".to_string());
+                    "".to_string()
+                }
+            }
+            _ => {
+                self.err_str = format!("return type {} not supported", &func.ret.raw_str);
+                return Err(&self.err_str);
+            }
+        };
+
+        self.enable_cxx(&func.ret);
+        for arg in &func.arg_list {
+            self.enable_cxx(arg);
+            let mut args_x_done = false;
+            let is_ref = arg.tp_full.chars().next().unwrap() == '&';
+            match arg.tp_wrap.as_str() {
+                ""|"POD" if is_ref => {
+                    match arg.tp.as_str() {
+                        "CStr" => {
+                            args_x_done = true;
+                            args_c.push(format!("{}: *const i8", &arg.name));
+                            args_r.push(format!("{}: &CStr", &arg.name));
+                            args_usage.push(format!("{}.as_ptr()", &arg.name))
+                        },
+                        "str" => {
+                            args_x_done = true;
+                            args_c.push(format!("{}: *const u8, {}_len: usize", &arg.name, &arg.name));
+                            args_r.push(format!("{}: &str", &arg.name));
+                            args_usage.push(format!("{}.as_ptr()", &arg.name));
+                            args_usage.push(format!("{}.len()", &arg.name));
+                        },
+                        _ => args_usage.push(format!("{} as *{} {}", &arg.name, select_val(arg.is_const, "const", "mut"), &arg.tp)),
+                    }
+                },
+                "Slice" => {
+                    // `&[T]` is `const T*, size_t`, `&mut [T]` is `T*, size_t`.
+                    args_x_done = true;
+                    args_c.push(format!("{}: {}, {}_len: usize", &arg.name, &arg.tp_asc, &arg.name));
+                    args_r.push(format!("{}: {}", &arg.name, &arg.tp_full));
+                    args_usage.push(format!("{}.{}()", &arg.name, select_val(arg.is_const, "as_ptr", "as_mut_ptr")));
+                    args_usage.push(format!("{}.len()", &arg.name));
+                },
+                // `&[T; N]` is `const T (&)[N]`, a pointer to the first element.
+                "Array" => args_usage.push(format!("{}.{}()", &arg.name, select_val(arg.is_const, "as_ptr", "as_mut_ptr"))),
+                "POD" if !is_ref => {
+                    // `POD<T>` is declared, `T` is taken and passed in registers or on the stack.
+                    if self.check_by_value(arg, false).is_err() {
+                        return Err(&self.err_str);
+                    }
+                    args_x_done = true;
+                    args_c.push(format!("{}: {}", &arg.name, &arg.tp_asc));
+                    args_r.push(format!("{}: {}", &arg.name, &arg.tp));
+                    args_usage.push(arg.name.clone());
+                },
+                "CPtr" => args_usage.push(format!("{}.addr as * const u8", &arg.name)),
+                "CxxVector" => args_usage.push(format!("{} as {}", &arg.name, &arg.tp_asc)),
+                "RustFn" => {
+                    // C++ takes `RustFn<sig>&` and may move the closure out, what is left drops here.
+                    args_x_done = true;
+                    args_c.push(format!("{}: *mut u8", &arg.name));
+                    args_r.push(format!("mut {}: {}", &arg.name, &arg.tp_full));
+                    args_usage.push(format!("&mut {} as *mut {} as *mut u8", &arg.name, &arg.tp_full));
+                },
+                "Vec" if is_ref => {
+                    // Pass a &Vec<T> to C++ by address; the C++ side receives it as
+                    // `const RustVec<T>&` (a reference == pointer), matching the layout
+                    // used by Vec return values.
+                    args_x_done = true;
+                    let pointee = arg.tp_full
+                        .trim_start_matches('&')
+                        .trim_start_matches("mut ")
+                        .to_string();
+                    args_c.push(format!("{}: *const {}", &arg.name, &pointee));
+                    args_r.push(format!("{}: {}", &arg.name, &arg.tp_full));
+                    args_usage.push(format!("{} as *const {}", &arg.name, &pointee));
+                },
+                "Vec" => {
+                    // `RustVec<T>` by value is passed by address. The MSVC callee destroys it,
+                    // so Rust forgets it; other ABIs leave it to the caller, what is left drops here.
+                    args_x_done = true;
+                    args_c.push(format!("{}: *mut {}", &arg.name, &arg.tp_full));
+                    if self.target.windows {
+                        args_r.push(format!("{}: {}", &arg.name, &arg.tp_full));
+                        args_usage.push(format!("&mut *std::mem::ManuallyDrop::new({}) as *mut {}", &arg.name, &arg.tp_full));
+                    } else {
+                        args_r.push(format!("mut {}: {}", &arg.name, &arg.tp_full));
+                        args_usage.push(format!("&mut {} as *mut {}", &arg.name, &arg.tp_full));
+                    }
+                },
+                "" if arg.tp == "String" => {
+                    // `RustString&&`, C++ moves out what it keeps, what is left drops here.
+                    args_x_done = true;
+                    args_c.push(format!("{}: *mut String", &arg.name));
+                    args_r.push(format!("mut {}: String", &arg.name));
+                    args_usage.push(format!("&mut {} as *mut String", &arg.name));
+                },
+                "Option" => match is_ref {
+                    true => args_usage.push(format!("{}.as_ref().map_or(0 as * const {}, |x| x as * const {})", &arg.name, &arg.tp, &arg.tp)),
+                    false => args_usage.push(format!("{}.map_or(0 as * const {}, |x| x as * const {})", &arg.name, &arg.tp, &arg.tp)),
+                },
+                // this is not tested, normally you should use CPtr<xx> for arguments, don't use these.
+                "SharedPtr"|"UniquePtr" => args_usage.push(format!("{}.as_cptr().addr as * const {}", &arg.name, &arg.tp)),
+                _ if arg.is_primitive => args_usage.push(format!("{}", &arg.name)),
+                _ => {
+                    let suggested_str = arg.raw_str.replace(":", ": &");
+                    self.err_str = format!("function \"{}\" argument \"{}\" not supported, \
+					you should always use a reference for non-primitive types in interop functions.\n\
+					try use \"{}\" instead, or POD<{}> for a trivially copyable struct.", func.rust_name, &arg.raw_str, &suggested_str, &arg.tp);
+                    return Err(&self.err_str);
+                }
+            };
+            if !args_x_done {
+                args_c.push(format!("{}: {}", &arg.name, &arg.tp_asc));
+                args_r.push(format!("{}: {}", &arg.name, &arg.tp_full));
+            }
+        }
+
+        if func.is_result {
+            return self.build_catch_func(func, &fn_name, &args_r, &args_usage);
+        }
+        if func.is_ctor {
+            return self.build_ctor_func(func, &fn_name, &args_c, &args_r, &args_usage);
+        }
+
+        let link_name = if func.is_async {
+            let sa = SimpArg{
+                name: "promise".to_string(),
+                tp: "usize".to_string(),
+                tp_full: "usize".to_string(),
+                tp_wrap: "".to_string(),
+                tp_cpp: format!("ValuePromise<{}>*", func.ret.tp_cpp),
+                is_const: false,
+                is_primitive: true,
+                raw_str: "usize".to_string(),
+                tp_asc: "usize".to_string()
+            };
+            let mut func1 = func.clone();
+            func1.ret.tp = "".to_string();
+            func1.ret.tp_cpp = "".to_string();
+            func1.ret.is_primitive = true;
+            func1.arg_list.insert(0, sa);
+            self.cpp_header.add_func(&func1, is_cpp);
+            self.get_link_name(&func1, is_cpp)
+        } else {
+            self.cpp_header.add_func(func, is_cpp);
+            self.get_link_name(func, is_cpp)
+        };
+        let link_name = match link_name {
+            Ok(x) => x,
+            Err(e) => {
+                self.err_str = format!("function {}: {}", func.rust_name, e);
+                return Err(&self.err_str);
+            }
+        };
+        let fnstart = format!("{} {}fn {}({}){}", &func.access,
+                              if func.is_async { "async " } else { "" },
+                              &fn_name, args_r.join(", "), return_code_r);
+        self.extc_code += &format!("\t{}#[link_name = \"{link_name}\"]\n\tfn ffi__{fn_name}({}){};\n",
+                                   Self::allow_lints(func), args_c.join(", "), return_code_c);
+        match ret_kind {
+            RetKind::RtPrimitive|RetKind::RtCPtr => {},
+            _ => {
+                if let Err(s) = self.show_dtor(&func.ret.tp, &func.ret.tp_wrap, &func.ret.tp_cpp) {
+                    self.err_str = s.to_string();
+                    return Err(&self.err_str);
+                }
+            }
+        }
+        let usage = args_usage.join(", ");
+        let norm_code = match ret_kind {
+            RetKind::RtPrimitive if func.is_async => {
+                format!("let mut fv= FutureValue::<{}>::default();\n\
+					unsafe {{ let dyn_fv_addr = fv.to_ptr(); ffi__{fn_name}({usage}); }}\n\
+					fv.await", &func.ret.tp)
+            },
+            RetKind::RtPrimitive|RetKind::RtPod => format!("unsafe {{ ffi__{fn_name}({usage}) }}"),
+            RetKind::RtCPtr => format!("CPtr{{ addr: unsafe {{ ffi__{fn_name}({usage}) as usize }}, _phantom: std::marker::PhantomData }}"),
+            RetKind::RtSharedPtr if by_sret => {
+                format!("let __rta = unsafe {{ ffi__{fn_name}({usage}) }};\n\
+					\tunsafe {{ std::ptr::read(&__rta as *const _ as *const {}) }}", &func.ret.tp_full)
+            },
+            RetKind::RtSharedPtr => {
+                let wrap1 = &func.ret.tp_wrap as &str;
+                let ret_type = &func.ret.tp as &str;
+                format!("let mut __rto = {wrap1}::<{ret_type}>::default();\n\
+					\tunsafe {{ ffi__{fn_name}({usage}); }}\n\
+					\t__rto")
+            },
+            RetKind::RtObject => {
+                let mut ret_type = &func.ret.tp_full as &str;
+                let tp1 = func.ret.tp_cpp.replace("<", "_").replace(">", "_");
+                let call_free = match func.ret.tp_wrap.as_str() {
+                    "POD" => {
+                        ret_type = &func.ret.tp as &str;
+                        "".to_string()
+                    },  // no destructor for POD
+                    _ => format!("ffi__free_{}(&mut __rta as *mut _ as *mut usize);\n\t\t", &tp1),
+                };
+                let call = match by_sret {
+                    true => format!("let {}__rta = unsafe {{ ffi__{fn_name}({usage}) }};\n\t",
+                                    select_val(call_free.is_empty(), "", "mut ")),
+                    false => format!("const SZ:usize = (std::mem::size_of::<{ret_type}>()+16)/8;\n\
+					\tlet mut __rta : [usize;SZ] = [0;SZ];\n\
+					\tunsafe {{ ffi__{fn_name}({usage}); }}\n\t"),
+                };
+                format!("{call}unsafe {{\n\
+					\t\tlet __rto = (*(&__rta as *const _ as *const {ret_type})).clone();\n\
+					\t\t{call_free}__rto\n\
+					\t}}")
+            },
+            // _ => return Err("xx")
+        };
+        self.norm_code += &format!("#[inline(never)]\n{fnstart} {{\n\t{norm_code}\n}}\n");
+        if func.is_method {
+            self.add_method(func, &fn_name, &args_r, &return_code_r);
+        }
+        if func.is_static {
+            self.add_static(func, &fn_name, &args_r, &return_code_r);
+        }
+        Ok(())
+    }
+
+    /// The `CppClass` impl of a class given the size and align of its `CppBox` storage,
+    /// destructed by `kls::~kls()`. Only once per class.
+    fn show_cpp_class(&mut self, func: &SimpFunc, size: usize, align: usize) -> Result<(), &str> {
+        let kls = Self::class_ident(func);
+        let mut mp = TYPE_STRATEGY.lock().unwrap();
+        let tp1 = mp.get(&func.klsname).copied().unwrap_or(TYPE_DTOR_TRIVIAL_MOVE << 16);
+        if tp1 & CPP_CLASS_DONE != 0 {
+            self.err_str = format!("function {}: size and align of {} are already given by another constructor", func.rust_name, kls);
+            return Err(&self.err_str);
+        }
+        mp.insert(func.klsname.clone(), tp1 | CPP_CLASS_DONE);
+        let link_name = match class_dtor_name(&func.klsname, self.target) {
+            Ok(x) => x,
+            Err(e) => {
+                self.err_str = format!("function {}: {}", func.rust_name, e);
+                return Err(&self.err_str);
+            }
+        };
+        self.extc_code += &format!("\t#[link_name = \"{link_name}\"]\n\tfn ffi__destruct_{kls}(this__: *mut u8);\n");
+        self.norm_code += &format!("
+impl CppClass for {kls} {{
+	const SIZE: usize = {size};
+	const ALIGN: usize = {align};
+	unsafe fn __destruct(ptr: *mut [u8;0]) {{
+		ffi__destruct_{kls}(ptr as *mut u8);
+	}}
+}}\n");
+        let dfunc = SimpFunc {
+            scope: func.scope.clone(),
+            klsname: func.klsname.clone(),
+            fn_name: format!("~{kls}"),
+            is_dtor: true,
+            ..Default::default()
+        };
+        self.cpp_header.add_func(&dfunc, true);
+        let kls_cpp = &func.klsname;
+        self.cpp_header.add_check(&format!("static_assert(sizeof({kls_cpp}) <= {size} && alignof({kls_cpp}) <= {align}, \"CppBox<{kls}> storage is too small\");"));
+        Ok(())
+    }
+
+    /// A `#[constructor]` runs the complete object constructor on storage from `CppBox::__alloc`.
+    fn build_ctor_func(&mut self, func: &SimpFunc, fn_name: &str, args_c: &[String], args_r: &[String], args_usage: &[String])
+                       -> Result<(), &str>
+    {
+        let kls = Self::class_ident(func);
+        let link_name = match mangle(func, self.target) {
+            Ok(x) => x,
+            Err(e) => {
+                self.err_str = format!("function {}: {}", func.rust_name, e);
+                return Err(&self.err_str);
+            }
+        };
+        self.cpp_header.add_func(func, true);
+        if let Some((size, align)) = func.box_layout {
+            if self.show_cpp_class(func, size, align).is_err() {
+                return Err(&self.err_str);
+            }
+        }
+        let ret_r = format!(" -> CppBox<{kls}>");
+        let usage = args_usage.join(", ");
+        self.extc_code += &format!("\t{}#[link_name = \"{link_name}\"]\n\tfn ffi__{fn_name}({});\n", Self::allow_lints(func), args_c.join(", "));
+        self.norm_code += &format!("#[inline(never)]\n{} fn {fn_name}({}){ret_r} {{\n\
+            \tlet __rtp = CppBox::<{kls}>::__alloc();\n\
+            \tunsafe {{ ffi__{fn_name}({usage}); CppBox::<{kls}>::__from_raw(__rtp) }}\n}}\n",
+            &func.access, args_r.join(", "));
+        self.add_static(func, fn_name, args_r, &ret_r);
+        Ok(())
+    }
+
+    /// A `Result<T, CppError>` function calls `ffi::catch_call<ffi_catch::NAME>` from rust-catch.h
+    /// instead, which passes the arguments by address and constructs the return value in place.
+    fn build_catch_func(&mut self, func: &SimpFunc, fn_name: &str, args_r: &[String], args_usage: &[String])
+                        -> Result<(), &str>
+    {
+        let ret = &func.ret;
+        let ret_type = match ret.tp_wrap.as_str() {
+            "" if ret.tp.is_empty() => "()".to_string(),
+            "POD" => ret.tp.clone(),
+            _ => ret.tp_full.clone(),
+        };
+        // (declare the storage, pointer to it, take the value out of it)
+        let (ret_hold, ret_ptr, ret_take) = match ret.tp_wrap.as_str() {
+            "" if ret.tp.is_empty() => (String::new(), "std::ptr::null_mut()".to_string(), "()".to_string()),
+            ""|"Ptr" if ret.is_primitive => (
+                format!("let mut __rtv = std::mem::MaybeUninit::<{ret_type}>::uninit();\n\t"),
+                "__rtv.as_mut_ptr() as *mut u8".to_string(),
+                "unsafe { __rtv.assume_init() }".to_string()),
+            "CPtr" => (
+                "let mut __rtv: usize = 0;\n\t".to_string(),
+                "&mut __rtv as *mut usize as *mut u8".to_string(),
+                "CPtr{ addr: __rtv, _phantom: std::marker::PhantomData }".to_string()),
+            "SharedPtr"|"UniquePtr" => (
+                format!("let mut __rto = {}::<{}>::default();\n\t", &ret.tp_wrap, &ret.tp),
+                format!("&mut __rto as *mut {ret_type} as *mut u8"),
+                "__rto".to_string()),
+            ""|"POD"|"Vec" => {
+                let tp1 = ret.tp_cpp.replace("<", "_").replace(">", "_");
+                let call_free = match ret.tp_wrap.as_str() {
+                    "POD" => String::new(),  // no destructor for POD
+                    _ => format!("ffi__free_{tp1}(&mut __rta as *mut usize); "),
+                };
+                (format!("const SZ:usize = (std::mem::size_of::<{ret_type}>()+16)/8;\n\t\
+                    let mut __rta : [usize;SZ] = [0;SZ];\n\t"),
+                 "&mut __rta as *mut usize as *mut u8".to_string(),
+                 format!("unsafe {{ let __rto = (*(&__rta as *const usize as *const {ret_type})).clone(); {call_free}__rto }}"))
+            },
+            _ => {
+                self.err_str = format!("return type {} not supported", &ret.raw_str);
+                return Err(&self.err_str);
+            }
+        };
+        // objects and smart pointers need their destructors, the same as without Result.
+        let is_object = match ret.tp_wrap.as_str() {
+            "" => !ret.tp.is_empty() && !ret.is_primitive,
+            wrap => wrap != "CPtr" && wrap != "Ptr",
+        };
+        if is_object {
+            if let Err(s) = self.show_dtor(&ret.tp, &ret.tp_wrap, &ret.tp_cpp) {
+                self.err_str = s.to_string();
+                return Err(&self.err_str);
+            }
+        }
+
+        let tag = format!("ffi_catch::{fn_name}");
+        let _ = set_class_hint(&tag, ClassHint::StrongStruct);
+        let _ = set_class_hint("ffi::CppError", ClassHint::StrongStruct);
+        let mut cfunc = SimpFunc::default();
+        cfunc.set_scope("ffi", "");
+        cfunc.fn_name = "catch_call".to_string();
+        cfunc.template_types.push(tag);
+        cfunc.ret = SimpArg{tp: "bool".to_string(), tp_cpp: "bool".to_string(), is_primitive: true, ..SimpArg::default()};
+        for tp in ["void*", "void**", "ffi::CppError*"] {
+            cfunc.arg_list.push(SimpArg{tp_cpp: tp.to_string(), ..SimpArg::default()});
+        }
+        let link_name = match mangle(&cfunc, self.target) {
+            Ok(x) => x,
+            Err(e) => {
+                self.err_str = format!("function {}: {}", func.rust_name, e);
+                return Err(&self.err_str);
+            }
+        };
+        self.cpp_header.add_func(func, true);
+        self.cpp_header.add_catch(func, fn_name);
+
+        let fnstart = format!("{} fn {}({}) -> Result<{ret_type}, CppError>", &func.access, fn_name, args_r.join(", "));
+        self.extc_code += &format!("\t#[link_name = \"{link_name}\"]\n\tfn ffi__{fn_name}(__ret: *mut u8, __args: *const *const u8, __err: *mut CppError) -> bool;\n");
+        let mut body = String::new();
+        let mut addrs = Vec::new();
+        for (idx, usage) in args_usage.iter().enumerate() {
+            body += &format!("let __v{idx} = {usage};\n\t");
+            addrs.push(format!("&__v{idx} as *const _ as *const u8"));
+        }
+        body += &format!("let __args: [*const u8; {}] = [{}];\n\t", addrs.len(), addrs.join(", "));
+        body += &format!("let mut __err = CppError::default();\n\t{ret_hold}");
+        body += &format!("if !unsafe {{ ffi__{fn_name}({ret_ptr}, __args.as_ptr(), &mut __err) }} {{\n\t\treturn Err(__err);\n\t}}\n\t");
+        body += &format!("Ok({ret_take})");
+        self.norm_code += &format!("#[inline(never)]\n{fnstart} {{\n\t{body}\n}}\n");
+        let ret_r = format!(" -> Result<{ret_type}, CppError>");
+        if func.is_method {
+            self.add_method(func, fn_name, args_r, &ret_r);
+        }
+        if func.is_static {
+            self.add_static(func, fn_name, args_r, &ret_r);
+        }
+        Ok(())
+    }
+
+    pub fn build_bridge_code(self: &mut Self, input: TokenStream) -> Result<TokenStream, &str> {
+        let mut xxx = Functions::new(self.target);
+        if let Err(s) = xxx.parse_ts(input) {
+            self.err_str = s.to_string();
+            return Err(&self.err_str);
+        }
+
+        for func in &xxx.funcs {
+            if let Err(_) = self.build_one_func(func, xxx.is_cpp) {
+                return Err(&self.err_str);
+            }
+        }
+        let extc_code = move_obj(&mut self.extc_code);
+        let norm_code = move_obj(&mut self.norm_code) + &self.method_traits();
+        let all_code = format!("extern \"C\" {{\n{extc_code}}}\n{norm_code}\n");
+        if env_as_bool("RUST_BRIDGE_DEBUG") {
+            println!("{}", all_code);
+        }
+        TokenStream::from_str(&all_code).map_err(|e| {
+            self.err_str = e.to_string();
+            self.err_str.as_str()
+        })
+    }
+}
@@ -0,0 +1,1327 @@
+// A small demangler for the Itanium and MSVC names produced by mangle.rs.
+// It only understands what our manglers can produce, but it follows the ABI rules
+// strictly: a component that should have been a substitution / back reference is an
+// error, so mistakes of the manglers show up when the macro expands, not when linking.
+use std::fmt;
+use crate::mangle::{std_type, SimpFunc, StdLib, OPERATORS, UNARY_OPERATORS};
+use crate::target::Target;
+use crate::util::select_val;
+
+lazy_static::lazy_static! {
+	static ref TYPE_TOKEN: regex::Regex = regex::Regex::new(r"\w+|::|&&|\S").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CppType {
+	Builtin(String),
+	Named(Vec<String>, Vec<CppType>),  // qualified name, template arguments
+	Const(Box<CppType>),
+	Ptr(Box<CppType>),
+	Ref(Box<CppType>),
+	RRef(Box<CppType>),
+	Func(Box<CppType>, Vec<CppType>),  // return type, parameter types
+	Array(Box<CppType>, usize),
+	Value(Box<CppType>, i64),  // a non-type template argument
+	Param(usize),  // Itanium `T_`, the template argument of the function it refers to
+}
+
+/// `const` on an array applies to its elements.
+fn make_const(t: CppType) -> CppType {
+	match t {
+		CppType::Array(e, n) => CppType::Array(Box::new(make_const(*e)), n),
+		t => CppType::Const(Box::new(t)),
+	}
+}
+
+impl fmt::Display for CppType {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			CppType::Builtin(s) => write!(f, "{}", s),
+			CppType::Named(path, args) => {
+				write!(f, "{}", path.join("::"))?;
+				if !args.is_empty() {
+					let v = args.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+					write!(f, "<{}>", v.join(", "))?;
+				}
+				Ok(())
+			}
+			CppType::Const(t) => write!(f, "{} const", t),
+			CppType::Ptr(t) | CppType::Ref(t) => {
+				let op = select_val(matches!(self, CppType::Ptr(_)), "*", "&");
+				match &**t {
+					CppType::Array(e, n) => write!(f, "{} ({}) [{}]", e, op, n),
+					t => write!(f, "{}{}", t, op),
+				}
+			}
+			CppType::RRef(t) => write!(f, "{}&&", t),
+			CppType::Func(r, args) => {
+				let v = args.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+				write!(f, "{} ({})", r, v.join(", "))
+			}
+			CppType::Array(e, n) => write!(f, "{} [{}]", e, n),
+			CppType::Value(t, v) => match t.to_string().as_str() {
+				"bool" => write!(f, "{}", v != &0),
+				"int" => write!(f, "{}", v),
+				"unsigned int" => write!(f, "{}u", v),
+				"long" => write!(f, "{}l", v),
+				"unsigned long" => write!(f, "{}ul", v),
+				"long long" => write!(f, "{}ll", v),
+				"unsigned long long" => write!(f, "{}ull", v),
+				tp => write!(f, "({}){}", tp, v),
+			},
+			CppType::Param(idx) => write!(f, "T{}", idx),
+		}
+	}
+}
+
+/// A demangled function, printed the way c++filt does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+	pub name: CppType,
+	pub args: Vec<CppType>,
+	pub ret: Option<CppType>,  // Itanium only encodes it for templates.
+	pub is_const: bool,
+	pub is_static: bool,  // MSVC only, Itanium doesn't encode it.
+}
+
+impl fmt::Display for Signature {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let v = self.args.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+		write!(f, "{}({}){}", self.name, v.join(", "), select_val(self.is_const, " const", ""))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Abi {
+	Itanium,
+	Msvc,
+}
+
+fn void() -> CppType {
+	CppType::Builtin("void".to_string())
+}
+
+/// How the compiler spells a builtin type or a well-known typedef of it.
+fn builtin_name(tp: &str, abi: Abi, target: Target) -> Option<&'static str> {
+	let is64 = target.is64;
+	let long64 = abi == Abi::Itanium && is64 && !target.macos;
+	let msvc = abi == Abi::Msvc;
+	Some(match tp {
+		"void"|"()" => "void",
+		"bool" => "bool",
+		"char" => "char",
+		"wchar_t" => "wchar_t",
+		"char16_t" => "char16_t",
+		"char32_t" => "char32_t",
+		"char8_t" => "char8_t",
+		"i8"|"int8_t"|"signed char" => "signed char",
+		"u8"|"uint8_t"|"unsigned char" => "unsigned char",
+		"i16"|"int16_t"|"short" => "short",
+		"u16"|"uint16_t"|"unsigned short" => "unsigned short",
+		"i32"|"int32_t"|"int" => "int",
+		"u32"|"uint32_t"|"unsigned int"|"unsigned" => "unsigned int",
+		"long" => "long",
+		"unsigned long" => "unsigned long",
+		"long long" => select_val(msvc, "__int64", "long long"),
+		"unsigned long long" => select_val(msvc, "unsigned __int64", "unsigned long long"),
+		"i64"|"int64_t" => select_val(msvc, "__int64", select_val(long64, "long", "long long")),
+		"u64"|"uint64_t" => select_val(msvc, "unsigned __int64",
+		                               select_val(long64, "unsigned long", "unsigned long long")),
+		"size_t" => match (msvc, is64) {
+			(true, true) => "unsigned __int64",
+			(false, true) => "unsigned long",
+			_ => "unsigned int",
+		},
+		"f32"|"float" => "float",
+		"ptrdiff_t" => match (msvc, is64) {
+			(true, true) => "__int64",
+			(false, true) => "long",
+			_ => "int",
+		},
+		"__int128" => "__int128",
+		"unsigned __int128" => "unsigned __int128",
+		"f64"|"double" => "double",
+		"long double" => "long double",
+		"nullptr_t"|"std::nullptr_t" => select_val(msvc, "std::nullptr_t", "decltype(nullptr)"),
+		_ => return None,
+	})
+}
+
+/// Parse a C++ type as written in `SimpArg::tp_cpp`, e.g. `const RustVec<uint8_t>&`.
+struct TypeParser {
+	toks: Vec<String>,
+	pos: usize,
+	abi: Abi,
+	target: Target,
+}
+
+impl TypeParser {
+	fn new(tp: &str, abi: Abi, target: Target) -> Self {
+		let toks = TYPE_TOKEN.find_iter(tp).map(|m| m.as_str().to_string()).collect();
+		Self { toks, pos: 0, abi, target }
+	}
+	fn peek(&self) -> Option<&str> {
+		self.toks.get(self.pos).map(|x| x.as_str())
+	}
+	fn next(&mut self) -> Result<String, String> {
+		let r = self.toks.get(self.pos).cloned().ok_or("unexpected end of type".to_string());
+		self.pos += 1;
+		r
+	}
+	fn expect(&mut self, tok: &str) -> Result<(), String> {
+		let x = self.next()?;
+		if x != tok {
+			return Err(format!("expect `{}`, got `{}`", tok, x));
+		}
+		Ok(())
+	}
+	fn parse(mut self) -> Result<CppType, String> {
+		let t = self.ty()?;
+		if let Some(x) = self.peek() {
+			return Err(format!("unexpected `{}` in type", x));
+		}
+		Ok(t)
+	}
+	/// A non-type template argument, `4`, `-1`, `(size_t)4` or `true`.
+	fn value(&mut self) -> Result<Option<CppType>, String> {
+		let first = self.peek().unwrap_or_default().to_string();
+		let (t, val) = match first.as_str() {
+			"true"|"false" => {
+				self.pos += 1;
+				(CppType::Builtin("bool".to_string()), select_val(first == "true", 1, 0))
+			}
+			"(" | "-" => {
+				let t = match first.as_str() {
+					"(" => {
+						self.pos += 1;
+						let t = self.ty()?;
+						self.expect(")")?;
+						t
+					}
+					_ => CppType::Builtin("int".to_string()),
+				};
+				let neg = self.peek() == Some("-");
+				if neg {
+					self.pos += 1;
+				}
+				let val = self.next()?.parse::<i64>().map_err(|_| "bad template value".to_string())?;
+				(t, select_val(neg, -val, val))
+			}
+			x if x.starts_with(|c: char| c.is_ascii_digit()) => {
+				let val = self.next()?.parse::<i64>().map_err(|_| "bad template value".to_string())?;
+				(CppType::Builtin("int".to_string()), val)
+			}
+			_ => return Ok(None),
+		};
+		// MSVC doesn't encode the type of the value.
+		let t = select_val(self.abi == Abi::Msvc, CppType::Builtin("int".to_string()), t);
+		Ok(Some(CppType::Value(Box::new(t), val)))
+	}
+	fn ty(&mut self) -> Result<CppType, String> {
+		if let Some(t) = self.value()? {
+			return Ok(t);
+		}
+		let mut is_const = false;
+		if self.peek() == Some("const") {
+			self.pos += 1;
+			is_const = true;
+		}
+		let mut t = self.base()?;
+		while let Some(x) = self.peek().map(|x| x.to_string()) {
+			match x.as_str() {
+				"const" => { is_const = true; }
+				"*"|"&"|"&&" => {
+					if is_const {
+						t = make_const(t);
+						is_const = false;
+					}
+					t = match x.as_str() {
+						"*" => CppType::Ptr(Box::new(t)),
+						"&" => CppType::Ref(Box::new(t)),
+						_ => CppType::RRef(Box::new(t)),
+					};
+				}
+				_ => break,
+			}
+			self.pos += 1;
+		}
+		if is_const {
+			t = make_const(t);
+		}
+		let op = self.toks.get(self.pos + 1).cloned().unwrap_or_default();
+		if self.peek() == Some("(") && (op == "&" || op == "*") {
+			// a reference or pointer to an array, `const float (&)[16]`.
+			self.pos += 2;
+			self.expect(")")?;
+			self.expect("[")?;
+			let n = self.next()?.parse::<usize>().map_err(|_| "bad array size".to_string())?;
+			self.expect("]")?;
+			let arr = Box::new(CppType::Array(Box::new(t), n));
+			return Ok(select_val(op == "&", CppType::Ref(arr.clone()), CppType::Ptr(arr)));
+		}
+		if self.peek() == Some("(") {
+			// a function type, `void(int, bool)`.
+			self.pos += 1;
+			let mut args = Vec::new();
+			while self.peek() != Some(")") {
+				args.push(self.ty()?);
+				if self.peek() == Some(",") {
+					self.pos += 1;
+				}
+			}
+			self.expect(")")?;
+			if args.len() == 1 && args[0] == void() {
+				args.clear();
+			}
+			t = CppType::Func(Box::new(t), args);
+		}
+		Ok(t)
+	}
+	fn base(&mut self) -> Result<CppType, String> {
+		const WORDS: [&str; 8] = ["unsigned", "signed", "long", "short", "int", "char", "double", "__int128"];
+		let first = self.next()?;
+		if WORDS.contains(&first.as_str()) {
+			let mut words = vec![first];
+			while let Some(x) = self.peek() {
+				if !WORDS.contains(&x) {
+					break;
+				}
+				words.push(self.next()?);
+			}
+			// `long int` is `long`, `unsigned` is `unsigned int`.
+			if words.len() > 1 && words.last().map(|x| x.as_str()) == Some("int") {
+				words.pop();
+			}
+			let s = words.join(" ");
+			return builtin_name(&s, self.abi, self.target)
+				.map(|x| CppType::Builtin(x.to_string()))
+				.ok_or(format!("unknown builtin type `{}`", s));
+		}
+		let mut path = vec![first];
+		while self.peek() == Some("::") {
+			self.pos += 1;
+			path.push(self.next()?);
+		}
+		if let Some(x) = path.iter().find(|x| !is_ident(x)) {
+			return Err(format!("`{}` is not an identifier", x));
+		}
+		let mut args = Vec::new();
+		if self.peek() == Some("<") {
+			self.pos += 1;
+			loop {
+				args.push(self.ty()?);
+				if self.peek() == Some(",") {
+					self.pos += 1;
+					continue;
+				}
+				self.expect(">")?;
+				break;
+			}
+		}
+		if args.is_empty() {
+			if let Some(x) = builtin_name(&path.join("::"), self.abi, self.target) {
+				return Ok(CppType::Builtin(x.to_string()));
+			}
+		}
+		if path.len() == 1 && matches!(path[0].as_str(), "shared_ptr"|"unique_ptr"|"default_delete") {
+			path.insert(0, "std".to_string());
+		}
+		// `std::string` is `basic_string<...>`, and the inline namespaces.
+		let lib = select_val(self.abi == Abi::Msvc, StdLib::Msvc, self.target.stdlib);
+		let mut name = path.pop().unwrap_or_default();
+		let mut std_args = select_val(args.is_empty(), None, Some(Vec::new()));
+		std_type(&mut path, &mut name, &mut std_args, lib);
+		if args.is_empty() {
+			for x in std_args.unwrap_or_default() {
+				args.push(parse_cpp_type(&x, self.abi, self.target)?);
+			}
+		}
+		// the defaulted arguments, in the namespace of the template.
+		let in_std = path.first().is_some_and(|x| x == "std");
+		let default_arg = match name.as_str() {
+			"unique_ptr" if in_std && args.len() == 1 => Some("default_delete"),
+			"vector" if in_std && args.len() == 1 => Some("allocator"),
+			_ => None,
+		};
+		if let Some(x) = default_arg {
+			args.push(CppType::Named([path.as_slice(), &[x.to_string()]].concat(), vec![args[0].clone()]));
+		}
+		path.push(name);
+		Ok(CppType::Named(path, args))
+	}
+}
+
+pub fn is_ident(s: &str) -> bool {
+	let mut it = s.chars();
+	matches!(it.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+		&& it.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+pub fn parse_cpp_type(tp: &str, abi: Abi, target: Target) -> Result<CppType, String> {
+	if tp.trim().is_empty() {
+		return Ok(void());
+	}
+	TypeParser::new(tp, abi, target).parse()
+}
+
+/// The C++ declaration a bridged function is expected to link to.
+pub fn expected_signature(func: &SimpFunc, abi: Abi, target: Target) -> Result<Signature, String> {
+	let mut path = func.path().iter().map(|x| x.to_string()).collect::<Vec<_>>();
+	let abi_tag = func.itanium_abi_tag(target.stdlib);
+	if abi == Abi::Itanium && !abi_tag.is_empty() {
+		if let Some(x) = path.last_mut() {
+			*x += &format!("[abi:{}]", abi_tag);
+		}
+	}
+	let targs = func.template_types.iter()
+		.map(|x| parse_cpp_type(x, abi, target))
+		.collect::<Result<Vec<_>, _>>()?;
+	let mut args = func.arg_list.iter()
+		.map(|x| parse_cpp_type(&x.tp_cpp, abi, target))
+		.collect::<Result<Vec<_>, _>>()?;
+	if args.len() == 1 && args[0] == void() {
+		args.clear();
+	}
+	Ok(Signature {
+		name: CppType::Named(path, targs),
+		args,
+		ret: Some(parse_cpp_type(&func.ret.tp_cpp, abi, target)?),
+		is_const: func.is_const,
+		is_static: func.is_static,
+	})
+}
+
+/// Demangle `link_name` and check it against the declaration described by `func`.
+/// Names which are not C++ mangled (extern "C" functions) are accepted as is.
+pub fn verify_link_name(func: &SimpFunc, link_name: &str, target: Target) -> Result<(), String> {
+	let (demangled, abi) = if link_name.starts_with("_Z") {
+		(demangle_itanium(link_name), Abi::Itanium)
+	} else if link_name.starts_with('?') {
+		(demangle_msvc(link_name), Abi::Msvc)
+	} else {
+		return Ok(());
+	};
+	let expected = expected_signature(func, abi, target)?;
+	let expected_ret = expected.ret.clone().unwrap_or(void());
+	let what = format!("link name `{}` is wrong: expected C++ declaration `{}` returning `{}`",
+		link_name, expected, expected_ret);
+	match demangled {
+		Err(e) => Err(format!("{}, but the name can't be demangled: {}", what, e)),
+		Ok(got) => {
+			let ret_ok = got.ret.is_none() || got.ret == expected.ret;
+			let static_ok = abi == Abi::Itanium || got.is_static == expected.is_static;
+			if got.name == expected.name && got.args == expected.args && got.is_const == expected.is_const && ret_ok && static_ok {
+				Ok(())
+			} else {
+				match &got.ret {
+					Some(r) => Err(format!("{}, but it demangles to `{}` returning `{}`", what, got, r)),
+					None => Err(format!("{}, but it demangles to `{}`", what, got)),
+				}
+			}
+		}
+	}
+}
+
+struct Input<'a> {
+	s: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Input<'a> {
+	fn peek(&self) -> u8 {
+		self.s.get(self.pos).copied().unwrap_or(0)
+	}
+	fn peek_at(&self, off: usize) -> u8 {
+		self.s.get(self.pos + off).copied().unwrap_or(0)
+	}
+	fn eat(&mut self, ch: u8) -> bool {
+		if self.peek() == ch {
+			self.pos += 1;
+			true
+		} else {
+			false
+		}
+	}
+	fn expect(&mut self, ch: u8) -> Result<(), String> {
+		if self.eat(ch) {
+			Ok(())
+		} else {
+			Err(format!("expect `{}` at offset {}", ch as char, self.pos))
+		}
+	}
+	fn eof(&self) -> bool {
+		self.pos >= self.s.len()
+	}
+	fn bad<T>(&self) -> Result<T, String> {
+		Err(format!("unexpected `{}` at offset {}", self.peek() as char, self.pos))
+	}
+}
+
+/// Demangle an Itanium C++ ABI function name (gcc, clang).
+pub fn demangle_itanium(sym: &str) -> Result<Signature, String> {
+	let mut p = ItaniumParser { inp: Input { s: sym.as_bytes(), pos: 0 }, subs: Vec::new() };
+	p.inp.expect(b'_')?;
+	p.inp.expect(b'Z')?;
+	let is_const;
+	let name = if p.inp.eat(b'N') {
+		is_const = p.inp.eat(b'K');
+		p.nested_name(false)?
+	} else {
+		is_const = false;
+		p.unscoped_name(false)?
+	};
+	let is_template = matches!(&name, CppType::Named(_, targs) if !targs.is_empty());
+	// templated functions carry their return type.
+	let ret = if is_template { Some(p.ty()?) } else { None };
+	let mut args = Vec::new();
+	while !p.inp.eof() {
+		args.push(p.ty()?);
+	}
+	if args.is_empty() {
+		return Err("missing parameter types".to_string());
+	}
+	if args.len() == 1 && args[0] == void() {
+		args.clear();
+	}
+	// `T_` in the signature stands for the template argument.
+	let targs = match &name {
+		CppType::Named(_, targs) => targs.clone(),
+		_ => Vec::new(),
+	};
+	let ret = ret.map(|x| resolve_params(x, &targs)).transpose()?;
+	let args = args.into_iter().map(|x| resolve_params(x, &targs)).collect::<Result<Vec<_>, _>>()?;
+	Ok(Signature { name, args, ret, is_const, is_static: false })
+}
+
+/// Replace the template parameters in `t` by the arguments.
+fn resolve_params(t: CppType, targs: &[CppType]) -> Result<CppType, String> {
+	let sub = |x: Box<CppType>| resolve_params(*x, targs).map(Box::new);
+	Ok(match t {
+		CppType::Param(idx) => match targs.get(idx) {
+			Some(CppType::Value(..)) | None => return Err(format!("no template type for T{}", idx)),
+			Some(x) => x.clone(),
+		},
+		CppType::Named(path, args) => CppType::Named(path,
+			args.into_iter().map(|x| resolve_params(x, targs)).collect::<Result<_, _>>()?),
+		CppType::Const(x) => make_const(resolve_params(*x, targs)?),
+		CppType::Ptr(x) => CppType::Ptr(sub(x)?),
+		CppType::Ref(x) => CppType::Ref(sub(x)?),
+		CppType::RRef(x) => CppType::RRef(sub(x)?),
+		CppType::Func(r, args) => CppType::Func(sub(r)?,
+			args.into_iter().map(|x| resolve_params(x, targs)).collect::<Result<_, _>>()?),
+		CppType::Array(e, n) => CppType::Array(sub(e)?, n),
+		t => t,
+	})
+}
+
+struct ItaniumParser<'a> {
+	inp: Input<'a>,
+	subs: Vec<CppType>,
+}
+
+impl<'a> ItaniumParser<'a> {
+	fn add_subst(&mut self, t: CppType) -> Result<(), String> {
+		if self.subs.contains(&t) {
+			return Err(format!("`{}` should be a substitution", t));
+		}
+		self.subs.push(t);
+		Ok(())
+	}
+	fn source_name(&mut self) -> Result<String, String> {
+		let mut len = 0;
+		while self.inp.peek().is_ascii_digit() {
+			len = len * 10 + (self.inp.peek() - b'0') as usize;
+			self.inp.pos += 1;
+		}
+		let end = self.inp.pos + len;
+		if len == 0 || end > self.inp.s.len() {
+			return Err(format!("bad source name at offset {}", self.inp.pos));
+		}
+		let name = String::from_utf8_lossy(&self.inp.s[self.inp.pos..end]).to_string();
+		if !is_ident(&name) {
+			return Err(format!("`{}` is not an identifier", name));
+		}
+		self.inp.pos = end;
+		Ok(name)
+	}
+	/// A source name and its abi tags, `1gB5cxx11` is `g[abi:cxx11]`.
+	fn tagged_name(&mut self) -> Result<String, String> {
+		let mut name = self.source_name()?;
+		while self.inp.eat(b'B') {
+			name += &format!("[abi:{}]", self.source_name()?);
+		}
+		Ok(name)
+	}
+	/// `_` is 0, a base 36 number `n` then `_` is n + 1.
+	fn seq_id(&mut self, what: &str) -> Result<usize, String> {
+		if self.inp.eat(b'_') {
+			return Ok(0);
+		}
+		let mut val = 0usize;
+		loop {
+			let ch = self.inp.peek();
+			self.inp.pos += 1;
+			match ch {
+				b'0'..=b'9' => val = val * 36 + (ch - b'0') as usize,
+				b'A'..=b'Z' => val = val * 36 + (ch - b'A') as usize + 10,
+				b'_' => return Ok(val + 1),
+				_ => return Err(format!("bad {} at offset {}", what, self.inp.pos - 1)),
+			}
+		}
+	}
+	fn substitution(&mut self) -> Result<CppType, String> {
+		self.inp.expect(b'S')?;
+		let idx = self.seq_id("substitution")?;
+		self.subs.get(idx).cloned().ok_or(format!("substitution S{} out of range", idx))
+	}
+	fn template_args(&mut self) -> Result<Vec<CppType>, String> {
+		self.inp.expect(b'I')?;
+		let mut args = Vec::new();
+		while !self.inp.eat(b'E') {
+			if self.inp.eat(b'L') {
+				args.push(self.literal()?);
+			} else {
+				args.push(self.ty()?);
+			}
+		}
+		Ok(args)
+	}
+	/// After `L`, an integer literal such as `i4E` or `ln3E`.
+	fn literal(&mut self) -> Result<CppType, String> {
+		let t = self.ty()?;
+		if !matches!(t, CppType::Builtin(_)) {
+			return Err(format!("`{}` can't be a template value", t));
+		}
+		let neg = self.inp.eat(b'n');
+		let start = self.inp.pos;
+		while self.inp.peek().is_ascii_digit() {
+			self.inp.pos += 1;
+		}
+		let digits = String::from_utf8_lossy(&self.inp.s[start..self.inp.pos]).to_string();
+		let val = digits.parse::<i64>().map_err(|_| format!("bad literal at offset {}", start))?;
+		self.inp.expect(b'E')?;
+		Ok(CppType::Value(Box::new(t), select_val(neg, -val, val)))
+	}
+	/// After `T`, `_` is the first template parameter, `0_` the second.
+	fn template_param(&mut self) -> Result<CppType, String> {
+		let t = CppType::Param(self.seq_id("template parameter")?);
+		self.add_subst(t.clone())?;
+		Ok(t)
+	}
+	/// After `N`. `is_type` registers the full name as a substitution too.
+	fn nested_name(&mut self, is_type: bool) -> Result<CppType, String> {
+		let mut path: Vec<String> = Vec::new();
+		let mut targs = Vec::new();
+		// the prefix seen so far, it's a candidate once we know it is not the last component.
+		let mut pending: Option<CppType> = None;
+		loop {
+			if self.inp.eat(b'E') {
+				break;
+			}
+			if !targs.is_empty() {
+				return Err("template arguments are only supported on the last name".to_string());
+			}
+			if let Some(t) = pending.take() {
+				self.add_subst(t)?;
+			}
+			match self.inp.peek() {
+				b'S' if path.is_empty() && self.inp.peek_at(1) == b't' => {
+					self.inp.pos += 2;
+					path.push("std".to_string());
+				}
+				b'S' if path.is_empty() => {
+					match self.substitution()? {
+						CppType::Named(p, a) if a.is_empty() => path = p,
+						t => return Err(format!("`{}` can't be a name prefix", t)),
+					}
+				}
+				b'0'..=b'9' => {
+					path.push(self.tagged_name()?);
+					pending = Some(CppType::Named(path.clone(), Vec::new()));
+				}
+				b'a'..=b'z' if !is_type && !path.is_empty() => {
+					path.push(self.operator_name()?);
+				}
+				// complete object constructor and destructor.
+				b'C'|b'D' if !is_type && !path.is_empty() && self.inp.peek_at(1) == b'1' => {
+					let kls = path.last().cloned().unwrap_or_default();
+					path.push(select_val(self.inp.peek() == b'C', kls.clone(), format!("~{}", kls)));
+					self.inp.pos += 2;
+				}
+				b'I' if !path.is_empty() => {
+					targs = self.template_args()?;
+				}
+				_ => return self.inp.bad(),
+			}
+		}
+		if path.len() < 2 {
+			return Err("nested name with a single component".to_string());
+		}
+		let t = CppType::Named(path, targs);
+		if is_type {
+			self.add_subst(t.clone())?;
+		}
+		Ok(t)
+	}
+	/// A two letter operator name such as `eq`, as `operator==`.
+	fn operator_name(&mut self) -> Result<String, String> {
+		let code = String::from_utf8_lossy(&self.inp.s[self.inp.pos..(self.inp.pos + 2).min(self.inp.s.len())]).to_string();
+		match OPERATORS.iter().chain(UNARY_OPERATORS.iter()).find(|x| x.1 == code) {
+			Some(x) => {
+				self.inp.pos += 2;
+				Ok(format!("operator{}", x.0))
+			}
+			None => self.inp.bad(),
+		}
+	}
+	fn unscoped_name(&mut self, is_type: bool) -> Result<CppType, String> {
+		let std = |x: &str| vec!["std".to_string(), x.to_string()];
+		let (path, registered) = match (self.inp.peek(), self.inp.peek_at(1)) {
+			(b'S', b't') => {
+				self.inp.pos += 2;
+				(vec!["std".to_string(), self.tagged_name()?], false)
+			}
+			// the abbreviations are no candidates, their template instances are.
+			(b'S', b'a') | (b'S', b'b') => {
+				self.inp.pos += 2;
+				(std(select_val(self.inp.s[self.inp.pos - 1] == b'a', "allocator", "basic_string")), true)
+			}
+			(b'S', b's') => {
+				self.inp.pos += 2;
+				let char_t = || CppType::Builtin("char".to_string());
+				return Ok(CppType::Named(std("basic_string"), vec![
+					char_t(),
+					CppType::Named(std("char_traits"), vec![char_t()]),
+					CppType::Named(std("allocator"), vec![char_t()]),
+				]));
+			}
+			(b'S', _) => match self.substitution()? {
+				CppType::Named(p, a) if a.is_empty() => (p, true),
+				t if self.inp.peek() != b'I' => return Ok(t),
+				t => return Err(format!("`{}` is not a template", t)),
+			},
+			(b'0'..=b'9', _) => (vec![self.tagged_name()?], false),
+			(b'a'..=b'z', _) if !is_type => (vec![self.operator_name()?], false),
+			_ => return self.inp.bad(),
+		};
+		if self.inp.peek() == b'I' {
+			if !registered {
+				self.add_subst(CppType::Named(path.clone(), Vec::new()))?;
+			}
+			let targs = self.template_args()?;
+			let t = CppType::Named(path, targs);
+			if is_type {
+				self.add_subst(t.clone())?;
+			}
+			return Ok(t);
+		}
+		let t = CppType::Named(path, Vec::new());
+		if is_type && !registered {
+			self.add_subst(t.clone())?;
+		}
+		Ok(t)
+	}
+	fn ty(&mut self) -> Result<CppType, String> {
+		let ch = self.inp.peek();
+		let builtin = match ch {
+			b'v' => "void",
+			b'w' => "wchar_t",
+			b'b' => "bool",
+			b'c' => "char",
+			b'a' => "signed char",
+			b'h' => "unsigned char",
+			b's' => "short",
+			b't' => "unsigned short",
+			b'i' => "int",
+			b'j' => "unsigned int",
+			b'l' => "long",
+			b'm' => "unsigned long",
+			b'x' => "long long",
+			b'y' => "unsigned long long",
+			b'n' => "__int128",
+			b'o' => "unsigned __int128",
+			b'f' => "float",
+			b'd' => "double",
+			b'e' => "long double",
+			_ => "",
+		};
+		if !builtin.is_empty() {
+			self.inp.pos += 1;
+			return Ok(CppType::Builtin(builtin.to_string()));
+		}
+		match ch {
+			b'D' => {
+				let builtin = match self.inp.peek_at(1) {
+					b's' => "char16_t",
+					b'i' => "char32_t",
+					b'u' => "char8_t",
+					b'n' => "decltype(nullptr)",
+					_ => return self.inp.bad(),
+				};
+				self.inp.pos += 2;
+				Ok(CppType::Builtin(builtin.to_string()))
+			}
+			b'P'|b'R'|b'O'|b'K' => {
+				self.inp.pos += 1;
+				let inner = Box::new(self.ty()?);
+				let t = match ch {
+					b'P' => CppType::Ptr(inner),
+					b'R' => CppType::Ref(inner),
+					b'O' => CppType::RRef(inner),
+					_ => make_const(*inner),
+				};
+				self.add_subst(t.clone())?;
+				Ok(t)
+			}
+			b'N' => {
+				self.inp.pos += 1;
+				self.nested_name(true)
+			}
+			b'A' => {
+				self.inp.pos += 1;
+				let mut n = 0;
+				while self.inp.peek().is_ascii_digit() {
+					n = n * 10 + (self.inp.peek() - b'0') as usize;
+					self.inp.pos += 1;
+				}
+				self.inp.expect(b'_')?;
+				let t = CppType::Array(Box::new(self.ty()?), n);
+				self.add_subst(t.clone())?;
+				Ok(t)
+			}
+			b'F' => {
+				self.inp.pos += 1;
+				let ret = Box::new(self.ty()?);
+				let mut args = Vec::new();
+				while !self.inp.eat(b'E') {
+					args.push(self.ty()?);
+				}
+				if args.len() == 1 && args[0] == void() {
+					args.clear();
+				}
+				let t = CppType::Func(ret, args);
+				self.add_subst(t.clone())?;
+				Ok(t)
+			}
+			b'T' => {
+				self.inp.pos += 1;
+				self.template_param()
+			}
+			b'S'|b'0'..=b'9' => self.unscoped_name(true),
+			_ => self.inp.bad(),
+		}
+	}
+}
+
+/// Demangle a MSVC C++ function name.
+pub fn demangle_msvc(sym: &str) -> Result<Signature, String> {
+	let mut p = MsvcParser { inp: Input { s: sym.as_bytes(), pos: 0 }, names: Vec::new(), args: Vec::new() };
+	p.inp.expect(b'?')?;
+	// `??0Foo@@` is the constructor `Foo::Foo`, `??1Foo@@` the destructor `Foo::~Foo`.
+	let special = match (p.inp.peek(), p.inp.peek_at(1)) {
+		(b'?', ch @ (b'0'|b'1')) => {
+			p.inp.pos += 2;
+			Some(ch)
+		}
+		_ => None,
+	};
+	// `??8` is `operator==` and so on, the scopes follow.
+	let op = match special {
+		None if p.inp.peek() == b'?' && p.inp.peek_at(1) != b'$' => {
+			let rest = &sym[p.inp.pos..];
+			match OPERATORS.iter().filter(|x| rest.starts_with(x.2)).max_by_key(|x| x.2.len()) {
+				Some(x) => {
+					p.inp.pos += x.2.len();
+					Some(format!("operator{}", x.0))
+				}
+				None => return p.inp.bad(),
+			}
+		}
+		_ => None,
+	};
+	let name = match special {
+		None if op.is_some() => {
+			let mut path = Vec::new();
+			while !p.inp.eat(b'@') {
+				let (scope, sargs) = p.unqualified(false)?;
+				if !sargs.is_empty() {
+					return Err("templated scopes are not supported".to_string());
+				}
+				path.insert(0, scope);
+			}
+			path.push(op.unwrap_or_default());
+			CppType::Named(path, Vec::new())
+		}
+		None => p.qualified_name(true)?,
+		Some(ch) => match p.qualified_name(false)? {
+			CppType::Named(mut path, targs) => {
+				let kls = path.last().cloned().unwrap_or_default();
+				path.push(select_val(ch == b'0', kls.clone(), format!("~{}", kls)));
+				CppType::Named(path, targs)
+			}
+			t => t,
+		},
+	};
+	let mut is_static = false;
+	let is_const = match p.inp.peek() {
+		b'Y' => {
+			p.inp.pos += 1;
+			false
+		}
+		b'S' => {
+			p.inp.pos += 1;
+			is_static = true;
+			false
+		}
+		b'Q' => {
+			p.inp.pos += 1;
+			p.inp.eat(b'E');  // __ptr64
+			match p.inp.peek() {
+				b'A' => { p.inp.pos += 1; false }
+				b'B' => { p.inp.pos += 1; true }
+				_ => return p.inp.bad(),
+			}
+		}
+		_ => return p.inp.bad(),
+	};
+	p.inp.expect(b'A')?;  // __cdecl
+	let ret = if special.is_some() {
+		p.inp.expect(b'@')?;  // no return type
+		None
+	} else if p.inp.eat(b'?') {
+		p.inp.expect(b'A')?;
+		let t = p.ty()?;
+		if !matches!(t, CppType::Named(..)) {
+			return Err(format!("return type `{}` should not have a storage class", t));
+		}
+		Some(t)
+	} else {
+		let t = p.ty()?;
+		if matches!(t, CppType::Named(..)) {
+			return Err(format!("return type `{}` needs storage class `?A`", t));
+		}
+		Some(t)
+	};
+	let mut args = Vec::new();
+	if !p.inp.eat(b'X') {
+		while !p.inp.eat(b'@') {
+			args.push(p.arg_type()?);
+		}
+	}
+	p.inp.expect(b'Z')?;
+	if !p.inp.eof() {
+		return p.inp.bad();
+	}
+	Ok(Signature { name, args, ret, is_const, is_static })
+}
+
+struct MsvcParser<'a> {
+	inp: Input<'a>,
+	names: Vec<(String, String, Vec<CppType>)>,  // name back references: raw, name, template args
+	args: Vec<(String, CppType)>,  // argument back references
+}
+
+impl<'a> MsvcParser<'a> {
+	fn memorize(&mut self, raw: &str, name: &str, targs: &[CppType]) -> Result<(), String> {
+		if self.names.iter().any(|x| x.0 == raw) {
+			return Err(format!("`{}` should be a back reference", raw));
+		}
+		if self.names.len() < 10 {
+			self.names.push((raw.to_string(), name.to_string(), targs.to_vec()));
+		}
+		Ok(())
+	}
+	fn simple_name(&mut self) -> Result<String, String> {
+		let start = self.inp.pos;
+		while !self.inp.eof() && self.inp.peek() != b'@' {
+			self.inp.pos += 1;
+		}
+		let name = String::from_utf8_lossy(&self.inp.s[start..self.inp.pos]).to_string();
+		self.inp.expect(b'@')?;
+		if !is_ident(&name) {
+			return Err(format!("`{}` is not an identifier", name));
+		}
+		Ok(name)
+	}
+	/// One `@`-terminated component. Function templates are not memorized.
+	fn unqualified(&mut self, is_func: bool) -> Result<(String, Vec<CppType>), String> {
+		let ch = self.inp.peek();
+		if ch.is_ascii_digit() {
+			self.inp.pos += 1;
+			let x = self.names.get((ch - b'0') as usize).ok_or(format!("name back reference {} out of range", ch as char))?;
+			return Ok((x.1.clone(), x.2.clone()));
+		}
+		if ch == b'?' && self.inp.peek_at(1) == b'$' {
+			// templates have their own back reference context.
+			let start = self.inp.pos;
+			self.inp.pos += 2;
+			let old_names = std::mem::take(&mut self.names);
+			let old_args = std::mem::take(&mut self.args);
+			let res = self.template_rest();
+			self.names = old_names;
+			self.args = old_args;
+			let (name, targs) = res?;
+			let raw = String::from_utf8_lossy(&self.inp.s[start..self.inp.pos]).to_string();
+			self.inp.expect(b'@')?;
+			if !is_func {
+				self.memorize(&raw, &name, &targs)?;
+			}
+			return Ok((name, targs));
+		}
+		let name = self.simple_name()?;
+		self.memorize(&name, &name, &Vec::new())?;
+		Ok((name, Vec::new()))
+	}
+	fn template_rest(&mut self) -> Result<(String, Vec<CppType>), String> {
+		let name = self.simple_name()?;
+		self.memorize(&name, &name, &Vec::new())?;
+		let mut targs = Vec::new();
+		while self.inp.peek() != b'@' {
+			if self.inp.eof() {
+				return self.inp.bad();
+			}
+			targs.push(self.ty()?);
+		}
+		Ok((name, targs))
+	}
+	fn qualified_name(&mut self, is_func: bool) -> Result<CppType, String> {
+		let (first, targs) = self.unqualified(is_func)?;
+		let mut path = vec![first];
+		while !self.inp.eat(b'@') {
+			let (scope, sargs) = self.unqualified(false)?;
+			if !sargs.is_empty() {
+				return Err("templated scopes are not supported".to_string());
+			}
+			path.insert(0, scope);
+		}
+		Ok(CppType::Named(path, targs))
+	}
+	/// A digit is 1 to 10, otherwise hex digits `A`-`P` ended by `@`.
+	fn number(&mut self) -> Result<usize, String> {
+		let ch = self.inp.peek();
+		self.inp.pos += 1;
+		if ch.is_ascii_digit() {
+			return Ok((ch - b'0') as usize + 1);
+		}
+		self.inp.pos -= 1;
+		let mut n = 0;
+		while !self.inp.eat(b'@') {
+			match self.inp.peek() {
+				ch @ b'A'..=b'P' => n = n * 16 + (ch - b'A') as usize,
+				_ => return self.inp.bad(),
+			}
+			self.inp.pos += 1;
+		}
+		Ok(n)
+	}
+	fn arg_type(&mut self) -> Result<CppType, String> {
+		let ch = self.inp.peek();
+		if ch.is_ascii_digit() {
+			self.inp.pos += 1;
+			return self.args.get((ch - b'0') as usize)
+				.map(|x| x.1.clone())
+				.ok_or(format!("argument back reference {} out of range", ch as char));
+		}
+		let start = self.inp.pos;
+		let t = self.ty()?;
+		let raw = String::from_utf8_lossy(&self.inp.s[start..self.inp.pos]).to_string();
+		if raw.len() > 1 {
+			if self.args.iter().any(|x| x.1 == t) {
+				return Err(format!("argument `{}` should be a back reference", raw));
+			}
+			if self.args.len() < 10 {
+				self.args.push((raw, t.clone()));
+			}
+		}
+		Ok(t)
+	}
+	fn ty(&mut self) -> Result<CppType, String> {
+		let ch = self.inp.peek();
+		self.inp.pos += 1;
+		let builtin = match ch {
+			b'X' => "void",
+			b'D' => "char",
+			b'C' => "signed char",
+			b'E' => "unsigned char",
+			b'F' => "short",
+			b'G' => "unsigned short",
+			b'H' => "int",
+			b'I' => "unsigned int",
+			b'J' => "long",
+			b'K' => "unsigned long",
+			b'M' => "float",
+			b'N' => "double",
+			b'O' => "long double",
+			b'_' => {
+				let ch2 = self.inp.peek();
+				self.inp.pos += 1;
+				match ch2 {
+					b'N' => "bool",
+					b'J' => "__int64",
+					b'K' => "unsigned __int64",
+					b'W' => "wchar_t",
+					b'S' => "char16_t",
+					b'U' => "char32_t",
+					b'Q' => "char8_t",
+					_ => {
+						self.inp.pos -= 2;
+						return self.inp.bad();
+					}
+				}
+			}
+			_ => "",
+		};
+		if !builtin.is_empty() {
+			return Ok(CppType::Builtin(builtin.to_string()));
+		}
+		match ch {
+			// `Q` is a const pointer, the constness is already given by what points to it.
+			b'P'|b'Q'|b'A' => {
+				self.inp.eat(b'E');  // __ptr64
+				let is_const = match self.inp.peek() {
+					b'A' => false,
+					b'B' => true,
+					_ => return self.inp.bad(),
+				};
+				self.inp.pos += 1;
+				let mut inner = self.ty()?;
+				if is_const {
+					inner = make_const(inner);
+				}
+				Ok(select_val(ch != b'A', CppType::Ptr(Box::new(inner.clone())), CppType::Ref(Box::new(inner))))
+			}
+			b'Y' => {
+				// an array, the number of dimensions then each of them.
+				let dims = (0..self.number()?).map(|_| self.number()).collect::<Result<Vec<_>, _>>()?;
+				let is_const = self.inp.s[self.inp.pos..].starts_with(b"$$CB");
+				if is_const || self.inp.s[self.inp.pos..].starts_with(b"$$CA") {
+					self.inp.pos += 4;
+				}
+				let mut t = self.ty()?;
+				if is_const {
+					t = make_const(t);
+				}
+				for n in dims.into_iter().rev() {
+					t = CppType::Array(Box::new(t), n);
+				}
+				Ok(t)
+			}
+			b'U'|b'V' => self.qualified_name(false),
+			b'W' => {
+				// an enum with int as the underlying type.
+				self.inp.expect(b'4')?;
+				self.qualified_name(false)
+			}
+			b'$' if self.inp.peek() == b'0' => {
+				// a non-type template argument, its type is not encoded.
+				self.inp.pos += 1;
+				let neg = self.inp.eat(b'?');
+				let val = self.number()? as i64;
+				Ok(CppType::Value(Box::new(CppType::Builtin("int".to_string())), select_val(neg, -val, val)))
+			}
+			b'$' if self.inp.s[self.inp.pos..].starts_with(b"$Q") => {
+				// an rvalue reference.
+				self.inp.pos += 2;
+				self.inp.eat(b'E');
+				let is_const = match self.inp.peek() {
+					b'A' => false,
+					b'B' => true,
+					_ => return self.inp.bad(),
+				};
+				self.inp.pos += 1;
+				let mut inner = self.ty()?;
+				if is_const {
+					inner = make_const(inner);
+				}
+				Ok(CppType::RRef(Box::new(inner)))
+			}
+			b'$' if self.inp.s[self.inp.pos..].starts_with(b"$T") => {
+				self.inp.pos += 2;
+				Ok(CppType::Builtin("std::nullptr_t".to_string()))
+			}
+			b'$' if self.inp.s[self.inp.pos..].starts_with(b"$A6A") => {
+				// a function type, cdecl.
+				self.inp.pos += 4;
+				let ret = if self.inp.eat(b'?') {
+					self.inp.expect(b'A')?;
+					self.ty()?
+				} else {
+					self.ty()?
+				};
+				let mut args = Vec::new();
+				if !self.inp.eat(b'X') {
+					while !self.inp.eat(b'@') {
+						args.push(self.arg_type()?);
+					}
+				}
+				self.inp.expect(b'Z')?;
+				Ok(CppType::Func(Box::new(ret), args))
+			}
+			_ => {
+				self.inp.pos -= 1;
+				self.inp.bad()
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mangle::SimpArg;
+
+	const LINUX: Target = Target{windows: false, macos: false, is64: true, i386: false, stdlib: StdLib::Libstdcxx};
+
+	fn sig(sym: &str) -> String {
+		match sym.starts_with("_Z") {
+			true => demangle_itanium(sym),
+			false => demangle_msvc(sym),
+		}.map(|x| x.to_string()).unwrap_or_else(|e| format!("error: {}", e))
+	}
+
+	#[test]
+	fn test_demangle_gcc() {
+		// all from g++ 12 / c++filt
+		assert_eq!(sig("_Z3foov"), "foo()");
+		assert_eq!(sig("_ZN6ns_foo6ns_bar7cpp_ptrEPKcS2_mPKhm"),
+			"ns_foo::ns_bar::cpp_ptr(char const*, char const*, unsigned long, unsigned char const*, unsigned long)");
+		assert_eq!(sig("_ZN5Proof5mergeEPS_"), "Proof::merge(Proof*)");
+		assert_eq!(sig("_ZNK5Proof4takeERKS_PS_"), "Proof::take(Proof const&, Proof*) const");
+		assert_eq!(sig("_ZN5ProofC1ERKS_"), "Proof::Proof(Proof const&)");
+		assert_eq!(sig("_ZN2ns6WidgetD1Ev"), "ns::Widget::~Widget()");
+		assert_eq!(sig("_ZNK5ProofixEm"), "Proof::operator[](unsigned long) const");
+		assert_eq!(sig("_ZltRK5ProofS1_"), "operator<(Proof const&, Proof const&)");
+		assert_eq!(sig("_ZN1a1b1C1fEPS1_S2_"), "a::b::C::f(a::b::C*, a::b::C*)");
+		assert_eq!(sig("_ZN1a1b1gEPNS0_1CE"), "a::b::g(a::b::C*)");
+		assert_eq!(sig("_ZN3ffi8man_dtorI3tplI10RustStringEEEvPv"), "ffi::man_dtor<tpl<RustString>>(void*)");
+		assert_eq!(sig("_ZN3ffi8man_dtorISt10unique_ptrI1TSt14default_deleteIS2_EEEEvPv"),
+			"ffi::man_dtor<std::unique_ptr<T, std::default_delete<T>>>(void*)");
+		assert_eq!(sig("_Z10slow_tostrP12ValuePromiseI10RustStringEi"), "slow_tostr(ValuePromise<RustString>*, int)");
+		assert_eq!(sig("_Z5applyiR6RustFnIFiiEERS_IFbvEE"), "apply(int, RustFn<int (int)>&, RustFn<bool ()>&)");
+		assert_eq!(sig("_Z5twiceR6RustFnIFhRK10RustStringS2_EES2_"),
+			"twice(RustFn<unsigned char (RustString const&, RustString const&)>&, RustString const&)");
+		assert_eq!(sig("_Z2lglmxwDijsha"),
+			"lg(long, unsigned long, long long, wchar_t, char32_t, unsigned int, short, unsigned char, signed char)");
+		assert_eq!(sig("_Z5charsDiwDsDu"), "chars(char32_t, wchar_t, char16_t, char8_t)");
+		assert_eq!(sig("_Z4wideno"), "wide(__int128, unsigned __int128)");
+		assert_eq!(sig("_Z3nulDne"), "nul(decltype(nullptr), long double)");
+		assert_eq!(sig("_Z10read_tablePKPdPPKh"), "read_table(double* const*, unsigned char const**)");
+		assert_eq!(sig("_Z1mRA16_KfRA32_hRA2_K1P"), "m(float const (&) [16], unsigned char (&) [32], P const (&) [2])");
+		assert_eq!(sig("_Z2m2RA16_KfS1_RA16_f"), "m2(float const (&) [16], float const (&) [16], float (&) [16])");
+		assert_eq!(sig("_Z11clamp_valueIfET_S0_S0_S0_"), "clamp_value<float>(float, float, float)");
+		assert_eq!(sig("_Z4fillI8MyStructLi4EEvPT_RKS1_"), "fill<MyStruct, 4>(MyStruct*, MyStruct const&)");
+		assert_eq!(sig("_Z4pickI8MyStructLb1EEPT_S2_Pf"), "pick<MyStruct, true>(MyStruct*, float*)");
+		assert_eq!(sig("_Z3negILln3EElv"), "neg<-3l>()");
+		assert_eq!(sig("_ZN2ns5firstIfEERKT_PS2_m"), "ns::first<float>(float const*, unsigned long)");
+		assert_eq!(sig("_Z1kP7RustVecIiEPS_IfEPSaIcEPSaIiE"),
+			"k(RustVec<int>*, RustVec<float>*, std::allocator<char>*, std::allocator<int>*)");
+		assert_eq!(sig("_ZN1a1gB5cxx11ERKNSt7__cxx1112basic_stringIcSt11char_traitsIcESaIcEEE"),
+			"a::g[abi:cxx11](std::__cxx11::basic_string<char, std::char_traits<char>, std::allocator<char>> const&)");
+		assert_eq!(sig("_ZN5Outer5Inner1mERKS0_PS_"), "Outer::Inner::m(Outer::Inner const&, Outer*)");
+		assert_eq!(sig("_Z6taggedB2v1i"), "tagged[abi:v1](int)");
+		// c++filt shows `std::string`.
+		assert_eq!(sig("_Z1fRKSs"), "f(std::basic_string<char, std::char_traits<char>, std::allocator<char>> const&)");
+	}
+
+	#[test]
+	fn test_demangle_strict() {
+		// the second `const char*` must be a substitution.
+		assert!(demangle_itanium("_ZN6ns_foo6ns_bar7cpp_ptrEPKcPKc").is_err());
+		// S0_ is `ns_foo::ns_bar` here, not `const char*`.
+		assert_eq!(sig("_ZN6ns_foo6ns_bar7cpp_ptrEPKcS0_"), "ns_foo::ns_bar::cpp_ptr(char const*, ns_foo::ns_bar)");
+		// `Sa` is no candidate, there is no S1_.
+		assert!(demangle_itanium("_Z1kPSaIcES1_").is_err());
+		assert_eq!(sig("_Z1kPSaIcES0_"), "k(std::allocator<char>*, std::allocator<char>*)");
+		assert!(demangle_itanium("_ZN5Proof5mergeEP5Proof").is_err());
+		assert!(demangle_itanium("_Z3fooPKc").is_ok());
+		assert!(demangle_itanium("_Z3foo").is_err());
+		assert!(demangle_itanium("_Z13unsigned longv").is_err());
+		// `T_` is a candidate like any other type.
+		assert!(demangle_itanium("_Z11clamp_valueIfET_T_S0_S0_").is_err());
+		assert!(demangle_itanium("_Z11clamp_valueIfET0_S0_S0_S0_").is_err());
+		assert!(demangle_msvc("?foo@Proof@@QEAAXPEAVProof@@@Z").is_err());
+		assert!(demangle_msvc("?foo@@YAXPEBDPEBD@Z").is_err());
+		assert!(demangle_msvc("?merge@@YAXAEBU?$RustVec@E@@AEBU1@@Z").is_err());
+		assert!(demangle_msvc("?get@@YA?APEAVProof@@XZ").is_err());
+		assert!(demangle_msvc("?get@@YAUMagicOut@@XZ").is_err());
+	}
+
+	#[test]
+	fn test_demangle_msvc() {
+		assert_eq!(sig("?on_start@@YAXXZ"), "on_start()");
+		assert_eq!(sig("?cpp_ptr@ns_bar@ns_foo@@YAHPEBD0_KPEBE1@Z"),
+			"ns_foo::ns_bar::cpp_ptr(char const*, char const*, unsigned __int64, unsigned char const*, unsigned __int64)");
+		assert_eq!(sig("??$man_dtor@UFoo@@@ffi@@YAXPEAX@Z"), "ffi::man_dtor<Foo>(void*)");
+		assert_eq!(sig("?cpp_ptr@@YA?AV?$shared_ptr@VCppStruct@@@std@@H@Z"), "cpp_ptr(int)");
+		assert_eq!(sig("?foo@Proof@@QEAAXPEAV1@@Z"), "Proof::foo(Proof*)");
+		assert_eq!(sig("?Print@Proof@@QEBAXXZ"), "Proof::Print() const");
+		assert_eq!(sig("??0Proof@@QEAA@AEBV0@@Z"), "Proof::Proof(Proof const&)");
+		assert_eq!(sig("??1Widget@ns@@QEAA@XZ"), "ns::Widget::~Widget()");
+		assert_eq!(sig("??8Proof@@QEBA_NAEBV0@@Z"), "Proof::operator==(Proof const&) const");
+		assert_eq!(sig("??M@YA_NAEBVProof@@0@Z"), "operator<(Proof const&, Proof const&)");
+		assert_eq!(sig("??_0Proof@@QEAAAEAV0@H@Z"), "Proof::operator/=(int)");
+		assert_eq!(sig("?foo@@YA?AV?$unique_ptr@VFoo@@U?$default_delete@VFoo@@@std@@@std@@XZ"), "foo()");
+		assert_eq!(sig("?apply@@YAHHAEAU?$RustFn@$$A6AHH@Z@@AEAU?$RustFn@$$A6A_NXZ@@@Z"),
+			"apply(int, RustFn<int (int)>&, RustFn<bool ()>&)");
+		let x = demangle_msvc("?create@Proof@@SAPEAV1@H@Z").unwrap();
+		assert_eq!(x.to_string(), "Proof::create(int)");
+		assert!(x.is_static);
+		let x = demangle_msvc("?get_message@myns@@YA?AURustString@@XZ").unwrap();
+		assert_eq!(x.ret.unwrap().to_string(), "RustString");
+		let x = demangle_msvc("?next_char@@YA_SAEBW4Color@ns@@@Z").unwrap();
+		assert_eq!(x.to_string(), "next_char(ns::Color const&)");
+		assert_eq!(x.ret.unwrap().to_string(), "char16_t");
+		assert_eq!(sig("?nul@@YAX$$TO@Z"), "nul(std::nullptr_t, long double)");
+		assert_eq!(sig("?keep@@YA_K$$QEAURustString@@$$QEBU1@@Z"), "keep(RustString&&, RustString const&&)");
+		assert_eq!(sig("?chars@@YA_U_U_W_S_Q@Z"), "chars(char32_t, wchar_t, char16_t, char8_t)");
+		assert_eq!(sig("?read_table@@YAXPEBQEANPEAPEBE@Z"), "read_table(double* const*, unsigned char const**)");
+		assert_eq!(sig("?m2@@YAXAEAY0BA@$$CBM0AEAY0BA@M@Z"), "m2(float const (&) [16], float const (&) [16], float (&) [16])");
+		assert_eq!(sig("?hash@@YAXAAY0CA@$$CBE@Z"), "hash(unsigned char const (&) [32])");
+		assert_eq!(sig("??$fill@UMyStruct@@$03@@YAXPEAUMyStruct@@AEBU0@@Z"), "fill<MyStruct, 4>(MyStruct*, MyStruct const&)");
+		assert_eq!(sig("??$neg@$0?2@@YAJXZ"), "neg<-3>()");
+		assert_eq!(sig("??$flag@$0A@@@YAXXZ"), "flag<0>()");
+	}
+
+	fn func(name: &str, ret: &str, args: &[&str]) -> SimpFunc {
+		let mut f = SimpFunc::default();
+		let (ns, name) = name.rsplit_once("::").unwrap_or(("", name));
+		f.set_scope(ns, "");
+		f.fn_name = name.to_string();
+		f.ret.tp_cpp = ret.to_string();
+		for x in args {
+			f.arg_list.push(SimpArg{tp_cpp: x.to_string(), ..Default::default()});
+		}
+		f
+	}
+
+	#[test]
+	fn test_verify() {
+		let f = func("myns::get_message", "RustString", &[]);
+		assert!(verify_link_name(&f, "_ZN4myns11get_messageEv", LINUX).is_ok());
+		assert!(verify_link_name(&f, "?get_message@myns@@YA?AURustString@@XZ", LINUX).is_ok());
+		assert!(verify_link_name(&f, "get_message", LINUX).is_ok());
+		let e = verify_link_name(&f, "_ZN4myns11get_messageEi", LINUX).unwrap_err();
+		assert!(e.contains("`myns::get_message()` returning `RustString`"), "{}", e);
+		assert!(e.contains("demangles to `myns::get_message(int)`"), "{}", e);
+		let e = verify_link_name(&f, "?get_message@myns@@YA?AUMagicOut@@XZ", LINUX).unwrap_err();
+		assert!(e.contains("returning `MagicOut`"), "{}", e);
+		let e = verify_link_name(&f, "_ZN4myns11get_messageE", LINUX).unwrap_err();
+		assert!(e.contains("can't be demangled"), "{}", e);
+
+		let f = func("cpp_ptr", "void", &["int", "const char*", "size_t", "const uint8_t *"]);
+		let e = verify_link_name(&f, "_Z7cpp_ptriPKcmPKh", LINUX);
+		assert!(e.is_ok(), "{:?}", e);
+		assert!(verify_link_name(&f, "_Z7cpp_ptriPKcjPKh", Target::new("x86", "linux")).is_ok());
+		let mut f = func("ffi::man_dtor", "", &["void*"]);
+		f.template_types.push("unique_ptr<T>".to_string());
+		assert!(verify_link_name(&f, "_ZN3ffi8man_dtorISt10unique_ptrI1TSt14default_deleteIS2_EEEEvPv", LINUX).is_ok());
+		assert!(verify_link_name(&f, "_ZN3ffi8man_dtorI10unique_ptrI1TEEEvPv", LINUX).is_err());
+		assert!(verify_link_name(&f, "??$man_dtor@V?$unique_ptr@VT@@U?$default_delete@VT@@@std@@@std@@@ffi@@YAXPEAX@Z", LINUX).is_ok());
+		let mut f = func("neg", "long", &[]);
+		f.template_types.push("(long)-3".to_string());
+		assert!(verify_link_name(&f, "_Z3negILln3EElv", LINUX).is_ok());
+		assert!(verify_link_name(&f, "_Z3negILin3EElv", LINUX).is_err());
+		assert!(verify_link_name(&f, "??$neg@$0?2@@YAJXZ", LINUX).is_ok());
+		assert!(verify_link_name(&f, "??$neg@$02@@YAJXZ", LINUX).is_err());
+	}
+}
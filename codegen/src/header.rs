@@ -0,0 +1,317 @@
+// Build the C++ declarations a bridge block expects, so the C++ side can include them
+// and let the compiler find signature mismatches instead of the linker.
+use std::fs;
+use std::path::{Path, PathBuf};
+use regex::Regex;
+use crate::mangle::{class_key, split_template_args, SimpArg, SimpFunc};
+use crate::util::select_val;
+
+lazy_static::lazy_static! {
+	static ref SMART_PTR: Regex = Regex::new(r"\b(shared_ptr|unique_ptr)<").unwrap();
+	static ref TYPE_NAME: Regex = Regex::new(r"[A-Za-z_]\w*(?:::[A-Za-z_]\w*)*").unwrap();
+}
+
+#[derive(Default, Clone)]
+pub struct CppHeader {
+	types: Vec<String>,
+	funcs: Vec<(String, String)>,    // (namespace, declaration)
+	members: Vec<(String, String)>,  // (class, declaration)
+	checks: Vec<String>,
+	layouts: Vec<(String, Vec<String>)>,  // (struct, static_asserts) from #[layout_check]
+	catches: Vec<String>,  // DIRECTCPP_CATCH(...) for Result<T, CppError> functions
+	instances: Vec<String>,  // explicit instantiations of #[template] functions
+	classes: Vec<String>,  // classes with members, a nested class can't be forward declared
+}
+
+fn is_known_type(name: &str) -> bool {
+	matches!(name, "const"|"void"|"bool"|"char"|"short"|"int"|"long"|"unsigned"|"signed"
+		|"wchar_t"|"char8_t"|"char16_t"|"char32_t"|"__int128"|"ptrdiff_t"
+		|"float"|"double"|"size_t"|"int8_t"|"int16_t"|"int32_t"|"int64_t"
+		|"uint8_t"|"uint16_t"|"uint32_t"|"uint64_t"|"std"|"shared_ptr"|"unique_ptr"
+		|"RustString"|"RustVec"|"RustFn"|"rust_refstr_t"|"ValuePromise")
+}
+
+/// `shared_ptr<T>` in tp_cpp is always the std one.
+fn qualify_std(tp: &str) -> String {
+	SMART_PTR.replace_all(tp, "std::$1<").to_string()
+}
+
+fn split_scope(name: &str) -> (&str, &str) {
+	match name.rfind("::") {
+		Some(pos) => (&name[..pos], &name[pos + 2..]),
+		None => ("", name),
+	}
+}
+
+/// `<float, 4>` of a #[template] function, empty otherwise.
+fn template_suffix(func: &SimpFunc) -> String {
+	if func.template_types.is_empty() {
+		String::new()
+	} else {
+		format!("<{}>", func.template_types.join(", "))
+	}
+}
+
+fn ret_type(ret: &SimpArg) -> String {
+	if ret.tp_cpp.is_empty() {
+		"void".to_string()
+	} else {
+		qualify_std(&ret.tp_cpp)
+	}
+}
+
+impl CppHeader {
+	fn add_types(&mut self, tp_cpp: &str) {
+		for m in TYPE_NAME.find_iter(tp_cpp) {
+			let name = m.as_str();
+			// an enum can't be forward declared without its underlying type.
+			if !is_known_type(name) && !name.starts_with("std::") && class_key(name) != "enum" && !self.types.iter().any(|x| x == name) {
+				self.types.push(name.to_string());
+			}
+		}
+	}
+
+	/// Record the C++ prototype of `func`. For async functions `func` already carries the
+	/// `ValuePromise<T>*` argument and a void return.
+	pub fn add_func(&mut self, func: &SimpFunc, is_cpp: bool) {
+		let mut args = Vec::new();
+		let mut arg_types = Vec::new();
+		for arg in &func.arg_list {
+			self.add_types(&arg.tp_cpp);
+			let tp_cpp = qualify_std(&arg.tp_cpp);
+			// `&str` and `&[u8]` expand to a (pointer, length) pair.
+			for (i, tp) in split_template_args(&tp_cpp).iter().enumerate() {
+				let name = if i == 0 { arg.name.clone() } else { format!("{}_len", arg.name) };
+				// the name of an array reference goes inside, `const float (&m)[16]`.
+				match tp.find("(&)").or(tp.find("(*)")) {
+					Some(pos) => args.push(format!("{}{}{}", &tp[..pos + 2], name, &tp[pos + 2..])),
+					None => args.push(format!("{} {}", tp, name)),
+				}
+				arg_types.push(tp.to_string());
+			}
+		}
+		self.add_types(&func.ret.tp_cpp);
+		let ret = ret_type(&func.ret);
+		let args = args.join(", ");
+		let arg_types = arg_types.join(", ");
+		let cst = if func.is_const { " const" } else { "" };
+		if !func.klsname.is_empty() && !self.classes.contains(&func.klsname) {
+			self.classes.push(func.klsname.clone());
+		}
+		if !func.template_types.is_empty() {
+			// the template itself can't be declared from one of its instances.
+			let name = format!("{}{}", func.qual_name(), template_suffix(func));
+			self.instances.push(format!("template {ret} {name}({arg_types}){cst}"));
+			self.checks.push(match (func.klsname.as_str(), func.is_static) {
+				("", _) | (_, true) => format!("ffi::force_ref<{ret} (*)({arg_types})>(&{name});"),
+				(kls, false) => format!("ffi::force_ref<{ret} ({kls}::*)({arg_types}){cst}>(&{name});"),
+			});
+		} else if func.klsname.is_empty() {
+			let extc = if is_cpp { "" } else { "extern \"C\" " };
+			let tag = match func.abi_tag.as_str() {
+				"" => String::new(),
+				tag => format!("[[gnu::abi_tag(\"{tag}\")]] "),
+			};
+			self.funcs.push((func.namespace(), format!("{tag}{extc}{ret} {}({args});", func.fn_name)));
+			self.checks.push(format!("ffi::force_ref<{ret} (*)({arg_types})>(&{});", func.qual_name()));
+		} else if func.is_dtor {
+			self.members.push((func.klsname.clone(), format!("{}();", func.fn_name)));
+		} else if func.is_ctor {
+			// a constructor has no address.
+			let kls = &func.klsname;
+			let targs: String = split_template_args(&arg_types).iter().filter(|x| !x.is_empty())
+				.map(|x| format!(", {x}")).collect();
+			self.members.push((kls.clone(), format!("{}({args});", func.fn_name)));
+			self.checks.push(format!("static_assert(std::is_constructible<{kls}{targs}>::value, \"no constructor {kls}({arg_types})\");"));
+		} else if func.is_static {
+			let kls = &func.klsname;
+			self.members.push((kls.clone(), format!("static {ret} {}({args});", func.fn_name)));
+			self.checks.push(format!("ffi::force_ref<{ret} (*)({arg_types})>(&{kls}::{});", func.fn_name));
+		} else {
+			let kls = &func.klsname;
+			self.members.push((kls.clone(), format!("{ret} {}({args}){cst};", func.fn_name)));
+			self.checks.push(format!("ffi::force_ref<{ret} ({kls}::*)({arg_types}){cst}>(&{kls}::{});", func.fn_name));
+		}
+	}
+
+	/// Record the `catch_call` instantiation of a `Result<T, CppError>` function, `tag` is
+	/// the name of the rust function.
+	pub fn add_catch(&mut self, func: &SimpFunc, tag: &str) {
+		let arg_types: Vec<String> = func.arg_list.iter()
+			.flat_map(|x| split_template_args(&qualify_std(&x.tp_cpp)))
+			.collect();
+		let arg_types = arg_types.join(", ");
+		let ret = ret_type(&func.ret);
+		// fully qualified, the tag struct could hide the function of the same name.
+		let targs = template_suffix(func);
+		let expr = if func.klsname.is_empty() {
+			format!("static_cast<{ret} (*)({arg_types})>(&::{}{targs})", func.qual_name())
+		} else if func.is_static {
+			format!("static_cast<{ret} (*)({arg_types})>(&::{}::{}{targs})", func.klsname, func.fn_name)
+		} else {
+			let kls = &func.klsname;
+			let cst = if func.is_const { " const" } else { "" };
+			format!("static_cast<{ret} ({kls}::*)({arg_types}){cst}>(&::{kls}::{}{targs})", func.fn_name)
+		};
+		self.catches.push(format!("DIRECTCPP_CATCH({tag}, {expr})"));
+	}
+
+	/// Record a registration such as `ffi::enable_class<MagicOut>()` needed by a return type.
+	pub fn add_check(&mut self, code: &str) {
+		self.checks.push(qualify_std(code));
+	}
+
+	/// Record the static_asserts of a `#[layout_check]` struct, replacing older ones.
+	pub fn add_layout(&mut self, name: &str, asserts: Vec<String>) {
+		match self.layouts.iter_mut().find(|x| x.0 == name) {
+			Some(x) => x.1 = asserts,
+			None => self.layouts.push((name.to_string(), asserts)),
+		}
+	}
+
+	pub fn append(&mut self, other: &CppHeader) {
+		for tp in &other.types {
+			if !self.types.contains(tp) {
+				self.types.push(tp.clone());
+			}
+		}
+		self.funcs.extend(other.funcs.iter().cloned());
+		self.members.extend(other.members.iter().cloned());
+		self.checks.extend(other.checks.iter().cloned());
+		self.catches.extend(other.catches.iter().cloned());
+		self.instances.extend(other.instances.iter().cloned());
+		for kls in &other.classes {
+			if !self.classes.contains(kls) {
+				self.classes.push(kls.clone());
+			}
+		}
+		for (name, asserts) in &other.layouts {
+			self.add_layout(name, asserts.clone());
+		}
+	}
+
+	/// Render the header. `guard` is the prefix of the checking macro, e.g. `PROVE_FFI`
+	/// gives `PROVE_FFI_ENABLE()`.
+	pub fn render(&self, guard: &str) -> String {
+		let mut outs = String::from("// Generated by directcpp #[bridge], do not edit.\n#pragma once\n");
+		outs += select_val(self.catches.is_empty(), "#include \"rust/rust-spt.h\"\n", "#include \"rust/rust-catch.h\"\n");
+		if !self.layouts.is_empty() {
+			outs += "#include <cstddef>\n";
+		}
+		if !self.types.is_empty() {
+			outs.push('\n');
+			for tp in &self.types {
+				let (ns, name) = split_scope(tp);
+				if self.classes.iter().any(|x| x == ns) {
+					continue;
+				}
+				outs += &match ns {
+					"" => format!("{} {};\n", class_key(name), name),
+					_ => format!("namespace {} {{ {} {}; }}\n", ns, class_key(name), name),
+				};
+			}
+		}
+		let mut cur_ns = None;
+		for (ns, decl) in &self.funcs {
+			if cur_ns != Some(ns) {
+				if cur_ns.is_some_and(|x| !x.is_empty()) {
+					outs += "}\n";
+				}
+				cur_ns = Some(ns);
+				outs.push('\n');
+				if !ns.is_empty() {
+					outs += &format!("namespace {ns} {{\n");
+				}
+			}
+			outs += &format!("{}{}\n", if ns.is_empty() { "" } else { "\t" }, decl);
+		}
+		if cur_ns.is_some_and(|x| !x.is_empty()) {
+			outs += "}\n";
+		}
+
+		let mut kls_list: Vec<&str> = Vec::new();
+		for (kls, _) in &self.members {
+			if !kls_list.contains(&kls.as_str()) {
+				kls_list.push(kls);
+			}
+		}
+		if !kls_list.is_empty() {
+			outs += "\n// member functions can only be declared by the class itself, they should read:\n";
+			for kls in kls_list {
+				outs += &format!("// {} {} {{\n", class_key(split_scope(kls).1), kls);
+				for (_, decl) in self.members.iter().filter(|x| x.0 == kls) {
+					outs += &format!("// \t{decl}\n");
+				}
+				outs += "// };\n";
+			}
+		}
+
+		// the checks need complete types, so they are a macro to be used from a .cpp file
+		// after all classes are defined. The function using it needs not be called.
+		if !self.checks.is_empty() {
+			outs += &format!("\n#define {guard}_ENABLE() do {{");
+			for chk in &self.checks {
+				outs += &format!(" \\\n\t{chk}");
+			}
+			outs += " \\\n} while (0)\n";
+		}
+		if !self.catches.is_empty() {
+			// at namespace scope, after the classes are defined: `XX_CATCH();`
+			outs += &format!("\n#define {guard}_CATCH() \\\n\t{}\n", self.catches.join("; \\\n\t"));
+		}
+		if !self.instances.is_empty() {
+			// at namespace scope, after the template definitions: `XX_INSTANTIATE();`
+			outs += &format!("\n#define {guard}_INSTANTIATE() \\\n\t{}\n", self.instances.join("; \\\n\t"));
+		}
+		if !self.layouts.is_empty() {
+			// used as `XX_LAYOUT_CHECK();`, so the last assert has no `;`.
+			let lines: Vec<&str> = self.layouts.iter().flat_map(|x| x.1.iter().map(|y| y.as_str())).collect();
+			outs += &format!("\n#define {guard}_LAYOUT_CHECK() \\\n\t{}\n", lines.join("; \\\n\t"));
+		}
+		outs
+	}
+}
+
+/// The macro prefix of a header file, `prove_ffi.h` gives `PROVE_FFI`.
+pub fn header_guard(path: &Path) -> String {
+	let stem = path.file_stem().map(|x| x.to_string_lossy().to_string()).unwrap_or_default();
+	let guard: String = stem.chars()
+		.map(|ch| if ch.is_ascii_alphanumeric() { ch.to_ascii_uppercase() } else { '_' })
+		.collect();
+	match guard.chars().next() {
+		Some(ch) if !ch.is_ascii_digit() => guard,
+		_ => format!("H_{guard}"),
+	}
+}
+
+/// Where `#[bridge(header)]` / `#[bridge(header = "path")]` writes to.
+/// A relative path is taken from the crate root.
+pub fn header_path(path: &str) -> Result<PathBuf, String> {
+	if path.is_empty() {
+		return match std::env::var("OUT_DIR") {
+			Ok(dir) => Ok(Path::new(&dir).join("directcpp_bridge.h")),
+			Err(_) => Err("OUT_DIR is not set (no build script?), use #[bridge(header = \"path\")] instead".to_string()),
+		};
+	}
+	let path = PathBuf::from(path);
+	if path.is_relative() {
+		if let Ok(dir) = std::env::var("CARGO_MANIFEST_DIR") {
+			return Ok(Path::new(&dir).join(path));
+		}
+	}
+	Ok(path)
+}
+
+/// Write the declarations of one block to `path`, which is left untouched if the content
+/// does not change. Returns whether the file was written.
+pub fn write_header(path: &Path, header: &CppHeader) -> Result<bool, String> {
+	let code = header.render(&header_guard(path));
+	if fs::read_to_string(path).map(|x| x == code).unwrap_or(false) {
+		return Ok(false);
+	}
+	if let Some(dir) = path.parent() {
+		let _ = fs::create_dir_all(dir);
+	}
+	fs::write(path, code).map_err(|e| format!("can't write header {}: {}", path.display(), e))?;
+	Ok(true)
+}
@@ -0,0 +1,181 @@
+// #[layout_check]: compute the C layout of a #[repr(C)] struct, assert it on the Rust side
+// and hand the same numbers to C++ as static_asserts.
+use std::collections::HashMap;
+use std::str::FromStr;
+use proc_macro2::TokenStream;
+use syn::{Fields, GenericArgument, ItemStruct, PathArguments, Type};
+use crate::header::CppHeader;
+use crate::target::Target;
+
+/// (size, align) of the structs that can be fields, by name.
+pub type Layouts = HashMap<String, (usize, usize)>;
+
+/// (size, align, offsets of the fields) of a struct.
+pub type StructLayout = (usize, usize, Vec<(String, usize)>);
+
+fn first_type_arg(args: &PathArguments) -> Option<&Type> {
+	match args {
+		PathArguments::AngleBracketed(a) => a.args.iter().find_map(|g| match g {
+			GenericArgument::Type(t) => Some(t),
+			_ => None,
+		}),
+		_ => None,
+	}
+}
+
+/// (size, align) of `ty`, None for a struct not in `known`: the macro can't see other items.
+fn type_layout(ty: &Type, known: &Layouts, target: Target) -> Result<Option<(usize, usize)>, String> {
+	let ptr = target.ptr_size();
+	match ty {
+		Type::Reference(r) => match r.elem.as_ref() {
+			Type::Slice(_) => Ok(Some((ptr * 2, ptr))),
+			Type::Path(p) if p.path.is_ident("str") => Ok(Some((ptr * 2, ptr))),
+			_ => Ok(Some((ptr, ptr))),
+		},
+		Type::Ptr(_) => Ok(Some((ptr, ptr))),
+		Type::Array(a) => {
+			let (size, align) = match type_layout(&a.elem, known, target)? {
+				Some(x) => x,
+				None => return Ok(None),
+			};
+			let cnt = match &a.len {
+				syn::Expr::Lit(syn::ExprLit{lit: syn::Lit::Int(n), ..}) => n.base10_parse::<usize>().map_err(|e| e.to_string())?,
+				other => return Err(format!("array length `{}` must be a literal", quote::quote!(#other))),
+			};
+			Ok(Some((size * cnt, align)))
+		},
+		Type::Path(p) => {
+			let seg = match p.path.segments.last() {
+				Some(s) => s,
+				None => return Err("empty type path".to_string()),
+			};
+			let name = seg.ident.to_string();
+			let sz = match name.as_str() {
+				"i8"|"u8"|"bool"|"c_char"|"c_schar"|"c_uchar" => 1,
+				"i16"|"u16"|"c_short"|"c_ushort" => 2,
+				"i32"|"u32"|"f32"|"char"|"c_int"|"c_uint"|"c_float" => 4,
+				"i64"|"u64"|"f64"|"c_longlong"|"c_ulonglong"|"c_double" => return Ok(Some((8, target.align8()))),
+				"c_long"|"c_ulong" => if target.windows { 4 } else { ptr },
+				"isize"|"usize"|"CPtr"|"UniquePtr"|"Box"|"NonNull" => ptr,
+				"String"|"Vec" => return Ok(Some((ptr * 3, ptr))),
+				"SharedPtr" => return Ok(Some((ptr * 2, ptr))),
+				"PhantomData" => return Ok(Some((0, 1))),
+				"Option" => {
+					// only the types with a niche keep their size in an Option.
+					let inner = first_type_arg(&seg.arguments);
+					return match inner {
+						Some(t @ Type::Reference(_)) => type_layout(t, known, target),
+						Some(t @ Type::Path(_)) if matches!(type_str(t).as_str(), "String"|"Vec"|"Box"|"NonNull") => type_layout(t, known, target),
+						_ => Err(format!("`{}` has no C layout", quote::quote!(#ty).to_string().replace(' ', ""))),
+					};
+				},
+				_ => return Ok(known.get(&name).copied()),
+			};
+			Ok(Some((sz, sz)))
+		},
+		other => Err(format!("`{}` has no C layout", quote::quote!(#other))),
+	}
+}
+
+fn type_str(ty: &Type) -> String {
+	match ty {
+		Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()).unwrap_or_default(),
+		other => quote::quote!(#other).to_string(),
+	}
+}
+
+fn is_repr_c(st: &ItemStruct) -> bool {
+	st.attrs.iter().any(|attr| {
+		attr.path().is_ident("repr") && attr.parse_args_with(
+			syn::punctuated::Punctuated::<syn::Ident, syn::Token![,]>::parse_terminated
+		).map(|x| x.iter().any(|id| id == "C")).unwrap_or(false)
+	})
+}
+
+/// The fields of a struct sharing its layout with C++.
+fn c_fields(st: &ItemStruct) -> Result<Vec<(String, &Type)>, String> {
+	let name = st.ident.to_string();
+	if !is_repr_c(st) {
+		return Err(format!("struct {} must be #[repr(C)] to have a layout shared with C++", name));
+	}
+	if !st.generics.params.is_empty() {
+		return Err(format!("struct {}: generic structs are not supported", name));
+	}
+	match &st.fields {
+		Fields::Named(f) => Ok(f.named.iter().map(|x| (x.ident.as_ref().unwrap().to_string(), &x.ty)).collect()),
+		_ => Err(format!("struct {}: only structs with named fields are supported", name)),
+	}
+}
+
+/// (size, align, field offsets) of `st`, None if a field is a struct not in `known`.
+pub fn struct_layout(st: &ItemStruct, known: &Layouts, target: Target) -> Result<Option<StructLayout>, String> {
+	let mut offset = 0usize;
+	let mut align = 1;
+	let mut offsets = Vec::new();
+	for (fname, ty) in c_fields(st)? {
+		let (fsize, falign) = match type_layout(ty, known, target).map_err(|e| format!("struct {} field {}: {}", st.ident, fname, e))? {
+			Some(x) => x,
+			None => return Ok(None),
+		};
+		offset = offset.next_multiple_of(falign);
+		align = align.max(falign);
+		offsets.push((fname, offset));
+		offset += fsize;
+	}
+	Ok(Some((offset.next_multiple_of(align), align, offsets)))
+}
+
+/// Add the static_asserts of a `#[layout_check]` struct to `header`, for directcpp-build.
+/// The struct fields must be primitives or structs in `known`.
+#[allow(dead_code)]
+pub fn add_layout_check(st: &ItemStruct, header: &mut CppHeader, known: &Layouts, target: Target) -> Result<(), String> {
+	let name = st.ident.to_string();
+	let (size, align, offsets) = match struct_layout(st, known, target)? {
+		Some(x) => x,
+		None => return Err(format!("struct {}: a field has an unknown layout, it must be a #[repr(C)] struct of the same sources", name)),
+	};
+	let mut asserts = vec![
+		format!("static_assert(sizeof({name}) == {size}, \"size of {name} differs from rust\")"),
+		format!("static_assert(alignof({name}) == {align}, \"align of {name} differs from rust\")"),
+	];
+	for (fname, off) in &offsets {
+		asserts.push(format!("static_assert(offsetof({name}, {fname}) == {off}, \"offset of {name}.{fname} differs from rust\")"));
+	}
+	header.add_layout(&name, asserts);
+	Ok(())
+}
+
+/// The asserts when some fields are other structs: their size and align come from Rust and
+/// the C layout is computed by the const block itself.
+fn rust_asserts_of(name: &str, fields: &[(String, &Type)]) -> String {
+	let mut outs = String::from("\tlet mut off = 0usize;\n\tlet mut align = 1usize;\n");
+	for (fname, ty) in fields {
+		let ty = quote::quote!(#ty);
+		outs += &format!("\toff = off.next_multiple_of(std::mem::align_of::<{ty}>());\n\
+			\tassert!(std::mem::offset_of!({name}, {fname}) == off, \"offset of {name}.{fname} differs from the C layout\");\n\
+			\toff += std::mem::size_of::<{ty}>();\n\
+			\tif std::mem::align_of::<{ty}>() > align {{ align = std::mem::align_of::<{ty}>(); }}\n");
+	}
+	outs += &format!("\tassert!(std::mem::align_of::<{name}>() == align, \"align of {name} differs from the C layout\");\n\
+		\tassert!(std::mem::size_of::<{name}>() == off.next_multiple_of(align), \"size of {name} differs from the C layout\");\n");
+	outs
+}
+
+/// Expand `#[layout_check]` on `input` for `target`.
+pub fn layout_check(input: TokenStream, target: Target) -> Result<TokenStream, String> {
+	let st: ItemStruct = syn::parse2(input.clone()).map_err(|e| format!("layout_check expects a struct: {}", e))?;
+	let name = st.ident.to_string();
+	let rust_asserts = match struct_layout(&st, &Layouts::new(), target)? {
+		Some((size, align, offsets)) => {
+			let mut outs = format!("\tassert!(std::mem::size_of::<{name}>() == {size}, \"size of {name} is not {size}\");\n\
+				\tassert!(std::mem::align_of::<{name}>() == {align}, \"align of {name} is not {align}\");\n");
+			for (fname, off) in &offsets {
+				outs += &format!("\tassert!(std::mem::offset_of!({name}, {fname}) == {off}, \"offset of {name}.{fname} is not {off}\");\n");
+			}
+			outs
+		},
+		None => rust_asserts_of(&name, &c_fields(&st)?),
+	};
+	let checks = TokenStream::from_str(&format!("const _: () = {{\n{rust_asserts}}};\n")).map_err(|e| e.to_string())?;
+	Ok(quote::quote!{ #input #checks })
+}
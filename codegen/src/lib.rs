@@ -0,0 +1,11 @@
+//! The code generation of directcpp, shared by the `#[bridge]` and `#[layout_check]` macros
+//! of directcpp-macro and the `build.rs` helpers of directcpp-build.
+mod mangle;
+mod demangle;
+mod parse;
+mod util;
+mod tests;
+pub mod buildcode;
+pub mod header;
+pub mod layout;
+pub mod target;
@@ -0,0 +1,1493 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::demangle::verify_link_name;
+use crate::target::Target;
+use crate::util::{move_obj, select_val};
+
+#[derive(Default, Debug, Copy, Clone)]
+pub enum ClassHint {
+	#[default]
+	NoHint,
+	WeakStruct,
+	WeakClass,
+	StrongStruct,
+	StrongClass,
+	Enum,
+}
+
+lazy_static::lazy_static! {
+	static ref FUNC_TYPE: regex::Regex = regex::Regex::new(r"^\s*([^()]*?)\s*\((.*)\)\s*$").unwrap();
+	static ref ARRAY_REF: regex::Regex = regex::Regex::new(r"^\s*(.*?)\s*\(\s*([&*])\s*\)\s*\[\s*(\d+)\s*\]\s*$").unwrap();
+	static ref TEMPLATE_VALUE: regex::Regex = regex::Regex::new(r"^\s*(?:\(\s*([^()]+?)\s*\))?\s*(-?\d+)\s*$").unwrap();
+	static ref CLASS_TYPE: regex::Regex = regex::Regex::new(r"^\s*((?:\w+\s*::\s*)*)(\w+)\s*(?:<(.*)>)?\s*$").unwrap();
+	static ref STD_STRING: regex::Regex = regex::Regex::new(r"\bstd\s*::\s*(string\b|__cxx11\b)").unwrap();
+	static ref CLASS_HINTS: Mutex<HashMap<String, ClassHint>> = Mutex::new(HashMap::new());
+}
+
+pub fn set_class_hint(name: &str, hint: ClassHint) -> Result<(), &'static str> {
+	let mut hints = CLASS_HINTS.lock().unwrap();
+	if let Some(&oldh) = hints.get(name) {
+		match (oldh, hint) {
+			(ClassHint::StrongClass, ClassHint::StrongClass) => return Ok(()),
+			(ClassHint::StrongStruct, ClassHint::StrongStruct) => return Ok(()),
+			(ClassHint::WeakClass, ClassHint::WeakClass) => return Ok(()),
+			(ClassHint::WeakStruct, ClassHint::WeakStruct) => return Ok(()),
+			(ClassHint::Enum, ClassHint::Enum) => return Ok(()),
+			(ClassHint::Enum, _) => return Err("class hint conflict"),
+			(ClassHint::StrongClass, _) => return Err("class hint conflict"),
+			(ClassHint::StrongStruct, _) => return Err("class hint conflict"),
+			(ClassHint::WeakClass, ClassHint::WeakStruct) => return Err("class hint conflict"),
+			(ClassHint::WeakStruct, ClassHint::WeakClass) => return Err("class hint conflict"),
+			_ => hints.insert(name.to_string(), hint),
+		};
+	} else {
+		hints.insert(name.to_string(), hint);
+	}
+	Ok(())
+}
+
+/// The C++ class-key the mangler assumes for `tp`, `class`, `struct` or `enum`.
+pub fn class_key(tp: &str) -> &'static str {
+	match MSVCMangler::class_flag(tp) {
+		'V' => "class",
+		'W' => "enum",
+		_ => "struct",
+	}
+}
+
+#[derive(Default, Clone)]
+pub struct SimpArg{
+	pub raw_str: String,
+	pub name: String,
+	pub tp: String,
+	pub tp_wrap: String,
+	pub tp_full: String,
+	pub tp_asc: String,  // as c arg in rust expression.
+	pub tp_cpp: String,  // the guessed type in cpp
+	pub is_const: bool,
+	pub is_primitive: bool,
+}
+
+/// A namespace or a class enclosing a function.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct Scope {
+	pub name: String,
+	pub is_class: bool,
+}
+
+#[derive(Default, Clone)]
+pub struct SimpFunc {
+	pub access: String,
+	pub scope: Vec<Scope>,  // the enclosing namespaces then classes, outermost first
+	pub klsname: String,  // the qualified class of a member, `scope` joined
+	pub fn_name: String,  // the unqualified C++ name
+	pub abi_tag: String,  // gcc `[[gnu::abi_tag]]`, `B5cxx11` in the Itanium name
+	pub rust_name: String,  // the rust identifier, from which the wrappers are named
+	pub template_types: Vec<String>,
+	pub arg_list: Vec<SimpArg>,
+	pub ret: SimpArg,
+	pub is_const: bool,  // const member function
+	pub is_async: bool,
+	pub is_result: bool,  // returns Result<T, CppError>, called through ffi::catch_call
+	pub is_method: bool,  // declared with `&self`/`&mut self`, also bridged as a trait method
+	pub is_static: bool,  // static member function of `klsname`, no `this`
+	pub is_ctor: bool,  // complete object constructor of `klsname`, `fn_name` is the class name
+	pub is_dtor: bool,  // complete object destructor of `klsname`
+	pub box_layout: Option<(usize, usize)>,  // (size, align) of the CppBox storage of a constructor
+	pub operator: String,  // the C++ operator of an #[operator] function, e.g. `==`
+}
+
+impl SimpFunc {
+	/// Place the function in namespace `ns` and, for members, class `kls`. Both may be nested,
+	/// `a::b`, `Outer::Inner`.
+	pub fn set_scope(&mut self, ns: &str, kls: &str) {
+		let parts = |x: &str, is_class: bool| x.split("::")
+			.filter(|x| !x.is_empty())
+			.map(|x| Scope{name: x.to_string(), is_class})
+			.collect::<Vec<_>>();
+		self.scope = parts(ns, false);
+		self.scope.extend(parts(kls, true));
+		self.klsname = select_val(kls.is_empty(), String::new(), self.scope_name());
+	}
+	/// `a::b::Kls`, the qualified name of the scope.
+	pub fn scope_name(&self) -> String {
+		self.scope.iter().map(|x| x.name.as_str()).collect::<Vec<_>>().join("::")
+	}
+	/// `a::b`, the enclosing namespaces.
+	pub fn namespace(&self) -> String {
+		self.scope.iter().filter(|x| !x.is_class).map(|x| x.name.as_str()).collect::<Vec<_>>().join("::")
+	}
+	/// The components of the qualified name, the function name last.
+	pub fn path(&self) -> Vec<&str> {
+		let mut v: Vec<&str> = self.scope.iter().map(|x| x.name.as_str()).collect();
+		v.push(&self.fn_name);
+		v
+	}
+	/// `a::b::Kls::fn`.
+	pub fn qual_name(&self) -> String {
+		self.path().join("::")
+	}
+	/// The Itanium abi tag. libstdc++ tags `std::string` with `cxx11`, g++ adds it to a
+	/// function returning one unless a parameter has it already. Templates encode the
+	/// return type instead.
+	pub fn itanium_abi_tag(&self, lib: StdLib) -> &str {
+		let reg = &*STD_STRING;
+		if self.abi_tag.is_empty() && lib == StdLib::Libstdcxx && self.template_types.is_empty()
+			&& reg.is_match(&self.ret.tp_cpp) && !self.arg_list.iter().any(|x| reg.is_match(&x.tp_cpp)) {
+			return "cxx11";
+		}
+		&self.abi_tag
+	}
+}
+
+/// (C++ operator, Itanium name, MSVC name) of the overloadable operators.
+/// `+ - * &` are binary here, their unary forms are in UNARY_OPERATORS.
+pub const OPERATORS: &[(&str, &str, &str)] = &[
+	("==", "eq", "?8"), ("!=", "ne", "?9"), ("<", "lt", "?M"), ("<=", "le", "?N"),
+	(">", "gt", "?O"), (">=", "ge", "?P"), ("[]", "ix", "?A"), ("()", "cl", "?R"),
+	("+", "pl", "?H"), ("-", "mi", "?G"), ("*", "ml", "?D"), ("/", "dv", "?K"),
+	("%", "rm", "?L"), ("&", "an", "?I"), ("|", "or", "?U"), ("^", "eo", "?T"),
+	("<<", "ls", "?6"), (">>", "rs", "?5"), ("=", "aS", "?4"), ("!", "nt", "?7"),
+	("~", "co", "?S"), ("+=", "pL", "?Y"), ("-=", "mI", "?Z"), ("*=", "mL", "?X"),
+	("/=", "dV", "?_0"), ("++", "pp", "?E"), ("--", "mm", "?F"),
+];
+pub const UNARY_OPERATORS: &[(&str, &str, &str)] = &[
+	("+", "ps", "?H"), ("-", "ng", "?G"), ("*", "de", "?D"), ("&", "ad", "?I"),
+];
+
+/// The operator of a function named like `operator==`, as its (Itanium, MSVC) names.
+/// `args` counts the parameters, `this` included.
+pub fn operator_names(fn_name: &str, args: usize) -> Option<(&'static str, &'static str)> {
+	let op = fn_name.rsplit("::").next()?.strip_prefix("operator")?;
+	let table = select_val(args == 1, UNARY_OPERATORS, OPERATORS);
+	table.iter().chain(OPERATORS.iter())
+		.find(|x| x.0 == op)
+		.map(|x| (x.1, x.2))
+}
+
+/// Split the argument list of a template type at top-level commas,
+/// `RustVec<int>,Foo` gives `["RustVec<int>", "Foo"]`.
+pub fn split_template_args(args: &str) -> Vec<String> {
+	let mut out = Vec::new();
+	let mut depth = 0;
+	let mut cur = String::new();
+	for ch in args.chars() {
+		match ch {
+			'<'|'(' => depth += 1,
+			'>'|')' => depth -= 1,
+			',' if depth == 0 => {
+				out.push(cur.trim().to_string());
+				cur.clear();
+				continue;
+			}
+			_ => {}
+		}
+		cur.push(ch);
+	}
+	out.push(cur.trim().to_string());
+	out
+}
+
+/// `void(int, bool)` gives `("void", ["int", "bool"])`, only for function types.
+fn split_func_type(tp: &str) -> Option<(String, Vec<String>)> {
+	let caps = FUNC_TYPE.captures(tp)?;
+	let args = split_template_args(&caps[2]).into_iter().filter(|x| !x.is_empty() && x != "void").collect();
+	Some((caps[1].to_string(), args))
+}
+
+/// `const T*` gives `('*', true, "T")`, `T* const*` gives `('*', true, "T*")`,
+/// `const T**` gives `('*', false, "const T*")`: the operator, the constness of its
+/// target and the target.
+fn split_indirection(tp: &str) -> Option<(char, bool, String)> {
+	let tp = tp.trim();
+	let op = tp.chars().last().filter(|x| *x == '&' || *x == '*')?;
+	let rest = tp[..tp.len() - 1].trim_end();
+	if let Some(x) = rest.strip_suffix("const").filter(|x| x.ends_with([' ', '*', '&'])) {
+		return Some((op, true, x.trim_end().to_string()));
+	}
+	Some(match rest.strip_prefix("const ") {
+		Some(x) if !x.trim_end().ends_with(['*', '&']) => (op, true, x.trim().to_string()),
+		_ => (op, false, rest.to_string()),
+	})
+}
+
+/// `const T&&` gives (true, `T`).
+fn split_rvalue_ref(tp: &str) -> Option<(bool, String)> {
+	let rest = tp.trim().strip_suffix("&&")?;
+	split_indirection(&format!("{}&", rest)).map(|(_, target_const, target)| (target_const, target))
+}
+
+/// `const T (&)[N]` gives (`&`, `const T`, N).
+fn split_array_ref(tp: &str) -> Option<(char, String, usize)> {
+	let caps = ARRAY_REF.captures(tp)?;
+	Some((caps[2].chars().next()?, caps[1].to_string(), caps[3].parse().ok()?))
+}
+
+/// `const T` gives (true, `T`).
+fn split_const(tp: &str) -> (bool, String) {
+	match tp.trim().strip_prefix("const ") {
+		Some(x) => (true, x.trim().to_string()),
+		None => (false, tp.trim().to_string()),
+	}
+}
+
+/// A non-type template argument, `4`, `(size_t)4` or `true`, as its C++ type and value.
+pub fn template_value(tp: &str) -> Option<(String, i64)> {
+	match tp.trim() {
+		"true" => return Some(("bool".to_string(), 1)),
+		"false" => return Some(("bool".to_string(), 0)),
+		_ => {}
+	}
+	let caps = TEMPLATE_VALUE.captures(tp)?;
+	let tp = caps.get(1).map_or("int", |x| x.as_str());
+	Some((tp.to_string(), caps[2].parse().ok()?))
+}
+
+/// standard library templates we refer to without the `std::` prefix.
+fn is_std_template(name: &str) -> bool {
+	matches!(name, "shared_ptr" | "unique_ptr" | "default_delete")
+}
+
+/// Template arguments as the C++ compiler sees them, with defaulted ones filled in.
+fn full_template_args(scope: &[String], name: &str, args: &str) -> Vec<String> {
+	let mut v = split_template_args(args);
+	if name == "unique_ptr" && v.len() == 1 {
+		let deleter = format!("std::default_delete<{}>", &v[0]);
+		v.push(deleter);
+	}
+	if scope == ["std"] && name == "vector" && v.len() == 1 {
+		let alloc = format!("std::allocator<{}>", &v[0]);
+		v.push(alloc);
+	}
+	v
+}
+
+/// `a::C<int>` gives (`["a"]`, `C`, `Some(["int"])`). Standard templates we refer to
+/// without `std::` get it back, and their defaulted arguments.
+fn split_class_type(tp: &str) -> Option<(Vec<String>, String, Option<Vec<String>>)> {
+	let caps = CLASS_TYPE.captures(tp)?;
+	let mut scope: Vec<String> = caps[1].split("::").map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect();
+	let name = caps[2].to_string();
+	if scope.is_empty() && is_std_template(&name) {
+		scope.push("std".to_string());
+	}
+	let args = caps.get(3).map(|x| full_template_args(&scope, &name, x.as_str()));
+	Some((scope, name, args))
+}
+
+/// The standard library a mangler names types of.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum StdLib {
+	Msvc,
+	#[default]
+	Libstdcxx,
+	Libcxx,  // macOS, or DIRECTCPP_STDLIB=libc++
+}
+
+/// The template arguments of `std::string`.
+pub const STRING_ARGS: [&str; 3] = ["char", "std::char_traits<char>", "std::allocator<char>"];
+/// The template arguments of `std::string_view`.
+pub const STRING_VIEW_ARGS: [&str; 2] = ["char", "std::char_traits<char>"];
+
+/// A `std::` type as the standard library declares it: `std::string` is a `basic_string`,
+/// in the inline namespace `std::__cxx11` of libstdc++, `std::string_view` is a
+/// `basic_string_view`. libc++ declares everything in `std::__1`. Other spellings are
+/// taken as written.
+pub fn std_type(scope: &mut Vec<String>, name: &mut String, args: &mut Option<Vec<String>>, lib: StdLib) {
+	if scope.first().map_or(true, |x| x != "std") {
+		return;
+	}
+	if scope.len() == 1 && name == "string" && args.is_none() {
+		*name = "basic_string".to_string();
+		*args = Some(STRING_ARGS.iter().map(|x| x.to_string()).collect());
+		if lib == StdLib::Libstdcxx {
+			scope.push("__cxx11".to_string());
+		}
+	}
+	if scope.len() == 1 && name == "string_view" && args.is_none() {
+		*name = "basic_string_view".to_string();
+		*args = Some(STRING_VIEW_ARGS.iter().map(|x| x.to_string()).collect());
+	}
+	if lib == StdLib::Libcxx && scope.get(1).map_or(true, |x| x != "__1") {
+		scope.insert(1, "__1".to_string());
+	}
+}
+
+#[derive(Default)]
+pub struct MSVCMangler{
+	sout: String,
+	is64: bool,
+	names: Vec<String>,  // name back references
+}
+
+impl MSVCMangler {
+	fn new(target: Target) -> Self {
+		Self{sout: String::new(), is64: target.is64, names: Vec::new()}
+	}
+	fn class_flag(tp: &str) -> char {
+		if let Some(&x) = CLASS_HINTS.lock().unwrap().get(tp) {
+			match x {
+				ClassHint::WeakStruct|ClassHint::StrongStruct => return 'U',
+				ClassHint::WeakClass|ClassHint::StrongClass => return 'V',
+				ClassHint::Enum => return 'W',
+				_ => {}
+			}
+		}
+		// check well-known types
+		match tp {
+			"shared_ptr"|"unique_ptr"|"basic_string"|"basic_string_view"|"allocator"|"vector" => 'V',
+			"RustVec"|"RustString"|"RustFn"|"default_delete"|"char_traits" => 'U',
+			_ => {
+				// panic!("class hint not set: {}", tp);
+				if tp.starts_with("C") || tp.ends_with("Class") {
+					'V'
+				} else {
+					'U'
+				}
+			}
+		}
+	}
+	fn map_tp(intp: &str, is64: bool) -> &'static str{
+		match intp {
+			"i32"|"int"|"int32_t" => "H",
+			"u32"|"uint32_t"|"unsigned int"|"unsigned" => "I",
+			"i64"|"int64_t"|"long long" => "_J",
+			"u64"|"uint64_t"|"unsigned long long" => "_K",
+			"long" => "J",
+			"unsigned long" => "K",
+			"bool" => "_N",
+			"char" => "D",
+			"wchar_t" => "_W",
+			"char16_t" => "_S",
+			"char32_t" => "_U",
+			"char8_t" => "_Q",
+			"size_t" => select_val(is64, "_K", "I"),
+			"ptrdiff_t" => select_val(is64, "_J", "H"),
+			"i8"|"int8_t"|"signed char" => "C",
+			"u8"|"uint8_t"|"unsigned char" => "E",
+			"i16"|"int16_t"|"short" => "F",
+			"u16"|"uint16_t"|"unsigned short" => "G",
+			"f32"|"float" => "M",
+			"f64"|"double" => "N",
+			"long double" => "O",
+			"std::nullptr_t"|"nullptr_t" => "$$T",
+			""|"()"|"void" => "X",
+			_ => "",
+		}
+	}
+	/// 1 to 10 are a single digit, others hex with `A`-`P` as digits, ended by `@`.
+	fn encode_number(n: usize) -> String {
+		if (1..=10).contains(&n) {
+			return (n - 1).to_string();
+		}
+		let mut s = String::new();
+		let mut n = n;
+		while n > 0 {
+			s.insert(0, (b'A' + (n % 16) as u8) as char);
+			n /= 16;
+		}
+		s.push('@');
+		select_val(s == "@", "A@".to_string(), s)
+	}
+	/// `is_const` is the constness of `tp` itself, a const pointer is `Q`.
+	fn add_type(&mut self, tp: &str, is_const: bool) -> Result<(), &'static str> {
+		if let Some((_, val)) = template_value(tp) {
+			// `$0` and a signed number, whatever the type is.
+			self.sout.push_str("$0");
+			if val < 0 {
+				self.sout.push('?');
+			}
+			self.sout.push_str(&Self::encode_number(val.unsigned_abs() as usize));
+			return Ok(());
+		}
+		if let Some((ret, args)) = split_func_type(tp) {
+			// `$$A6` function type, cdecl.
+			self.sout.push_str("$$A6A");
+			if Self::is_class_value(&ret, self.is64) {
+				self.sout.push_str("?A");
+			}
+			self.add_type(&ret, false)?;
+			return self.add_arg_types(&args);
+		}
+		if let Some((op, elem, n)) = split_array_ref(tp) {
+			// `Y` + the number of dimensions + the dimensions, the element is `$$CB` if const.
+			let (elem_const, elem) = split_const(&elem);
+			self.sout.push(select_val(op == '&', 'A', 'P'));
+			if self.is64 {
+				self.sout.push('E');
+			}
+			self.sout.push_str("AY");
+			self.sout.push_str(&Self::encode_number(1));
+			self.sout.push_str(&Self::encode_number(n));
+			if elem_const {
+				self.sout.push_str("$$CB");
+			}
+			return self.add_type(&elem, elem_const);
+		}
+		if let Some((target_const, target)) = split_rvalue_ref(tp) {
+			self.sout.push_str("$$Q");
+			if self.is64 {
+				self.sout.push('E');
+			}
+			self.sout.push(select_val(target_const, 'B', 'A'));
+			return self.add_type(&target, target_const);
+		}
+		if let Some((op, target_const, target)) = split_indirection(tp) {
+			self.sout.push(match (op, is_const) {
+				('&', _) => 'A',
+				(_, false) => 'P',
+				(_, true) => 'Q',
+			});
+			if self.is64 {
+				self.sout.push('E');
+			}
+			self.sout.push(select_val(target_const, 'B', 'A'));
+			return self.add_type(&target, target_const);
+		}
+
+		if tp.ends_with("__int128") {
+			return Err("MSVC has no 128-bit integer type");
+		}
+		let tpstr = Self::map_tp(tp, self.is64);
+		if ! tpstr.is_empty() {
+			self.sout.push_str(tpstr);
+			return Ok(());
+		}
+		// treat as UDT, enums are `W4`.
+		let (mut scope, mut name, mut args) = split_class_type(tp).ok_or("unsupported type")?;
+		std_type(&mut scope, &mut name, &mut args, StdLib::Msvc);
+		let flag = match args {
+			Some(_) => Self::class_flag(&name),
+			None => Self::class_flag(tp.trim()),
+		};
+		match flag {
+			'W' => self.sout.push_str("W4"),
+			flag => self.sout.push(flag),
+		}
+		match &args {
+			Some(args) => {
+				let frag = self.template_name(&name, args)?;
+				self.add_source_name(&frag);
+			}
+			None => self.add_source_name(&name),
+		}
+		for part in scope.iter().rev() {
+			self.add_source_name(part);
+		}
+		self.sout.push('@');
+		return Ok(());
+	}
+	/// emit a name fragment, or a back reference if it appeared before.
+	fn add_source_name(&mut self, name: &str) {
+		if let Some(idx) = self.names.iter().position(|x| x == name) {
+			self.sout.push_str(&idx.to_string());
+		} else {
+			self.sout.push_str(name);
+			self.sout.push('@');
+			if self.names.len() < 10 {
+				self.names.push(name.to_string());
+			}
+		}
+	}
+	/// `?$name@args`, mangled with its own back reference context.
+	fn template_name(&mut self, name: &str, template_types: &[String])
+		-> Result<String, &'static str>
+	{
+		let old_sout = move_obj(&mut self.sout);
+		let old_names = move_obj(&mut self.names);
+		self.sout.push_str("?$");
+		self.add_source_name(name);
+		let res = template_types.iter().try_for_each(|tp| self.add_type(tp, false));
+		let frag = move_obj(&mut self.sout);
+		self.sout = old_sout;
+		self.names = old_names;
+		res.map(|_| frag)
+	}
+	pub fn add_name(self: &mut Self, name:&str, template_types: Option<&Vec<String>>)
+		-> Result<(), &'static str>
+	{
+		if let Some(template_types) = template_types {
+			if template_types.len() > 0 {
+				// function templates never take part in name back referencing.
+				let frag = self.template_name(name, template_types)?;
+				self.sout.push_str(&frag);
+				self.sout.push('@');
+				return Ok(());
+			}
+		}
+		self.add_source_name(name);
+		Ok(())
+	}
+	/// only class types returned by value carry the `?A` storage class.
+	fn is_class_value(tp: &str, is64: bool) -> bool {
+		let tp = tp.trim();
+		!(tp.ends_with('*') || tp.ends_with('&') || !Self::map_tp(tp, is64).is_empty())
+	}
+	pub fn mangle(self: &mut Self, func: &SimpFunc) -> Result<String, &'static str> {
+		self.names.clear();
+		self.sout.push('?');
+		let is_special = func.is_ctor || func.is_dtor;
+		let op = operator_names(&func.fn_name, func.arg_list.len() + select_val(func.klsname.is_empty(), 0, 1));
+		let mut parts = func.path();
+		if is_special || op.is_some() {
+			// `?0`, `?1` and the operator codes take the place of the name.
+			parts.pop();
+			self.sout.push_str(match op {
+				Some((_, code)) => code,
+				None => select_val(func.is_ctor, "?0", "?1"),
+			});
+			if is_special && parts.is_empty() {
+				return Err("constructor without a class");
+			}
+			parts.reverse();
+			for part in parts {
+				self.add_name(part, None)?;
+			}
+		} else {
+			parts.reverse();
+			self.add_name(parts[0], Some(&func.template_types))?;
+			for part in parts.iter().skip(1) {
+				self.add_name(part, None)?;
+			}
+		}
+		self.sout.push('@'); // end of name
+
+		if func.is_static {
+			self.sout.push('S'); // public static
+		} else if ! func.klsname.is_empty() {
+			self.sout.push('Q'); // public
+			if self.is64 { // ptr64
+				self.sout.push('E');
+			}
+			if func.is_const {
+				self.sout.push('B');
+			} else {
+				self.sout.push('A');
+			}
+		} else {
+			self.sout.push('Y'); // global
+		}
+
+		self.sout.push('A');  // cdecl
+		if is_special {
+			self.sout.push('@');  // no return type
+		} else if Self::is_class_value(&func.ret.tp_cpp, self.is64) {
+			self.sout.push_str("?A");  // return storage class: empty. non-cv
+		}
+		if !is_special {
+			self.add_type(&func.ret.tp_cpp, false)?;
+		}
+
+		let args: Vec<String> = func.arg_list.iter().map(|x| x.tp_cpp.clone()).collect();
+		self.add_arg_types(&args)?;
+		Ok(move_obj(&mut self.sout))
+	}
+	/// the parameter list and the closing `Z` of a function or function type.
+	fn add_arg_types(&mut self, args: &[String]) -> Result<(), &'static str> {
+		if args.is_empty() {
+			self.sout.push('X');
+		} else {
+			let mut cache = HashMap::new();
+			let mut cache_idx = 0;
+			for arg in args {
+				// back references are by type, the spelling may differ after name back references.
+				let key = arg.replace(' ', "");
+				if let Some(&idx) = cache.get(&key) {
+					self.sout.push_str(&format!("{}", idx));
+					continue;
+				}
+				let old_sz = self.sout.len();
+				self.add_type(arg, false)?;
+				if self.sout.len() - old_sz > 1 && cache_idx < 10 {
+					cache.insert(key, cache_idx);
+					cache_idx += 1;
+				}
+			}
+			self.sout.push('@');
+		}
+		self.sout.push('Z');
+		Ok(())
+	}
+}
+
+#[derive(Default)]
+pub struct GccMangler {
+	sout: String,
+	is64: bool,
+	macos: bool,
+	stdlib: StdLib,
+	subs: HashMap<String, usize>,
+	subs_cnt: usize,
+	tparams: Vec<String>,  // template arguments of the function, `T_`, `T0_`... in its signature
+}
+
+impl GccMangler{
+	pub fn new(target: Target)->Self{
+		Self {
+			sout:String::new(),
+			is64: target.is64,
+			macos: target.macos,
+			stdlib: target.stdlib,
+			subs: HashMap::new(),
+			subs_cnt: 0,
+			tparams: Vec::new(),
+		}
+	}
+	fn format_radix(mut x: u128, radix: u32) -> String {
+		let mut result = vec![];
+
+		loop {
+			let m = x % radix as u128;
+			x = x / radix as u128;
+
+			// will panic if you use a bad radix (< 2 or > 36).
+			result.push(std::char::from_digit(m as u32, radix).unwrap());
+			if x == 0 {
+				break;
+			}
+		}
+		result.into_iter().rev().collect()
+	}
+
+	fn subst_ref(val: usize) -> String {
+		if val == 0 {
+			"S_".to_string()
+		} else {
+			format!("S{}_", Self::format_radix((val-1) as u128, 36).to_uppercase())
+		}
+	}
+
+	fn gen_packed(&mut self, full: &str, old_packed: String) -> String {
+		match self.subs.get(full) {
+			Some(&val) => Self::subst_ref(val),
+			None => {
+				self.subs.insert(full.to_string(), self.subs_cnt);
+				self.subs_cnt += 1;
+				old_packed
+			}
+		}
+	}
+
+	fn add_type0(&mut self, tp: &str) -> (String,String) {
+		if let Some((vtp, val)) = template_value(tp) {
+			// literals are not substitution candidates.
+			let (code, _) = self.add_type0(&vtp);
+			let outs = format!("L{}{}{}E", code, select_val(val < 0, "n", ""), val.unsigned_abs());
+			return (outs.clone(), outs);
+		}
+		let key = tp.replace(' ', "");
+		if let Some(idx) = self.tparams.iter().position(|x| x.replace(' ', "") == key) {
+			// the signature is the one of the template, spelled with its parameters.
+			let full = match idx {
+				0 => "T_".to_string(),
+				_ => format!("T{}_", Self::format_radix((idx - 1) as u128, 36).to_uppercase()),
+			};
+			let packed = self.gen_packed(&full, full.clone());
+			return (full, packed);
+		}
+		if let Some((ret, args)) = split_func_type(tp) {
+			let (f1, p1) = self.add_type0(&ret);
+			let mut full = format!("F{f1}");
+			let mut packed = format!("F{p1}");
+			if args.is_empty() {
+				full.push('v'); packed.push('v');
+			}
+			for arg in &args {
+				let (f2, p2) = self.add_type0(arg);
+				full.push_str(&f2); packed.push_str(&p2);
+			}
+			full.push('E'); packed.push('E');
+			packed = self.gen_packed(&full, packed);
+			return (full, packed);
+		}
+		if let Some((op, elem, n)) = split_array_ref(tp) {
+			let (elem_const, elem) = split_const(&elem);
+			let (mut full, mut packed) = self.add_type0(&elem);
+			let mut tags = vec![select_val(op == '&', "R", "P").to_string(), format!("A{}_", n)];
+			if elem_const {
+				tags.push("K".to_string());
+			}
+			while let Some(tag) = tags.pop() {
+				full = tag.clone() + &full;
+				packed = self.gen_packed(&full, tag + &packed);
+			}
+			return (full, packed);
+		}
+		if let Some((target_const, target)) = split_rvalue_ref(tp) {
+			let (mut full, mut packed) = self.add_type0(&target);
+			if target_const {
+				full = format!("K{full}");
+				packed = self.gen_packed(&full, format!("K{packed}"));
+			}
+			full = format!("O{full}");
+			packed = self.gen_packed(&full, format!("O{packed}"));
+			return (full, packed);
+		}
+		if let Some((op, target_const, target)) = split_indirection(tp) {
+			let mut vouts = Vec::new();
+			vouts.push(select_val(op == '&', "R", "P").to_string());
+			if target_const {
+				vouts.push("K".to_string());
+			}
+			let (mut full, mut packed) = self.add_type0(&target);
+			while ! vouts.is_empty() {
+				let tag = vouts.pop().unwrap();
+				full = tag.clone() + &full;
+				packed = self.gen_packed(&full, tag + &packed);
+			}
+			return (full, packed);
+		}
+		{
+			let mut outs = String::new();
+			match tp {
+				"i32"|"int"|"int32_t" => outs.push('i'),
+				"u32"|"uint32_t"|"unsigned int"|"unsigned" => outs.push('j'),
+				"i64"|"int64_t" => outs.push(select_val(self.is64 && !self.macos, 'l', 'x')),
+				"u64"|"uint64_t" => outs.push(select_val(self.is64 && !self.macos, 'm', 'y')),
+				"long" => outs.push('l'),
+				"unsigned long" => outs.push('m'),
+				"long long" => outs.push('x'),
+				"unsigned long long" => outs.push('y'),
+				"bool" => outs.push('b'),
+				"char" => outs.push('c'),
+				"wchar_t" => outs.push('w'),
+				"char16_t" => outs.push_str("Ds"),
+				"char32_t" => outs.push_str("Di"),
+				"char8_t" => outs.push_str("Du"),
+				"size_t" => outs.push(select_val(self.is64, 'm', 'j')),
+				"ptrdiff_t" => outs.push(select_val(self.is64, 'l', 'i')),
+				"__int128" => outs.push('n'),
+				"unsigned __int128" => outs.push('o'),
+				"i8"|"int8_t"|"signed char" => outs.push('a'),
+				"u8"|"uint8_t"|"unsigned char" => outs.push('h'),
+				"i16"|"int16_t"|"short" => outs.push('s'),
+				"u16"|"uint16_t"|"unsigned short" => outs.push('t'),
+				"f32"|"float" => outs.push('f'),
+				"f64"|"double" => outs.push('d'),
+				"long double" => outs.push('e'),
+				"std::nullptr_t"|"nullptr_t" => outs.push_str("Dn"),
+				""|"()"|"void" => outs.push('v'),
+				_ => return self.add_class_type(tp),
+			}
+			(outs.clone(), outs)
+		}
+	}
+	/// A class or enum, `a::C<int>` gives `N1a1CIiEE`. Every prefix of the name is a
+	/// substitution candidate, except `St` and the `Sa`, `Sb`, `Ss` abbreviations.
+	fn add_class_type(&mut self, tp: &str) -> (String, String) {
+		let (mut scope, mut name, mut args) = match split_class_type(tp) {
+			Some(x) => x,
+			None => return (tp.to_string(), tp.to_string()),  // fails verify_link_name
+		};
+		std_type(&mut scope, &mut name, &mut args, self.stdlib);
+		let in_std = scope.len() == 1 && scope[0] == "std";
+		let is_string = args.as_ref().is_some_and(|x| x.iter().map(|x| x.replace(' ', "")).eq(STRING_ARGS.iter().map(|x| x.replace(' ', ""))));
+		if in_std && name == "basic_string" && is_string {
+			return ("Ss".to_string(), "Ss".to_string());
+		}
+		// (segment, is a substitution candidate)
+		let segs: Vec<(String, bool)> = match name.as_str() {
+			"allocator" if in_std => vec![("Sa".to_string(), false)],
+			"basic_string" if in_std => vec![("Sb".to_string(), false)],
+			_ => scope.iter().enumerate()
+				.map(|(idx, x)| match (idx, x.as_str()) {
+					(0, "std") => ("St".to_string(), false),
+					_ => (format!("{}{}", x.len(), x), true),
+				})
+				.chain(std::iter::once((format!("{}{}", name.len(), name), true)))
+				.collect(),
+		};
+		let nested = segs.len() > 1 && !(segs.len() == 2 && segs[0].0 == "St");
+		let mut keys: Vec<String> = Vec::new();
+		for (seg, _) in &segs {
+			keys.push(keys.last().map_or(String::new(), |k| k.clone()) + seg);
+		}
+		// the longest known prefix, for a template that includes its name.
+		let last = segs.len() - 1;
+		let mut packed = String::new();
+		let mut start = 0;
+		for idx in (0..select_val(args.is_some(), last + 1, last)).rev() {
+			if let Some(&val) = self.subs.get(&keys[idx]).filter(|_| segs[idx].1) {
+				packed = Self::subst_ref(val);
+				start = idx + 1;
+				break;
+			}
+		}
+		for idx in start..segs.len() {
+			packed.push_str(&segs[idx].0);
+			if segs[idx].1 && (idx < last || args.is_some()) {
+				self.gen_packed(&keys[idx], String::new());
+			}
+		}
+		let mut full = keys[last].clone();
+		if let Some(args) = &args {
+			full.push('I'); packed.push('I');
+			for arg in args {
+				let (f2, p2) = self.add_type0(arg);
+				full.push_str(&f2); packed.push_str(&p2);
+			}
+			full.push('E'); packed.push('E');
+		}
+		let wrap = |x: String| select_val(nested, format!("N{x}E"), x.clone());
+		match self.subs.get(&full) {
+			Some(&val) => (wrap(full), Self::subst_ref(val)),
+			None => {
+				self.gen_packed(&full, String::new());
+				(wrap(full), wrap(packed))
+			}
+		}
+	}
+	fn add_type(self: &mut Self, tp: &str) {
+		let (_, packed) = self.add_type0(tp);
+		self.sout.push_str(packed.as_str());
+	}
+
+	/// `special` replaces the encoding of the last component, e.g. `C1` for a constructor.
+	/// `abi_tag` follows the last name, `B5cxx11`.
+	fn add_source_name_n(&mut self, v: &[&str], tt: Option<&Vec<String>>, is_const: bool, special: Option<&str>, abi_tag: &str)
+		-> Result<bool,&'static str>
+	{
+		if v.iter().any(|x| x.is_empty()) {
+			return Err("empty name");
+		}
+		let has_temp = tt.is_some_and(|tt| !tt.is_empty());
+		let is_std = v[0] == "std" && v.len() > 1;
+		let need_e = v.len() > select_val(is_std, 2, 1);
+
+		// every prefix of the name is a substitution candidate, keyed the same way as a type
+		// with that name. the function name itself is not, unless it's a template.
+		let mut segs = Vec::new();
+		let mut keys = Vec::new();
+		for (idx, x) in v.iter().enumerate() {
+			let seg = match special {
+				Some(sp) if idx == v.len() - 1 => sp.to_string(),
+				_ if idx == 0 && is_std => "St".to_string(),
+				_ if idx == v.len() - 1 && !abi_tag.is_empty() => format!("{}{}B{}{}", x.len(), x, abi_tag.len(), abi_tag),
+				_ => format!("{}{}", x.len(), x),
+			};
+			let key = keys.last().map_or(String::new(), |k: &String| k.clone()) + &seg;
+			segs.push(seg);
+			keys.push(key);
+		}
+		let last = v.len() - 1;
+		if need_e {
+			self.sout.push('N');
+			if is_const {
+				self.sout.push('K');
+			}
+		}
+		let mut start = 0;
+		for idx in (0..last).rev() {
+			if let Some(&val) = self.subs.get(&keys[idx]) {
+				self.sout.push_str(&Self::subst_ref(val));
+				start = idx + 1;
+				break;
+			}
+		}
+		for idx in start..v.len() {
+			self.sout.push_str(&segs[idx]);
+			if (idx < last || has_temp) && !(idx == 0 && is_std) {
+				self.gen_packed(&keys[idx], String::new());
+			}
+		}
+		if let Some(tt) = tt {
+			if tt.len() > 0 {
+				self.sout.push('I');
+				for x in tt {
+					self.add_type(x);
+				}
+				self.sout.push('E');
+			}
+		}
+		if need_e {
+			self.sout.push('E');
+		}
+		Ok(has_temp)
+	}
+
+	pub fn mangle(self: &mut Self, func: &SimpFunc) -> Result<String, &'static str> {
+		self.subs.clear();
+		self.subs_cnt = 0;
+		self.tparams.clear();
+		self.sout.push_str("_Z");
+		let op = operator_names(&func.fn_name, func.arg_list.len() + select_val(func.klsname.is_empty(), 0, 1));
+		let special = match (func.is_ctor, func.is_dtor) {
+			(true, _) => Some("C1"),
+			(_, true) => Some("D1"),
+			_ => op.map(|x| x.0),
+		};
+		let abi_tag = func.itanium_abi_tag(self.stdlib);
+		let show_ret = self.add_source_name_n(&func.path(), Some(&func.template_types), func.is_const, special, abi_tag)?;
+		self.tparams = func.template_types.clone();
+		if show_ret {
+			self.add_type(&func.ret.tp_cpp);
+		}
+		if func.arg_list.is_empty() {
+			self.sout.push('v');
+		} else {
+			for arg in &func.arg_list {
+				self.add_type(&arg.tp_cpp);
+			}
+		}
+		Ok(move_obj(&mut self.sout))
+	}
+}
+
+/// The name x86_64 MSVC gives `func`.
+pub fn mangle_msvc(func: &SimpFunc) -> Result<String, &'static str> {
+	MSVCMangler::new(Target::new("x86_64", "windows")).mangle(func)
+}
+
+/// The name x86_64 linux gives `func`.
+pub fn mangle_gcc(func: &SimpFunc) -> Result<String, &'static str> {
+	GccMangler::new(Target::new("x86_64", "linux")).mangle(func)
+}
+
+pub fn mangle(func:&SimpFunc, target: Target) -> Result<String, String> {
+	// arguments may not 1:1
+	let mut func2 = func.clone();
+	func2.arg_list.clear();
+	for args in  &func.arg_list {
+		for seg in split_template_args(&args.tp_cpp) {
+			let mut arg = args.clone();
+			arg.tp_cpp = seg;
+			func2.arg_list.push(arg);
+		}
+	}
+	let name = if target.windows {
+		MSVCMangler::new(target).mangle(&func2)?
+	} else {
+		GccMangler::new(target).mangle(&func2)?
+	};
+	// catch a bad name now, rather than as an unreadable undefined symbol at link time.
+	verify_link_name(&func2, &name, target)?;
+	Ok(name)
+}
+
+fn helper_dtor_name(helper: &str, tp: &str, target: Target) -> Result<String, String> {
+	let mut func = SimpFunc::default();
+	func.set_scope("ffi", "");
+	func.fn_name = helper.to_string();
+	func.template_types.push(tp.to_string());
+	func.ret.is_primitive = true;
+	let mut arg = SimpArg::default();
+	arg.tp_cpp = "void*".to_string();
+	func.arg_list.push(arg);
+	mangle(&func, target)
+}
+
+pub fn dtor_name(tp: &str, target: Target) -> Result<String, String> {
+	helper_dtor_name("man_dtor", tp, target)
+}
+
+pub fn sp_dtor_name(tp: &str, target: Target) -> Result<String, String> {
+	helper_dtor_name("man_dtor_sp", tp, target)
+}
+
+/// The complete object destructor `kls::~kls()`.
+pub fn class_dtor_name(kls: &str, target: Target) -> Result<String, String> {
+	let mut func = SimpFunc::default();
+	func.set_scope("", kls);
+	func.fn_name = format!("~{}", kls.rsplit("::").next().unwrap_or_default());
+	func.is_dtor = true;
+	func.ret.is_primitive = true;
+	mangle(&func, target)
+}
+
+// I don't know why these are warning as unused. they're used in other files.
+#[allow(dead_code)]
+fn kill_warnings() {
+	let _ = mangle_gcc;
+	let _ = mangle_msvc;
+	let _ = (ClassHint::StrongClass, ClassHint::StrongStruct,
+	         ClassHint::WeakClass, ClassHint::WeakStruct, ClassHint::NoHint, ClassHint::Enum);
+	let _ = set_class_hint;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn add_arg(f: &mut SimpFunc, tp: &str, name: &str) {
+		let mut arg = SimpArg::default();
+		arg.tp_cpp = tp.to_string();
+		arg.name = name.to_string();
+		arg.is_primitive = match tp {
+			"int8_t"|"uint8_t" => true,
+			"int16_t"|"uint16_t" => true,
+			"int"|"int32_t"|"uint32_t" => true,
+			"int64_t"|"uint64_t" => true,
+			"bool" => true,
+			"char" => true,
+			"size_t" => true,
+			"float" => true,
+			"double" => true,
+			"void" => true,
+			_ => false,
+		};
+		f.arg_list.push(arg);
+	}
+	fn set_ret(f: &mut SimpFunc, tp:&str) {
+		let mut f2 = SimpFunc::default();
+		add_arg(&mut f2, tp, "ret");
+		f.ret = f2.arg_list[0].clone();
+	}
+
+	fn should_be(func: &SimpFunc, res: &str, is_gcc:bool) {
+		let res2 = if is_gcc {
+			mangle_gcc(func)
+		} else {
+			mangle_msvc(func)
+		};
+		assert!(res2.is_ok());
+		assert_eq!(res2.unwrap().as_str(), res);
+	}
+
+	#[test]
+	fn test_mangle() {
+		let mut func = SimpFunc::default();
+		func.fn_name = "foo".to_string();
+		let res = mangle_gcc(&func).unwrap();
+		assert_eq!(res, "_Z3foov");
+
+		{
+			let tp = "Foo";
+			let mut func = SimpFunc::default();
+			func.set_scope("ffi", "");
+			func.fn_name = "man_dtor".to_string();
+			func.template_types.push(tp.to_string());
+			func.ret.is_primitive = true;
+			let mut arg = SimpArg::default();
+			arg.tp_cpp = "void*".to_string();
+			func.arg_list.push(arg);
+			assert_eq!(mangle_msvc(&func), Ok("??$man_dtor@UFoo@@@ffi@@YAXPEAX@Z".to_string()));
+			assert_eq!(mangle_gcc(&func), Ok("_ZN3ffi8man_dtorI3FooEEvPv".to_string()));
+		}
+		{
+			let mut func = SimpFunc::default();
+			func.fn_name = "cpp_ptr".to_string();
+			set_ret(&mut func, "void");
+			add_arg(&mut func, "int", "a");
+			add_arg(&mut func, "const char*", "b");
+			add_arg(&mut func, "size_t", "c");
+			add_arg(&mut func, "const char*", "d");
+			add_arg(&mut func, "const uint8_t*", "e");
+			add_arg(&mut func, "size_t", "f");
+			assert_eq!(mangle_gcc(&func), Ok("_Z7cpp_ptriPKcmS0_PKhm".to_string()));
+		}
+		{
+			let mut func = SimpFunc::default();
+			func.fn_name = "slow_tostr".to_string();
+			set_ret(&mut func, "void");
+			add_arg(&mut func, "ValuePromise<RustString>*", "val");
+			add_arg(&mut func, "int", "val");
+			assert_eq!(mangle_gcc(&func), Ok("_Z10slow_tostrP12ValuePromiseI10RustStringEi".to_string()));
+		}
+		{
+			let mut func = SimpFunc::default();
+			func.fn_name = "foo".to_string();
+			set_ret(&mut func, "void");
+			add_arg(&mut func, "const RustString &", "a");
+			add_arg(&mut func, "const RustString &", "b");
+			should_be(&func, "_Z3fooRK10RustStringS1_", true);
+		}
+		{
+			let mut func = SimpFunc::default();
+			func.fn_name = "foo".to_string();
+			set_ret(&mut func, "void");
+			add_arg(&mut func, "tpl<RustString>*", "a");
+			add_arg(&mut func, "const RustString &", "b");
+			should_be(&func, "_Z3fooP3tplI10RustStringERKS0_", true);
+		}
+	}
+
+	#[test]
+	fn test_mangle2(){
+		let mut func = SimpFunc::default();
+		func.fn_name = "foo".to_string();
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "AA*", "a");
+		add_arg(&mut func, "BB*", "b");
+		add_arg(&mut func, "AA", "c");
+		add_arg(&mut func, "AA", "d");
+		should_be(&func, "_Z3fooP2AAP2BBS_S_", true);
+
+		// builtins spelled by #[cpp_type].
+		let mut func = SimpFunc{fn_name: "lg".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		for tp in ["long", "unsigned long", "long long", "wchar_t", "char32_t", "unsigned", "short", "unsigned char", "signed char"] {
+			add_arg(&mut func, tp, "a");
+		}
+		should_be(&func, "_Z2lglmxwDijsha", true);
+		should_be(&func, "?lg@@YAXJK_J_W_UIFEC@Z", false);
+		let _ = set_class_hint("ns::Color", ClassHint::Enum);
+		let mut func = SimpFunc{fn_name: "next_char".to_string(), ..Default::default()};
+		set_ret(&mut func, "char16_t");
+		add_arg(&mut func, "const ns::Color&", "c");
+		should_be(&func, "_Z9next_charRKN2ns5ColorE", true);
+		should_be(&func, "?next_char@@YA_SAEBW4Color@ns@@@Z", false);
+
+		let mut func = SimpFunc{fn_name: "nul".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "std::nullptr_t", "a");
+		add_arg(&mut func, "long double", "b");
+		should_be(&func, "_Z3nulDne", true);
+		should_be(&func, "?nul@@YAX$$TO@Z", false);
+		let mut func = SimpFunc{fn_name: "wide".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "__int128", "a");
+		add_arg(&mut func, "unsigned __int128", "b");
+		should_be(&func, "_Z4wideno", true);
+		assert!(mangle_msvc(&func).is_err());
+
+		// the const of `T* const*` is on the inner pointer, of `const T**` on T.
+		let mut func = SimpFunc{fn_name: "read_table".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "double* const*", "a");
+		add_arg(&mut func, "const uint8_t**", "b");
+		add_arg(&mut func, "const double* const*", "c");
+		should_be(&func, "_Z10read_tablePKPdPPKhPKPKd", true);
+		should_be(&func, "?read_table@@YAXPEBQEANPEAPEBEPEBQEBN@Z", false);
+
+		// slices are (pointer, length) pairs.
+		let _ = set_class_hint("Point", ClassHint::WeakStruct);
+		let mut func = SimpFunc{fn_name: "draw".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		for tp in ["const Point*", "size_t", "Point*", "size_t"] {
+			add_arg(&mut func, tp, "a");
+		}
+		should_be(&func, "_Z4drawPK5PointmPS_m", true);
+		should_be(&func, "?draw@@YAXPEBUPoint@@_KPEAU1@1@Z", false);
+
+		// references to arrays, the const goes on the elements.
+		let mut func = SimpFunc{fn_name: "m2".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		for tp in ["const float (&)[16]", "const float (&)[16]", "float (&)[16]"] {
+			add_arg(&mut func, tp, "a");
+		}
+		should_be(&func, "_Z2m2RA16_KfS1_RA16_f", true);
+		should_be(&func, "?m2@@YAXAEAY0BA@$$CBM0AEAY0BA@M@Z", false);
+		let _ = set_class_hint("P", ClassHint::WeakStruct);
+		let mut func = SimpFunc{fn_name: "m3".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "const P (&)[2]", "a");
+		add_arg(&mut func, "P*", "b");
+		should_be(&func, "_Z2m3RA2_K1PPS_", true);
+		should_be(&func, "?m3@@YAXAEAY01$$CBUP@@PEAU1@@Z", false);
+	}
+
+	#[test]
+	fn test_mangle_verified() {
+		let mut funcs = Vec::new();
+		let _ = set_class_hint("Proof", ClassHint::StrongClass);
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Proof");
+		func.fn_name = "merge".to_string();
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "Proof*", "a");
+		add_arg(&mut func, "const RustVec<uint8_t>&", "b");
+		add_arg(&mut func, "const RustVec<uint8_t>&", "c");
+		should_be(&func, "_ZN5Proof5mergeEPS_RK7RustVecIhES4_", true);
+		should_be(&func, "?merge@Proof@@QEAAXPEAV1@AEBU?$RustVec@E@@1@Z", false);
+		funcs.push(func);
+
+		// const member functions: `NK...E` and `QEB`.
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Proof");
+		func.fn_name = "take".to_string();
+		func.is_const = true;
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "const Proof&", "a");
+		add_arg(&mut func, "Proof*", "b");
+		should_be(&func, "_ZNK5Proof4takeERKS_PS_", true);
+		should_be(&func, "?take@Proof@@QEBAXAEBV1@PEAV1@@Z", false);
+		funcs.push(func);
+
+		// static member functions: no `this`, `S` on msvc.
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Proof");
+		func.fn_name = "create".to_string();
+		func.is_static = true;
+		set_ret(&mut func, "Proof*");
+		add_arg(&mut func, "int", "v");
+		should_be(&func, "_ZN5Proof6createEi", true);
+		should_be(&func, "?create@Proof@@SAPEAV1@H@Z", false);
+		funcs.push(func);
+
+		// constructors and destructors: `C1`/`D1` and `?0`/`?1`, no return type.
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Proof");
+		func.fn_name = "Proof".to_string();
+		func.is_ctor = true;
+		add_arg(&mut func, "int", "a");
+		add_arg(&mut func, "const char*", "b");
+		should_be(&func, "_ZN5ProofC1EiPKc", true);
+		should_be(&func, "??0Proof@@QEAA@HPEBD@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("ns", "Widget");
+		func.fn_name = "~Widget".to_string();
+		func.is_dtor = true;
+		should_be(&func, "_ZN2ns6WidgetD1Ev", true);
+		should_be(&func, "??1Widget@ns@@QEAA@XZ", false);
+		funcs.push(func);
+
+		// operators, the unary ones have their own names on Itanium.
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Proof");
+		func.fn_name = "operator==".to_string();
+		func.is_const = true;
+		set_ret(&mut func, "bool");
+		add_arg(&mut func, "const Proof&", "other");
+		should_be(&func, "_ZNK5ProofeqERKS_", true);
+		should_be(&func, "??8Proof@@QEBA_NAEBV0@@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Proof");
+		func.fn_name = "operator[]".to_string();
+		func.is_const = true;
+		set_ret(&mut func, "const int&");
+		add_arg(&mut func, "size_t", "idx");
+		should_be(&func, "_ZNK5ProofixEm", true);
+		should_be(&func, "??AProof@@QEBAAEBH_K@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Proof");
+		func.fn_name = "operator-".to_string();
+		func.is_const = true;
+		set_ret(&mut func, "Proof");
+		should_be(&func, "_ZNK5ProofngEv", true);
+		should_be(&func, "??GProof@@QEBA?AV0@XZ", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Proof");
+		func.fn_name = "operator()".to_string();
+		set_ret(&mut func, "int");
+		add_arg(&mut func, "int", "a");
+		add_arg(&mut func, "int", "b");
+		should_be(&func, "_ZN5ProofclEii", true);
+		should_be(&func, "??RProof@@QEAAHHH@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc{fn_name: "operator<".to_string(), ..Default::default()};
+		set_ret(&mut func, "bool");
+		add_arg(&mut func, "const Proof&", "a");
+		add_arg(&mut func, "const Proof&", "b");
+		should_be(&func, "_ZltRK5ProofS1_", true);
+		should_be(&func, "??M@YA_NAEBVProof@@0@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("ffi", "");
+		func.fn_name = "man_dtor".to_string();
+		func.template_types.push("unique_ptr<Foo>".to_string());
+		add_arg(&mut func, "void*", "a");
+		should_be(&func, "_ZN3ffi8man_dtorISt10unique_ptrI3FooSt14default_deleteIS2_EEEEvPv", true);
+		should_be(&func, "??$man_dtor@V?$unique_ptr@UFoo@@U?$default_delete@UFoo@@@std@@@std@@@ffi@@YAXPEAX@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("myns", "");
+		func.fn_name = "get_proof".to_string();
+		set_ret(&mut func, "Proof*");
+		should_be(&func, "?get_proof@myns@@YAPEAVProof@@XZ", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("myns", "");
+		func.fn_name = "get_message".to_string();
+		set_ret(&mut func, "RustString");
+		add_arg(&mut func, "shared_ptr<Proof>*", "a");
+		funcs.push(func);
+
+		// function templates, the Itanium signature refers to the template parameters.
+		let mut func = SimpFunc{fn_name: "clamp_value".to_string(), ..Default::default()};
+		func.template_types.push("float".to_string());
+		set_ret(&mut func, "float");
+		for name in ["v", "lo", "hi"] {
+			add_arg(&mut func, "float", name);
+		}
+		should_be(&func, "_Z11clamp_valueIfET_S0_S0_S0_", true);
+		should_be(&func, "??$clamp_value@M@@YAMMMM@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc{fn_name: "fill".to_string(), ..Default::default()};
+		func.template_types = vec!["MyStruct".to_string(), "4".to_string()];
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "MyStruct*", "p");
+		add_arg(&mut func, "const MyStruct&", "v");
+		should_be(&func, "_Z4fillI8MyStructLi4EEvPT_RKS1_", true);
+		should_be(&func, "??$fill@UMyStruct@@$03@@YAXPEAUMyStruct@@AEBU0@@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc{fn_name: "pick".to_string(), ..Default::default()};
+		func.template_types = vec!["MyStruct".to_string(), "true".to_string()];
+		set_ret(&mut func, "MyStruct*");
+		add_arg(&mut func, "MyStruct*", "a");
+		add_arg(&mut func, "float*", "b");
+		should_be(&func, "_Z4pickI8MyStructLb1EEPT_S2_Pf", true);
+		should_be(&func, "??$pick@UMyStruct@@$00@@YAPEAUMyStruct@@PEAU0@PEAM@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc{fn_name: "neg".to_string(), ..Default::default()};
+		func.template_types.push("(long)-3".to_string());
+		set_ret(&mut func, "long");
+		should_be(&func, "_Z3negILln3EElv", true);
+		should_be(&func, "??$neg@$0?2@@YAJXZ", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("ns", "");
+		func.fn_name = "first".to_string();
+		func.template_types.push("float".to_string());
+		set_ret(&mut func, "const float&");
+		add_arg(&mut func, "const float*", "p");
+		add_arg(&mut func, "size_t", "n");
+		should_be(&func, "_ZN2ns5firstIfEERKT_PS2_m", true);
+		should_be(&func, "??$first@M@ns@@YAAEBMPEBM_K@Z", false);
+		funcs.push(func);
+
+		// standard abbreviations, every template instance is a candidate but `Sa` is not.
+		let mut func = SimpFunc{fn_name: "k".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		for tp in ["RustVec<int>*", "RustVec<float>*", "std::allocator<char>*", "std::allocator<int>*"] {
+			add_arg(&mut func, tp, "a");
+		}
+		should_be(&func, "_Z1kP7RustVecIiEPS_IfEPSaIcEPSaIiE", true);
+		should_be(&func, "?k@@YAXPEAU?$RustVec@H@@PEAU?$RustVec@M@@PEAV?$allocator@D@std@@PEAV?$allocator@H@4@@Z", false);
+		funcs.push(func);
+
+		// `std::string` is in the inline namespace `std::__cxx11` of libstdc++.
+		let mut func = SimpFunc{fn_name: "s".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "std::string*", "a");
+		add_arg(&mut func, "const std::string&", "b");
+		should_be(&func, "_Z1sPNSt7__cxx1112basic_stringIcSt11char_traitsIcESaIcEEERKS4_", true);
+		should_be(&func, "?s@@YAXPEAV?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@AEBV12@@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc{fn_name: "v".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "std::vector<int, std::allocator<int>>&", "a");
+		add_arg(&mut func, "std::shared_ptr<a::D>&", "b");
+		add_arg(&mut func, "std::vector<float, std::allocator<float>>&", "c");
+		should_be(&func, "_Z1vRSt6vectorIiSaIiEERSt10shared_ptrIN1a1DEERS_IfSaIfEE", true);
+		should_be(&func, "?v@@YAXAEAV?$vector@HV?$allocator@H@std@@@std@@AEAV?$shared_ptr@UD@a@@@2@AEAV?$vector@MV?$allocator@M@std@@@2@@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("std", "");
+		func.fn_name = "mine".to_string();
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "int", "a");
+		should_be(&func, "_ZSt4minei", true);
+		should_be(&func, "?mine@std@@YAXH@Z", false);
+		funcs.push(func);
+
+		// nested names, the prefixes are candidates.
+		let mut func = SimpFunc::default();
+		func.set_scope("a", "");
+		func.fn_name = "f".to_string();
+		set_ret(&mut func, "void");
+		for tp in ["a::C<int>*", "a::C<int>*", "a::C<float>*", "a::D*"] {
+			add_arg(&mut func, tp, "a");
+		}
+		should_be(&func, "_ZN1a1fEPNS_1CIiEES2_PNS0_IfEEPNS_1DE", true);
+		should_be(&func, "?f@a@@YAXPEAV?$C@H@1@0PEAV?$C@M@1@PEAUD@1@@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("a::v2", "");
+		func.fn_name = "h".to_string();
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "a::D*", "a");
+		should_be(&func, "_ZN1a2v21hEPNS_1DE", true);
+		should_be(&func, "?h@v2@a@@YAXPEAUD@2@@Z", false);
+		funcs.push(func);
+
+		let _ = set_class_hint("Outer", ClassHint::StrongClass);
+		let _ = set_class_hint("Outer::Inner", ClassHint::StrongClass);
+		let mut func = SimpFunc::default();
+		func.set_scope("", "Outer::Inner");
+		func.fn_name = "m".to_string();
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "const Outer::Inner&", "a");
+		add_arg(&mut func, "Outer*", "b");
+		should_be(&func, "_ZN5Outer5Inner1mERKS0_PS_", true);
+		should_be(&func, "?m@Inner@Outer@@QEAAXAEBV12@PEAV2@@Z", false);
+		funcs.push(func);
+
+		// abi tags follow the name, Itanium only.
+		let mut func = SimpFunc{fn_name: "tagged".to_string(), abi_tag: "v1".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "int", "a");
+		should_be(&func, "_Z6taggedB2v1i", true);
+		should_be(&func, "?tagged@@YAXH@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc::default();
+		func.set_scope("a", "");
+		func.fn_name = "g".to_string();
+		func.abi_tag = "cxx11".to_string();
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "const std::string&", "a");
+		should_be(&func, "_ZN1a1gB5cxx11ERKNSt7__cxx1112basic_stringIcSt11char_traitsIcESaIcEEE", true);
+		should_be(&func, "?g@a@@YAXAEBV?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@@Z", false);
+		funcs.push(func);
+
+		// `std::string_view` and `std::vector` with the defaulted allocator.
+		let mut func = SimpFunc{fn_name: "sv".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "const std::string_view&", "a");
+		add_arg(&mut func, "const std::vector<int>&", "b");
+		add_arg(&mut func, "std::vector<int>&", "c");
+		should_be(&func, "_Z2svRKSt17basic_string_viewIcSt11char_traitsIcEERKSt6vectorIiSaIiEERS7_", true);
+		should_be(&func, "?sv@@YAXAEBV?$basic_string_view@DU?$char_traits@D@std@@@std@@AEBV?$vector@HV?$allocator@H@std@@@2@AEAV32@@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc{fn_name: "vals".to_string(), ..Default::default()};
+		set_ret(&mut func, "const std::vector<double>&");
+		add_arg(&mut func, "const std::vector<double>*", "p");
+		should_be(&func, "_Z4valsPKSt6vectorIdSaIdEE", true);
+		should_be(&func, "?vals@@YAAEBV?$vector@NV?$allocator@N@std@@@std@@PEBV12@@Z", false);
+		funcs.push(func);
+
+		// returning a `std::string` tags the function, unless a parameter has the tag.
+		let mut func = SimpFunc{fn_name: "mk".to_string(), ..Default::default()};
+		set_ret(&mut func, "unique_ptr<std::string>");
+		add_arg(&mut func, "const char*", "a");
+		add_arg(&mut func, "size_t", "b");
+		should_be(&func, "_Z2mkB5cxx11PKcm", true);
+		should_be(&func, "?mk@@YA?AV?$unique_ptr@V?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@U?$default_delete@V?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@@2@@std@@PEBD_K@Z", false);
+		funcs.push(func);
+
+		let mut func = SimpFunc{fn_name: "echo".to_string(), ..Default::default()};
+		set_ret(&mut func, "std::string");
+		add_arg(&mut func, "const std::string&", "s");
+		should_be(&func, "_Z4echoRKNSt7__cxx1112basic_stringIcSt11char_traitsIcESaIcEEE", true);
+		should_be(&func, "?echo@@YA?AV?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@AEBV12@@Z", false);
+		funcs.push(func);
+
+		// rvalue references, an owned `String` is `RustString&&`.
+		let mut func = SimpFunc{fn_name: "take".to_string(), ..Default::default()};
+		set_ret(&mut func, "void");
+		add_arg(&mut func, "const RustString&&", "a");
+		add_arg(&mut func, "RustVec<int>", "b");
+		add_arg(&mut func, "RustString&&", "c");
+		add_arg(&mut func, "RustVec<int>&&", "d");
+		should_be(&func, "_Z4takeOK10RustString7RustVecIiEOS_OS3_", true);
+		should_be(&func, "?take@@YAX$$QEBURustString@@U?$RustVec@H@@$$QEAU1@$$QEAU2@@Z", false);
+		funcs.push(func);
+
+		for func in &funcs {
+			let name = mangle_gcc(func).unwrap();
+			assert_eq!(verify_link_name(func, &name, Target::new("x86_64", "linux")), Ok(()));
+			let name = mangle_msvc(func).unwrap();
+			assert_eq!(verify_link_name(func, &name, Target::new("x86_64", "windows")), Ok(()));
+		}
+	}
+}
@@ -0,0 +1,914 @@
+use proc_macro2::TokenStream;
+use syn::{
+	Attribute, Expr, FnArg, ForeignItem, ItemForeignMod, GenericArgument, Lit, Meta, Pat, PathArguments,
+	ReturnType, Token, Type, Visibility,
+};
+use syn::punctuated::Punctuated;
+use crate::util::*;
+use crate::mangle::*;
+use crate::demangle::{is_ident, parse_cpp_type, Abi};
+use crate::target::Target;
+
+lazy_static::lazy_static! {
+	static ref INDIRECTION: regex::Regex = regex::Regex::new(r"^\s*(?:const\s+)?(.*?)\s*([&*]*)\s*$").unwrap();
+}
+
+pub fn map_to_cxx(tp: &str) -> &str {
+	match tp {
+		"String" => "RustString",
+		"CxxString" => "std::string",
+		"CxxStringView" => "std::string_view",
+		_ => tp
+	}
+}
+
+/// The element types of `CxxVector<T>`, the runtime crate has their accessors.
+const CXX_VECTOR_ELEMENTS: [&str; 10] = ["i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "f32", "f64"];
+
+pub struct Functions {
+	pub funcs: Vec<SimpFunc>,
+	pub is_cpp: bool,
+	err_str: String,
+	target: Target,
+}
+
+/// Render a `syn::Type` back into a compact type string (no incidental spaces),
+/// e.g. `&mut MagicIn`, `CPtr<CppStruct>`, `&Vec<&str>`, `[u8]`.
+fn type_str(ty: &Type) -> String {
+	match ty {
+		Type::Reference(r) => {
+			format!("&{}{}", if r.mutability.is_some() { "mut " } else { "" }, type_str(&r.elem))
+		}
+		Type::Path(p) => {
+			let seg = match p.path.segments.last() {
+				Some(s) => s,
+				None => return String::new(),
+			};
+			let base = seg.ident.to_string();
+			match &seg.arguments {
+				PathArguments::AngleBracketed(a) => {
+					let inner: Vec<String> = a.args.iter().filter_map(|g| match g {
+						GenericArgument::Type(t) => Some(type_str(t)),
+						_ => None,
+					}).collect();
+					format!("{}<{}>", base, inner.join(","))
+				}
+				_ => base,
+			}
+		}
+		Type::Slice(s) => format!("[{}]", type_str(&s.elem)),
+		Type::Ptr(p) => {
+			format!("*{}{}", if p.mutability.is_some() { "mut " } else { "const " }, type_str(&p.elem))
+		}
+		other => quote::quote!(#other).to_string().replace(' ', ""),
+	}
+}
+
+/// The "core" identifier of a type: peel references and take the last path segment.
+fn core_ident(ty: &Type) -> String {
+	match ty {
+		Type::Reference(r) => core_ident(&r.elem),
+		Type::Ptr(p) => core_ident(&p.elem),
+		Type::Path(p) => p.path.segments.last().map(|s| s.ident.to_string()).unwrap_or_default(),
+		Type::Slice(_) => type_str(ty),
+		other => type_str(other),
+	}
+}
+
+fn pat_name(pat: &Pat) -> String {
+	match pat {
+		Pat::Ident(pi) => pi.ident.to_string(),
+		other => quote::quote!(#other).to_string().replace(' ', ""),
+	}
+}
+
+fn path_to_string(path: &syn::Path) -> String {
+	path.segments.iter()
+		.map(|s| s.ident.to_string())
+		.collect::<Vec<_>>()
+		.join("::")
+}
+
+/// One argument of `#[template(...)]` as written in C++.
+fn template_arg(expr: &Expr) -> Option<String> {
+	let int_lit = |v: &syn::LitInt, neg: bool| -> Option<String> {
+		let val = format!("{}{}", select_val(neg, "-", ""), v.base10_digits());
+		val.parse::<i64>().ok()?;
+		Some(match v.suffix() {
+			""|"i32" => val,
+			sfx if sfx.starts_with('u') && neg => return None,
+			sfx => format!("({}){}", rust_prim_to_cpp(sfx)?, val),
+		})
+	};
+	match expr {
+		Expr::Path(p) if p.qself.is_none() && p.path.segments.iter().all(|x| x.arguments.is_none()) => {
+			let name = path_to_string(&p.path);
+			Some(match rust_prim_to_cpp(&name) {
+				Some(x) => x.to_string(),
+				None => {
+					let _ = set_class_hint(&name, ClassHint::WeakStruct);
+					name
+				}
+			})
+		}
+		Expr::Lit(x) => match &x.lit {
+			Lit::Bool(b) => Some(b.value.to_string()),
+			Lit::Int(v) => int_lit(v, false),
+			_ => None,
+		},
+		Expr::Unary(x) if matches!(x.op, syn::UnOp::Neg(_)) => match &*x.expr {
+			Expr::Lit(lit) => match &lit.lit {
+				Lit::Int(v) => int_lit(v, true),
+				_ => None,
+			},
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// The C++ type of a rust primitive, or of a `std::ffi::c_*` alias.
+fn rust_prim_to_cpp(tp: &str) -> Option<&'static str> {
+	Some(match tp {
+		"i8" => "int8_t",
+		"i16" => "int16_t",
+		"i32" => "int",
+		"i64" => "int64_t",
+		"i128" => "__int128",
+		"isize" => "ptrdiff_t",
+		"u8" => "uint8_t",
+		"u16" => "uint16_t",
+		"u32" => "uint32_t",
+		"u64" => "uint64_t",
+		"u128" => "unsigned __int128",
+		"usize" => "size_t",
+		"f32"|"c_float" => "float",
+		"f64"|"c_double" => "double",
+		"bool" => "bool",
+		"char" => "char32_t",
+		"c_char" => "char",
+		"c_schar" => "signed char",
+		"c_uchar" => "unsigned char",
+		"c_short" => "short",
+		"c_ushort" => "unsigned short",
+		"c_int" => "int",
+		"c_uint" => "unsigned int",
+		"c_long" => "long",
+		"c_ulong" => "unsigned long",
+		"c_longlong" => "long long",
+		"c_ulonglong" => "unsigned long long",
+		"wchar_t" => "wchar_t",
+		_ => return None,
+	})
+}
+
+/// The kind and size of a C++ builtin: `b`ool, `i`nteger or `f`loat.
+fn prim_layout(tp: &str, target: Target) -> Option<(char, usize)> {
+	let ptr = target.ptr_size();
+	let win = target.windows;
+	Some(match tp {
+		"bool" => ('b', 1),
+		"char"|"signed char"|"unsigned char"|"int8_t"|"uint8_t"|"char8_t" => ('i', 1),
+		"short"|"unsigned short"|"int16_t"|"uint16_t"|"char16_t" => ('i', 2),
+		"int"|"unsigned int"|"unsigned"|"int32_t"|"uint32_t"|"char32_t" => ('i', 4),
+		"long long"|"unsigned long long"|"int64_t"|"uint64_t" => ('i', 8),
+		"__int128"|"unsigned __int128" => ('i', 16),
+		"long"|"unsigned long" => ('i', select_val(win, 4, ptr)),
+		"wchar_t" => ('i', select_val(win, 2, 4)),
+		"size_t"|"ptrdiff_t" => ('i', ptr),
+		"float" => ('f', 4),
+		"double" => ('f', 8),
+		"long double" => ('f', select_val(win, 8, 16)),
+		_ => return None,
+	})
+}
+
+/// The C++ type of a raw pointer, `*mut *const T` is `const T**`, `*const *mut T` is `T* const*`.
+/// `base` is the C++ type of `T`.
+fn raw_ptr_cpp(tp_full: &str, base: &str) -> String {
+	let mut levels = Vec::new();
+	let mut rest = tp_full;
+	while let Some(x) = rest.strip_prefix('*') {
+		let (is_const, x) = match x.strip_prefix("const ") {
+			Some(x) => (true, x),
+			None => (false, x.trim_start_matches("mut ")),
+		};
+		levels.push(is_const);
+		rest = x;
+	}
+	let mut cpp = base.to_string();
+	for is_const in levels.into_iter().rev() {
+		cpp = match (is_const, cpp.ends_with('*')) {
+			(true, true) => format!("{} const*", cpp),
+			(true, false) => format!("const {}*", cpp),
+			_ => format!("{}*", cpp),
+		};
+	}
+	cpp
+}
+
+/// `const unsigned long&` gives (`unsigned long`, `&`).
+fn split_indirection(tp: &str) -> (String, String) {
+	match INDIRECTION.captures(tp) {
+		Some(caps) => (caps[1].to_string(), caps[2].to_string()),
+		None => (tp.to_string(), String::new()),
+	}
+}
+
+/// `N` of `&[T;N]`.
+fn array_len(tp_full: &str) -> &str {
+	tp_full.rsplit(';').next().unwrap_or_default().trim_end_matches(']')
+}
+
+impl Functions {
+	pub fn new(target: Target) -> Self { Self{
+		funcs: Vec::new(),
+		is_cpp: false,
+		err_str: "".to_string(),
+		target,
+	} }
+
+	fn is_compatible_rettype(x: &str) -> bool {
+		x.is_empty() || rust_prim_to_cpp(x).is_some()
+	}
+
+	fn build_as_c_arg(self: &mut Self, arg: &mut SimpArg) -> Result<(), ()> {
+		if arg.tp_full.is_empty() {
+			arg.is_primitive = true;
+			// arg.tp_cpp = "void".to_string();
+			return Ok(());
+		}
+		let is_ref = arg.tp_full.chars().next().unwrap() == '&';
+		arg.tp_asc = match arg.tp_wrap.as_str() {
+			"Option" => format!("*{} {}", if arg.is_const {"const"} else {"mut"},  &arg.tp),
+			"CPtr" => String::from("*const u8"),
+			"Ptr" => arg.tp_full.clone(),
+			"POD" if !is_ref => arg.tp.clone(),
+			"RustFn" => String::from("*mut u8"),
+			"CxxVector" => format!("*{} CxxVector<{}>", if arg.is_const {"const"} else {"mut"}, &arg.tp),
+			"SharedPtr"|"UniquePtr" => {
+				let _ = set_class_hint(&arg.tp, ClassHint::WeakClass);
+				String::from("*const u8")
+			},
+			_ if is_ref => format!("*{} {}", if arg.is_const {"const"} else {"mut"},  &arg.tp),
+			_ => arg.tp_full.clone(),
+		};
+
+		// C++ objects stay in C++ memory, a libstdc++ `std::string` can't be moved by memcpy.
+		let cxx_ok = match (arg.tp.as_str(), arg.tp_wrap.as_str()) {
+			("CxxString", "UniquePtr") => true,
+			("CxxString"|"CxxStringView", wrap) => is_ref && wrap.is_empty(),
+			_ => true,
+		};
+		if !cxx_ok {
+			self.err_str = format!("unkown type {}, use &{} or UniquePtr<CxxString>", arg.tp_full, arg.tp);
+			return Err(());
+		}
+
+		arg.is_primitive = true;
+		let cpp_type = match (rust_prim_to_cpp(&arg.tp), &arg.tp as &str) {
+			(Some(cpp), _) => cpp,
+			(_, "String") => {
+				arg.is_primitive=false;
+				"RustString"   //special map for String
+			},
+			(_, "str") => {
+				arg.is_primitive=false;
+				"rust_refstr_t"   //&str maps to the rust_refstr_t fat-pointer struct
+			},
+			(_, "CxxString"|"CxxStringView") => {
+				arg.is_primitive = false;
+				map_to_cxx(&arg.tp)
+			},
+			_ => {arg.is_primitive = false; &arg.tp as &str},
+		};
+		// arg.name="" means it's a return value.
+		arg.tp_cpp = match arg.tp_wrap.as_str() {
+			"CPtr" => format!("{}*", cpp_type),
+			"Ptr" => {
+				// `*mut *const T`, T is a primitive, a struct or `c_void`.
+				if !arg.tp_full.rsplit(' ').next().is_some_and(is_ident) {
+					self.err_str = format!("unkown type {}", arg.tp_full);
+					return Err(());
+				}
+				let base = match arg.tp.as_str() {
+					"c_void" => "void",
+					_ if arg.is_primitive => cpp_type,
+					_ => {
+						let _ = set_class_hint(&arg.tp, ClassHint::WeakStruct);
+						cpp_type
+					}
+				};
+				arg.is_primitive = true;
+				raw_ptr_cpp(&arg.tp_full, base)
+			}
+			"SharedPtr" => match arg.name.as_str() {
+				"" =>  format!("shared_ptr<{}>", cpp_type),
+				_ => format!("{}*", cpp_type),
+			}
+			"UniquePtr" => match arg.name.as_str() {
+				"" =>  format!("unique_ptr<{}>", cpp_type),
+				_ => format!("{}*", cpp_type),
+			}
+			"RustFn" if arg.name.is_empty() => {
+				self.err_str = "RustFn can only be used as an argument".to_string();
+				return Err(());
+			}
+			"RustFn" => format!("RustFn<{}>&", cpp_type),
+			"Option" => match arg.is_const {
+				true=> format!("const {}*", cpp_type),
+				false => format!("{}*", cpp_type),
+			}
+			"CxxVector" if !is_ref || !CXX_VECTOR_ELEMENTS.contains(&arg.tp.as_str()) => {
+				self.err_str = format!("unkown type {}, CxxVector<T> is only passed by reference, T is a number", arg.tp_full);
+				return Err(());
+			}
+			"CxxVector" => {
+				arg.is_primitive = false;
+				format!("{}std::vector<{}>&", select_val(arg.is_const, "const ", ""), cpp_type)
+			}
+			"Vec" => {
+				arg.is_primitive = false;
+				if is_ref {
+					format!("{}RustVec<{}>&", select_val(arg.is_const, "const ", ""), cpp_type)
+				} else {
+					format!("RustVec<{}>", cpp_type)
+				}
+			}
+			"Slice" => {
+				if !arg.is_primitive {
+					let _ = set_class_hint(&arg.tp, ClassHint::WeakStruct);
+				}
+				arg.is_primitive = false;
+				format!("{}{}*,size_t", select_val(arg.is_const, "const ", ""), cpp_type)
+			}
+			"Array" if arg.name.is_empty() => {
+				self.err_str = "arrays can only be used as arguments".to_string();
+				return Err(());
+			}
+			"Array" => {
+				if !arg.is_primitive {
+					let _ = set_class_hint(&arg.tp, ClassHint::WeakStruct);
+				}
+				arg.is_primitive = false;
+				format!("{}{} (&)[{}]", select_val(arg.is_const, "const ", ""), cpp_type, array_len(&arg.tp_full))
+			}
+			""|"POD" if is_ref => {
+				match arg.tp.as_str() {
+					"CStr" => "const char*".to_string(),
+					"str" => "const char*,size_t".to_string(),
+					_ => {
+						let _ = set_class_hint(&arg.tp, ClassHint::WeakStruct);
+						format!("{}{}&", select_val(arg.is_const, "const ", ""), cpp_type)
+					}
+				}
+			}
+			"" if arg.is_primitive => cpp_type.to_string(),
+			""|"POD" if arg.name=="" => { //a return value.
+				let _ = set_class_hint(&arg.tp, ClassHint::WeakStruct);
+				cpp_type.to_string()
+			}
+			// an owned `String` argument is moved into C++.
+			"" if arg.tp == "String" => "RustString&&".to_string(),
+			"POD" if !arg.is_primitive => {
+				// a trivially copyable struct passed by value.
+				let _ = set_class_hint(&arg.tp, ClassHint::WeakStruct);
+				cpp_type.to_string()
+			}
+			_ => {
+				self.err_str = format!("unkown type {}", arg.tp_full);
+				return Err(());
+			}
+		};
+		Ok(())
+	}
+
+	/// Walk a `syn::Type` into the SimpArg IR fields (tp / tp_wrap / tp_full / is_const).
+	/// `name` is "" for return values.
+	fn parse_arg_type(&mut self, name: &str, ty: &Type) -> Result<SimpArg, ()> {
+		let mut arg = SimpArg::default();
+		arg.name = name.to_string();
+		let tp_full = type_str(ty);
+		arg.raw_str = if name.is_empty() { tp_full.clone() } else { format!("{}:{}", name, tp_full) };
+
+		// Peel a single outer reference for inspection.
+		let (is_ref, is_mut, core): (bool, bool, &Type) = match ty {
+			Type::Reference(r) => (true, r.mutability.is_some(), r.elem.as_ref()),
+			other => (false, false, other),
+		};
+
+		match core {
+			Type::Ptr(p) if !is_ref => {
+				arg.tp = core_ident(core);
+				arg.tp_wrap = "Ptr".to_string();
+				arg.is_const = p.mutability.is_none();
+				arg.tp_full = tp_full;
+			}
+			Type::Slice(sl) => {
+				// `&[T]` of a primitive or a struct, a (pointer, length) pair.
+				let is_plain = match sl.elem.as_ref() {
+					Type::Path(p) => p.path.segments.last().is_some_and(|s| s.arguments.is_empty()),
+					_ => false,
+				};
+				if !is_ref || !is_plain {
+					self.err_str = format!("unkown type {}", tp_full);
+					return Err(());
+				}
+				arg.tp = core_ident(&sl.elem);
+				arg.tp_wrap = "Slice".to_string();
+				arg.is_const = !is_mut;
+				arg.tp_full = tp_full;
+			}
+			Type::Array(a) => {
+				// `&[T; N]` of a primitive or a struct, a reference to a C++ array.
+				let is_plain = match a.elem.as_ref() {
+					Type::Path(p) => p.path.segments.last().is_some_and(|s| s.arguments.is_empty()),
+					_ => false,
+				};
+				let len = match &a.len {
+					Expr::Lit(x) => match &x.lit {
+						Lit::Int(v) => v.base10_parse::<usize>().ok(),
+						_ => None,
+					},
+					_ => None,
+				};
+				let len = match len {
+					Some(n) if is_ref && is_plain => n,
+					_ => {
+						self.err_str = format!("unkown type {}", tp_full);
+						return Err(());
+					}
+				};
+				arg.tp = core_ident(&a.elem);
+				arg.tp_wrap = "Array".to_string();
+				arg.is_const = !is_mut;
+				arg.tp_full = format!("&{}[{};{}]", select_val(is_mut, "mut ", ""), type_str(&a.elem), len);
+			}
+			Type::Path(p) => {
+				let seg = match p.path.segments.last() {
+					Some(s) => s,
+					None => { self.err_str = "empty type path".to_string(); return Err(()); }
+				};
+				let outer = seg.ident.to_string();
+				match &seg.arguments {
+					PathArguments::AngleBracketed(a) => {
+						let inner_ident = a.args.iter().find_map(|g| match g {
+							GenericArgument::Type(t) => Some(core_ident(t)),
+							_ => None,
+						}).unwrap_or_default();
+						match outer.as_str() {
+							"RustFn" => {
+								let targs: Vec<&Type> = a.args.iter().filter_map(|g| match g {
+									GenericArgument::Type(t) => Some(t),
+									_ => None,
+								}).collect();
+								arg.tp = self.rustfn_sig(&targs)?;
+								arg.tp_wrap = "RustFn".to_string();
+								arg.is_const = false;
+								arg.tp_full = tp_full;
+							}
+							"CPtr" => {
+								arg.tp = inner_ident;
+								arg.tp_wrap = "CPtr".to_string();
+								arg.is_const = false;
+								arg.tp_full = format!("CPtr<{}>", &arg.tp);
+							}
+							"Option" => {
+								arg.tp = inner_ident;
+								arg.tp_wrap = "Option".to_string();
+								arg.is_const = false;
+								arg.tp_full = format!("Option<&{}>", &arg.tp);
+							}
+							_ => {
+								// Vec / SharedPtr / UniquePtr / POD / other wrappers.
+								arg.tp = inner_ident;
+								arg.tp_wrap = outer;
+								arg.is_const = if is_ref { !is_mut } else { false };
+								arg.tp_full = tp_full;
+							}
+						}
+					}
+					_ => {
+						// Plain type (possibly behind a reference).
+						arg.tp = outer;
+						arg.tp_wrap = String::new();
+						arg.is_const = if is_ref { !is_mut } else { true };
+						arg.tp_full = tp_full;
+					}
+				}
+			}
+			_ => {
+				arg.tp = type_str(core);
+				arg.tp_wrap = String::new();
+				arg.is_const = if is_ref { !is_mut } else { true };
+				arg.tp_full = tp_full;
+			}
+		}
+		Ok(arg)
+	}
+
+	/// The C++ signature of `RustFn<(A0, A1), R>`, e.g. `void(int, const RustString&)`.
+	/// Arguments are cloned by rust from their addresses, the result is written back as is.
+	fn rustfn_sig(&mut self, targs: &[&Type]) -> Result<String, ()> {
+		let (args, ret) = match targs {
+			[Type::Tuple(args), ret] => (args, ret),
+			_ => {
+				self.err_str = "RustFn expects RustFn<(argument types...), return type>".to_string();
+				return Err(());
+			}
+		};
+		let mut sig_args = Vec::new();
+		for ty in &args.elems {
+			if let Type::Reference(_) = ty {
+				self.err_str = format!("RustFn argument {} should be an owned type, it's cloned", type_str(ty));
+				return Err(());
+			}
+			let mut arg = self.parse_arg_type("", ty)?;
+			arg.is_primitive = arg.tp_wrap.is_empty() && Self::is_compatible_rettype(&arg.tp);
+			self.build_as_c_arg(&mut arg)?;
+			sig_args.push(select_val(arg.is_primitive, arg.tp_cpp.clone(), format!("const {}&", arg.tp_cpp)));
+		}
+		let ret = match ret {
+			Type::Tuple(t) if t.elems.is_empty() => "void".to_string(),
+			ty => {
+				let ret = self.parse_ret(&ReturnType::Type(Default::default(), Box::new((*ty).clone())))?;
+				if !ret.is_primitive {
+					self.err_str = format!("RustFn can only return a primitive type, not {}", ret.raw_str);
+					return Err(());
+				}
+				ret.tp_cpp
+			}
+		};
+		Ok(format!("{}({})", ret, sig_args.join(", ")))
+	}
+
+	/// `#[constructor(Class)]` or `#[constructor(Class, size = 64, align = 8)]`.
+	fn parse_ctor_attr(&mut self, attr: &Attribute, curfunc: &mut SimpFunc) -> Result<(), ()> {
+		let bad = |this: &mut Self| {
+			this.err_str = format!("function {}: expect #[constructor(Class, size = N, align = N)]", curfunc.rust_name);
+			Err(())
+		};
+		let metas = match attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated) {
+			Ok(x) => x,
+			Err(_) => return bad(self),
+		};
+		let (mut size, mut align) = (None, None);
+		for meta in metas {
+			match meta {
+				Meta::Path(path) if curfunc.klsname.is_empty() => {
+					curfunc.klsname = path_to_string(&path);
+				}
+				Meta::NameValue(nv) => {
+					let val = match &nv.value {
+						Expr::Lit(x) => match &x.lit {
+							Lit::Int(v) => v.base10_parse::<usize>().ok(),
+							_ => None,
+						},
+						_ => None,
+					};
+					match (nv.path.get_ident().map(|x| x.to_string()).as_deref(), val) {
+						(Some("size"), Some(v)) if v > 0 => size = Some(v),
+						(Some("align"), Some(v)) if v.is_power_of_two() => align = Some(v),
+						_ => return bad(self),
+					}
+				}
+				_ => return bad(self),
+			}
+		}
+		curfunc.box_layout = match (size, align) {
+			(Some(size), Some(align)) => Some((size, align)),
+			(None, None) => None,
+			_ => return bad(self),
+		};
+		if curfunc.klsname.is_empty() {
+			return bad(self);
+		}
+		let _ = set_class_hint(&curfunc.klsname, ClassHint::StrongClass);
+		curfunc.is_ctor = true;
+		Ok(())
+	}
+
+	/// `#[template(f32, MyStruct, 4, true)]`, the template arguments of the C++ function.
+	/// Integers are `int` unless suffixed, `4usize` is `(size_t)4`.
+	fn parse_template_attr(&mut self, attr: &Attribute, curfunc: &mut SimpFunc) -> Result<(), ()> {
+		let exprs = attr.parse_args_with(Punctuated::<Expr, Token![,]>::parse_terminated);
+		let targs = exprs.ok().and_then(|x| x.iter().map(template_arg).collect::<Option<Vec<_>>>());
+		match targs {
+			Some(targs) if !targs.is_empty() => {
+				curfunc.template_types = targs;
+				Ok(())
+			}
+			_ => {
+				self.err_str = format!("function {}: expect #[template(type or integer, ...)]", curfunc.rust_name);
+				Err(())
+			}
+		}
+	}
+
+	fn parse_attr(&mut self, attr: &Attribute, ns: &mut String, curfunc: &mut SimpFunc) -> Result<(), ()> {
+		let name = match attr.path().segments.last() {
+			Some(s) => s.ident.to_string(),
+			None => return Ok(()),
+		};
+		match name.as_str() {
+			"namespace" => {
+				if let Ok(path) = attr.parse_args::<syn::Path>() {
+					*ns = path_to_string(&path);
+				}
+			}
+			"member_of" => {
+				if let Ok(path) = attr.parse_args::<syn::Path>() {
+					let klsname = path_to_string(&path);
+					let _ = set_class_hint(&klsname, ClassHint::StrongClass);
+					curfunc.klsname = klsname;
+				}
+			}
+			"static_of" => {
+				if let Ok(path) = attr.parse_args::<syn::Path>() {
+					let klsname = path_to_string(&path);
+					let _ = set_class_hint(&klsname, ClassHint::StrongClass);
+					curfunc.klsname = klsname;
+					curfunc.is_static = true;
+				}
+			}
+			"constructor" => {
+				self.parse_ctor_attr(attr, curfunc)?;
+			}
+			"cpp_name" => {
+				match attr.parse_args::<syn::LitStr>() {
+					Ok(name) if is_ident(&name.value()) => curfunc.fn_name = name.value(),
+					_ => {
+						self.err_str = format!("function {}: expect #[cpp_name(\"name\")] with a C++ identifier", curfunc.rust_name);
+						return Err(());
+					}
+				}
+			}
+			"template" => {
+				self.parse_template_attr(attr, curfunc)?;
+			}
+			"abi_tag" => {
+				match attr.parse_args::<syn::LitStr>() {
+					Ok(tag) if is_ident(&tag.value()) => curfunc.abi_tag = tag.value(),
+					_ => {
+						self.err_str = format!("function {}: expect #[abi_tag(\"tag\")]", curfunc.rust_name);
+						return Err(());
+					}
+				}
+			}
+			"operator" => {
+				let op = attr.parse_args::<TokenStream>().map(|x| x.to_string().replace(' ', "")).unwrap_or_default();
+				if !OPERATORS.iter().any(|x| x.0 == op) {
+					self.err_str = format!("function {}: unsupported #[operator({})]", curfunc.rust_name, op);
+					return Err(());
+				}
+				curfunc.operator = op;
+			}
+			"const_fn" => {
+				curfunc.is_const = true;
+			}
+			"class" => {
+				if let Ok(path) = attr.parse_args::<syn::Path>() {
+					let _ = set_class_hint(&path_to_string(&path), ClassHint::StrongClass);
+				}
+			}
+			"struct" => {
+				if let Ok(path) = attr.parse_args::<syn::Path>() {
+					let _ = set_class_hint(&path_to_string(&path), ClassHint::StrongStruct);
+				}
+			}
+			_ => {}
+		}
+		Ok(())
+	}
+
+	/// The C++ type of `#[cpp_type("unsigned long")]`, spaces normalized.
+	fn cpp_type_attr(&mut self, attrs: &[Attribute], rust_name: &str) -> Result<Option<String>, ()> {
+		let attr = match attrs.iter().find(|x| x.path().segments.last().is_some_and(|s| s.ident == "cpp_type")) {
+			Some(x) => x,
+			None => return Ok(None),
+		};
+		match attr.parse_args::<syn::LitStr>() {
+			Ok(tp) => Ok(Some(tp.value().split_whitespace().collect::<Vec<_>>().join(" ")
+				.replace("long int", "long").replace("short int", "short"))),
+			Err(_) => {
+				self.err_str = format!("function {}: expect #[cpp_type(\"C++ type\")]", rust_name);
+				Err(())
+			}
+		}
+	}
+
+	/// Replace the guessed C++ type of `arg`. The rust type is what crosses the FFI boundary,
+	/// so the C++ one must keep its size and indirection.
+	fn override_cpp_type(&mut self, arg: &mut SimpArg, cpp: &str, rust_name: &str) -> Result<(), ()> {
+		let (core, indir) = split_indirection(cpp);
+		// an array reference may decay to a pointer to its first element.
+		let tp_cpp = match arg.tp_cpp.split_once(" (&)") {
+			Some((elem, _)) if arg.tp_wrap == "Array" => format!("{}*", elem),
+			_ => arg.tp_cpp.clone(),
+		};
+		let (guess, guess_indir) = split_indirection(&tp_cpp);
+		let ok = matches!(arg.tp_wrap.as_str(), ""|"Option"|"Array") && !tp_cpp.contains(',')
+			&& parse_cpp_type(cpp, Abi::Itanium, self.target).is_ok()
+			&& indir == guess_indir
+			&& match (prim_layout(&guess, self.target), prim_layout(&core, self.target)) {
+				(Some(x), Some(y)) => x == y,
+				// an enum with an integer of the same size underlying.
+				(Some((kind, _)), None) => kind == 'i' && core.split("::").all(is_ident),
+				(None, x) => x.is_none() && !arg.tp_full.is_empty(),
+			};
+		if !ok {
+			let what = select_val(arg.name.is_empty(), "the return type", arg.raw_str.as_str());
+			self.err_str = format!("function {}: #[cpp_type(\"{}\")] doesn't match {}", rust_name, cpp, what);
+			return Err(());
+		}
+		if prim_layout(&guess, self.target).is_some() && prim_layout(&core, self.target).is_none() {
+			let _ = set_class_hint(&core, ClassHint::Enum);
+		}
+		arg.tp_cpp = cpp.to_string();
+		Ok(())
+	}
+
+	fn parse_ret(&mut self, output: &ReturnType) -> Result<SimpArg, ()> {
+		let mut ret = match output {
+			ReturnType::Default => SimpArg::default(),
+			ReturnType::Type(_, ty) => self.parse_arg_type("", ty)?,
+		};
+		ret.is_primitive = ret.tp_wrap.is_empty() && Self::is_compatible_rettype(&ret.tp);
+		self.build_as_c_arg(&mut ret)?;
+		Ok(ret)
+	}
+
+	/// `Result<T, CppError>` returns `T` through the catching trampoline, `Result<(), CppError>` nothing.
+	fn strip_result(&mut self, output: &ReturnType, curfunc: &mut SimpFunc) -> Result<ReturnType, ()> {
+		let seg = match output {
+			ReturnType::Type(_, ty) => match ty.as_ref() {
+				Type::Path(p) => p.path.segments.last(),
+				_ => None,
+			},
+			ReturnType::Default => None,
+		};
+		let seg = match seg {
+			Some(seg) if seg.ident == "Result" => seg,
+			_ => return Ok(output.clone()),
+		};
+		let targs: Vec<&Type> = match &seg.arguments {
+			PathArguments::AngleBracketed(a) => a.args.iter().filter_map(|g| match g {
+				GenericArgument::Type(t) => Some(t),
+				_ => None,
+			}).collect(),
+			_ => Vec::new(),
+		};
+		if targs.len() != 2 || core_ident(targs[1]) != "CppError" {
+			self.err_str = format!("function {}: only Result<T, CppError> is supported as a Result", curfunc.rust_name);
+			return Err(());
+		}
+		curfunc.is_result = true;
+		Ok(match targs[0] {
+			Type::Tuple(t) if t.elems.is_empty() => ReturnType::Default,
+			ty => ReturnType::Type(Default::default(), Box::new(ty.clone())),
+		})
+	}
+
+	fn parse_fn(&mut self, f: &syn::ForeignItemFn) -> Result<(), ()> {
+		let mut curfunc = SimpFunc::default();
+		curfunc.access = match &f.vis {
+			Visibility::Public(_) => "pub".to_string(),
+			Visibility::Restricted(r) => format!("pub({})", path_to_string(&r.path)),
+			Visibility::Inherited => String::new(),
+		};
+		curfunc.is_async = f.sig.asyncness.is_some();
+		curfunc.rust_name = f.sig.ident.to_string();
+		curfunc.fn_name = curfunc.rust_name.clone();
+
+		let mut ns = String::new();
+		for attr in &f.attrs {
+			self.parse_attr(attr, &mut ns, &mut curfunc)?;
+		}
+		// operators and constructors have their own C++ names.
+		if (!curfunc.operator.is_empty() || curfunc.is_ctor) && curfunc.fn_name != curfunc.rust_name {
+			self.err_str = format!("function {}: #[cpp_name] conflicts with #[operator] and #[constructor]", curfunc.rust_name);
+			return Err(());
+		}
+		if !curfunc.template_types.is_empty() && (!curfunc.operator.is_empty() || curfunc.is_ctor) {
+			self.err_str = format!("function {}: #[template] conflicts with #[operator] and #[constructor]", curfunc.rust_name);
+			return Err(());
+		}
+		if !curfunc.template_types.is_empty() && !self.is_cpp {
+			self.err_str = format!("function {}: #[template] needs extern \"C++\"", curfunc.rust_name);
+			return Err(());
+		}
+		if !curfunc.operator.is_empty() {
+			curfunc.fn_name = format!("operator{}", curfunc.operator);
+		}
+		if curfunc.is_ctor {
+			curfunc.fn_name = curfunc.klsname.rsplit("::").next().unwrap_or_default().to_string();
+		}
+		if curfunc.is_const && curfunc.klsname.is_empty() {
+			self.err_str = format!("function {}: #[const_fn] needs #[member_of(Class)]", curfunc.rust_name);
+			return Err(());
+		}
+		if curfunc.is_static && (curfunc.is_const || curfunc.is_async) {
+			self.err_str = format!("function {}: #[static_of] functions can't be async or #[const_fn]", curfunc.rust_name);
+			return Err(());
+		}
+		if !curfunc.abi_tag.is_empty() && (!curfunc.operator.is_empty() || curfunc.is_ctor) {
+			self.err_str = format!("function {}: #[abi_tag] conflicts with #[operator] and #[constructor]", curfunc.rust_name);
+			return Err(());
+		}
+		let kls = move_obj(&mut curfunc.klsname);
+		curfunc.set_scope(&ns, &kls);
+
+		if curfunc.is_ctor {
+			// constructs in place, nothing is returned by C++.
+			let kls = curfunc.klsname.rsplit("::").next().unwrap_or_default().to_string();
+			let ok = match &f.sig.output {
+				ReturnType::Type(_, ty) => self.parse_arg_type("", ty)
+					.is_ok_and(|x| x.tp_wrap == "CppBox" && x.tp == kls),
+				ReturnType::Default => false,
+			};
+			if !ok || curfunc.is_static || curfunc.is_const || curfunc.is_async {
+				self.err_str = format!("function {}: a non-async #[constructor] should return CppBox<{}>", curfunc.rust_name, kls);
+				return Err(());
+			}
+			curfunc.ret.is_primitive = true;
+		} else {
+			let output = self.strip_result(&f.sig.output, &mut curfunc)?;
+			curfunc.ret = self.parse_ret(&output)?;
+		}
+		if let Some(cpp) = self.cpp_type_attr(&f.attrs, &curfunc.rust_name)? {
+			if curfunc.is_ctor {
+				self.err_str = format!("function {}: a #[constructor] has no return type for #[cpp_type]", curfunc.rust_name);
+				return Err(());
+			}
+			self.override_cpp_type(&mut curfunc.ret, &cpp, &curfunc.rust_name)?;
+		}
+
+		for input in &f.sig.inputs {
+			match input {
+				FnArg::Typed(pt) => {
+					let name = pat_name(&pt.pat);
+					let mut arg = self.parse_arg_type(&name, &pt.ty)?;
+					if let Err(_) = self.build_as_c_arg(&mut arg) {
+						let x = move_obj(&mut self.err_str);
+						self.err_str = format!("function {} error: {x}", curfunc.rust_name);
+						return Err(());
+					}
+					if let Some(cpp) = self.cpp_type_attr(&pt.attrs, &curfunc.rust_name)? {
+						self.override_cpp_type(&mut arg, &cpp, &curfunc.rust_name)?;
+					}
+					curfunc.arg_list.push(arg);
+				}
+				FnArg::Receiver(r) => {
+					if curfunc.klsname.is_empty() || curfunc.is_static || curfunc.is_ctor || r.reference.is_none() || curfunc.is_async {
+						self.err_str = format!("function {}: only non-async #[member_of] functions can take `&self` or `&mut self`", curfunc.rust_name);
+						return Err(());
+					}
+					// `&self` is a const member function.
+					if curfunc.is_const && r.mutability.is_some() {
+						self.err_str = format!("function {}: #[const_fn] conflicts with `&mut self`", curfunc.rust_name);
+						return Err(());
+					}
+					curfunc.is_const = r.mutability.is_none();
+					curfunc.is_method = true;
+				}
+			}
+		}
+
+		// a reference borrows from the object, a `&self` method ties their lifetimes.
+		let ret = &curfunc.ret;
+		if ret.tp_full.starts_with('&') && (!curfunc.is_method || curfunc.is_result || !matches!(ret.tp_wrap.as_str(), ""|"CxxVector")
+			|| matches!(ret.tp.as_str(), "str"|"CStr")) {
+			self.err_str = format!("function {}: only methods with `&self` or `&mut self` can return a reference like {}", curfunc.rust_name, ret.raw_str);
+			return Err(());
+		}
+
+		self.funcs.push(curfunc);
+		Ok(())
+	}
+
+	pub fn parse_ts(self: &mut Self, input: TokenStream) -> Result<(), &str> {
+		let fm: ItemForeignMod = match syn::parse2(input) {
+			Ok(x) => x,
+			Err(e) => {
+				self.err_str = format!("macro expect extern \"C\"/\"C++\" {{...}}: {}", e);
+				return Err(&self.err_str);
+			}
+		};
+		self.is_cpp = fm.abi.name.as_ref().map(|n| n.value() == "C++").unwrap_or(false);
+
+		for item in &fm.items {
+			match item {
+				ForeignItem::Fn(f) => {
+					if let Err(_) = self.parse_fn(f) {
+						return Err(&self.err_str);
+					}
+				}
+				_ => {
+					self.err_str = "only function declarations are supported inside the bridge block".to_string();
+					return Err(&self.err_str);
+				}
+			}
+		}
+		Ok(())
+	}
+}
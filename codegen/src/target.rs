@@ -0,0 +1,105 @@
+// The target the bridge code is generated for. A proc macro runs on the host, and cargo sets
+// CARGO_CFG_TARGET_* only for build scripts: directcpp_build::forward_target() passes them
+// on to the macro. Without them the host is assumed, and the generated code fails to compile
+// where that guess is wrong.
+use std::env;
+use proc_macro2::TokenStream;
+use quote::quote;
+use crate::mangle::StdLib;
+use crate::util::select_val;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Target {
+	pub windows: bool,  // the MSVC ABI
+	pub macos: bool,
+	pub is64: bool,
+	pub i386: bool,  // 32-bit x86 aligns i64/f64 to 4, except on windows
+	pub stdlib: StdLib,  // of the Itanium ABI, the MSVC one has its own
+}
+
+impl Default for Target {
+	fn default() -> Self {
+		Self::host()
+	}
+}
+
+/// libc++ is the system C++ library of these, libstdc++ of the others.
+fn default_stdlib(os: &str) -> StdLib {
+	select_val(matches!(os, "macos"|"ios"|"tvos"|"watchos"|"visionos"|"freebsd"|"openbsd"|"android"),
+		StdLib::Libcxx, StdLib::Libstdcxx)
+}
+
+impl Target {
+	/// From the values of `CARGO_CFG_TARGET_ARCH` and `CARGO_CFG_TARGET_OS`,
+	/// e.g. `Target::new("aarch64", "macos")`.
+	pub fn new(arch: &str, os: &str) -> Self {
+		let is64 = matches!(arch, "x86_64"|"aarch64"|"arm64ec"|"riscv64"|"powerpc64"|"mips64"|"s390x"|"loongarch64"|"sparc64");
+		Self{windows: os == "windows", macos: os == "macos", is64, i386: arch == "x86", stdlib: default_stdlib(os)}
+	}
+
+	fn host() -> Self {
+		Self {
+			windows: cfg!(windows),
+			macos: cfg!(target_os = "macos"),
+			is64: cfg!(target_pointer_width = "64"),
+			i386: cfg!(target_arch = "x86"),
+			stdlib: default_stdlib(env::consts::OS),
+		}
+	}
+
+	/// The target of the crate being built. `DIRECTCPP_STDLIB=libc++` or `libstdc++` picks
+	/// the C++ library, e.g. for clang with `-stdlib=libc++` on linux.
+	pub fn current() -> Result<Self, String> {
+		let host = Self::host();
+		let os = env::var("CARGO_CFG_TARGET_OS").ok();
+		let is64 = env::var("CARGO_CFG_TARGET_POINTER_WIDTH").ok().map(|x| x == "64");
+		let i386 = env::var("CARGO_CFG_TARGET_ARCH").ok().map(|x| x == "x86");
+		let stdlib = match env::var("DIRECTCPP_STDLIB").as_deref() {
+			Ok("libc++") => StdLib::Libcxx,
+			Ok("libstdc++") => StdLib::Libstdcxx,
+			Ok("") | Err(_) => os.as_deref().map(default_stdlib).unwrap_or(host.stdlib),
+			Ok(x) => return Err(format!("DIRECTCPP_STDLIB is `{}`, expect `libc++` or `libstdc++`", x)),
+		};
+		Ok(Self {
+			windows: os.as_ref().map(|x| x == "windows").unwrap_or(host.windows),
+			macos: os.as_ref().map(|x| x == "macos").unwrap_or(host.macos),
+			is64: is64.unwrap_or(host.is64),
+			i386: i386.unwrap_or(host.i386),
+			stdlib,
+		})
+	}
+
+	/// Whether `current()` saw the target, not just the host.
+	pub fn is_forwarded() -> bool {
+		env::var_os("CARGO_CFG_TARGET_OS").is_some()
+	}
+
+	/// Nothing when the target is forwarded, otherwise a `compile_error!` for the targets
+	/// this host guess doesn't match.
+	pub fn guess_check(&self) -> TokenStream {
+		if Self::is_forwarded() {
+			return TokenStream::new();
+		}
+		let os = match (self.windows, self.macos) {
+			(true, _) => quote!(target_os = "windows"),
+			(_, true) => quote!(target_os = "macos"),
+			_ => quote!(not(any(target_os = "windows", target_os = "macos"))),
+		};
+		let width = select_val(self.is64, "64", "32");
+		let arch = select_val(self.i386, quote!(target_arch = "x86"), quote!(not(target_arch = "x86")));
+		quote! {
+			#[cfg(not(all(#os, target_pointer_width = #width, #arch)))]
+			compile_error!("directcpp: the target isn't known to the macro, call directcpp_build::forward_target() from build.rs");
+		}
+	}
+
+	/// The size of a pointer.
+	pub fn ptr_size(&self) -> usize {
+		select_val(self.is64, 8, 4)
+	}
+
+	/// The alignment of `i64`, `u64` and `f64`: 4 on i686 linux, macOS and the BSDs.
+	pub fn align8(&self) -> usize {
+		select_val(self.i386 && !self.windows, 4, 8)
+	}
+}
@@ -0,0 +1,1221 @@
+#![cfg(test)]
+
+use proc_macro2::TokenStream;
+use crate::buildcode::FFIBuilder;
+use crate::mangle::{SimpArg, SimpFunc, StdLib};
+use crate::target::Target;
+use crate::util::select_val;
+
+macro_rules! win_posix {
+    ($win:expr, $posix:expr) => {{
+        #[cfg(windows)]
+        { $win }
+        #[cfg(not(windows))]
+        { $posix }
+    }};
+}
+
+fn to_string(ts: TokenStream) -> String {
+	let ins = ts.to_string();
+	let mut outs = String::with_capacity(ins.len());
+	let mut last_ch = None;
+	let mut need_sp = false;
+	let mut in_quote = 0;
+	for ch in ins.chars() {
+		if (ch == '\'') && (in_quote == 0 || in_quote == 1) {
+			in_quote = select_val(in_quote == 0, 1, 0);
+			outs.push(ch);
+			need_sp = false;
+		} else if (ch == '"') && (in_quote == 0 || in_quote == 2) {
+			if in_quote == 0 && need_sp {
+				if let Some(ch1) = last_ch {
+					outs.push(ch1);
+				}
+			}
+			in_quote = select_val(in_quote == 0, 2, 0);
+			outs.push(ch);
+			need_sp = false;
+		} else if ch == '[' && in_quote == 0 {
+			in_quote = 3;
+			outs.push(ch);
+			need_sp = false;
+		} else if ch == ']' && in_quote == 3 {
+			in_quote = 0;
+			outs.push(ch);
+			need_sp = false;
+		} else if in_quote == 1 || in_quote == 2 {
+			outs.push(ch);
+			last_ch = None;
+		} else if ch.is_ascii_whitespace() {
+			last_ch = Some(ch);
+			continue;
+		} else if ch != '_' && ch.is_ascii_punctuation() {
+			outs.push(ch);
+			if ch == '{' || ch == '}' || (ch == ';' && in_quote==0) {
+				outs.push('\n');
+			}
+			last_ch = None;
+			need_sp = false;
+		} else {
+			if let Some(ch1) = last_ch {
+				if need_sp {
+					outs.push(ch1);
+				}
+			}
+			outs.push(ch);
+			last_ch = None;
+			need_sp = true;
+		}
+	}
+	outs
+}
+
+#[test]
+fn test_retvec() {
+	let input = quote::quote! {
+		extern "C++" {
+			pub fn init_asset_config(url: &CStr, olddata: &[u8]) -> Vec<u8>;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input).unwrap());
+	println!("{}", os);
+
+	let mut func = SimpFunc::default();
+	func.set_scope("ffi", "");
+	func.fn_name = "man_dtor".to_string();
+	func.template_types.push("RustVec<uint8_t>".to_string());
+	func.ret.is_primitive = true;
+	let mut arg = SimpArg::default();
+	arg.tp_cpp = "void*".to_string();
+	func.arg_list.push(arg);
+	let s = crate::mangle::GccMangler::new(Target::new("x86_64", "linux")).mangle(&func).unwrap();
+	assert_eq!(s.as_str(), "_ZN3ffi8man_dtorI7RustVecIhEEEvPv");
+}
+
+fn build_ts(input: TokenStream) -> Result<String, String> {
+	let mut bb = FFIBuilder::new(true);
+	match bb.build_bridge_code(input) {
+		Ok(s) => Ok(to_string(s)),
+		Err(e) => Err(e.to_string()),
+	}
+}
+
+#[test]
+fn test_cstr() {
+	let input = quote::quote! {
+		extern "C++" {
+			#[namespace(ns_foo::ns_bar)]
+			pub fn cpp_ptr(foo:&CStr, bar:&str, baz:&[u8]) -> i32;
+		}
+	};
+	let name = win_posix!("?cpp_ptr@ns_bar@ns_foo@@YAHPEBD0_KPEBE1@Z", "_ZN6ns_foo6ns_bar7cpp_ptrEPKcS2_mPKhm");
+	let expect = quote::quote! {
+		extern "C" {
+			#[link_name=#name]
+			fn ffi__cpp_ptr(foo:*const i8,bar:*const u8,bar_len:usize,baz:*const u8,baz_len:usize)->i32;
+		}
+		#[inline(never)]
+		pub fn cpp_ptr(foo:&CStr,bar:&str,baz:&[u8])->i32 {
+			unsafe { ffi__cpp_ptr(foo.as_ptr(),bar.as_ptr(),bar.len(),baz.as_ptr(),baz.len()) }
+		}
+	};
+	assert_eq!(build_ts(input).unwrap(), to_string(expect));
+}
+
+#[test]
+fn test_link_name_verified() {
+	// `Proof` is known from the member function scope, so the argument must refer back to it.
+	let input = quote::quote! {
+		extern "C++" {
+			#[member_of(Proof)]
+			pub fn merge(other: CPtr<Proof>, data: &Vec<u8>, more: &Vec<u8>);
+		}
+	};
+	let name = win_posix!("?merge@Proof@@QEAAXPEAV1@AEBU?$RustVec@E@@1@Z", "_ZN5Proof5mergeEPS_RK7RustVecIhES4_");
+	let os = build_ts(input).unwrap();
+	assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{}", os);
+}
+
+#[test]
+fn test_header() {
+	let input = quote::quote! {
+		extern "C++" {
+			pub fn hdr_magic(magic: &HdrIn, name: &str) -> HdrOut;
+			#[namespace(myns)]
+			pub fn hdr_message() -> String;
+			pub fn hdr_widget(id: i64) -> SharedPtr<Widget>;
+			pub async fn hdr_slow(val: i32) -> String;
+			#[member_of(Widget)]
+			pub fn Resize(w: u32, h: u32) -> bool;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	bb.build_bridge_code(input).unwrap();
+	let expect = r#"// Generated by directcpp #[bridge], do not edit.
+#pragma once
+#include "rust/rust-spt.h"
+
+struct HdrIn;
+struct HdrOut;
+class Widget;
+
+HdrOut hdr_magic(const HdrIn& magic, const char* name, size_t name_len);
+
+namespace myns {
+	RustString hdr_message();
+}
+
+std::shared_ptr<Widget> hdr_widget(int64_t id);
+void hdr_slow(ValuePromise<RustString>* promise, int val);
+
+// member functions can only be declared by the class itself, they should read:
+// class Widget {
+// 	bool Resize(uint32_t w, uint32_t h);
+// };
+
+#define DIRECTCPP_BRIDGE_ENABLE() do { \
+	ffi::force_ref<HdrOut (*)(const HdrIn&, const char*, size_t)>(&hdr_magic); \
+	ffi::enable_class<HdrOut>(); \
+	ffi::force_ref<RustString (*)()>(&myns::hdr_message); \
+	ffi::enable_class<RustString>(); \
+	ffi::force_ref<std::shared_ptr<Widget> (*)(int64_t)>(&hdr_widget); \
+	ffi::enable_class_sp<Widget>(); \
+	ffi::force_ref<void (*)(ValuePromise<RustString>*, int)>(&hdr_slow); \
+	ffi::force_ref<bool (Widget::*)(uint32_t, uint32_t)>(&Widget::Resize); \
+} while (0)
+"#;
+	assert_eq!(bb.header_code(), expect);
+
+	// #[bridge(header = "...")] writes the block with the macros named after the file.
+	let path = std::env::temp_dir().join(format!("directcpp_{}/hdr_test.h", std::process::id()));
+	let path_str = path.to_str().unwrap();
+	assert_eq!(crate::header::header_path(path_str).unwrap(), path);
+	assert!(crate::header::write_header(&path, bb.cpp_header()).unwrap());
+	assert!(!crate::header::write_header(&path, bb.cpp_header()).unwrap());
+	let code = std::fs::read_to_string(&path).unwrap();
+	assert_eq!(code, expect.replace("DIRECTCPP_BRIDGE_ENABLE", "HDR_TEST_ENABLE"));
+	let _ = std::fs::remove_dir_all(path.parent().unwrap());
+}
+
+#[test]
+fn test_layout_check() {
+	let input = quote::quote! {
+		#[repr(C)]
+		struct LayoutIn {
+			ivalue: i32,
+			fvalue: f32,
+			svalue: String,
+			flag: u8,
+		}
+	};
+	let out = crate::layout::layout_check(input, Target::new("x86_64", "linux")).unwrap().to_string().replace(' ', "");
+	assert!(out.contains("size_of::<LayoutIn>()==40"), "{}", out);
+	assert!(out.contains("align_of::<LayoutIn>()==8"), "{}", out);
+	assert!(out.contains("offset_of!(LayoutIn,svalue)==8"), "{}", out);
+	assert!(out.contains("offset_of!(LayoutIn,flag)==32"), "{}", out);
+
+	// another struct as a field: its size and align come from rust at compile time.
+	let input = quote::quote! {
+		#[repr(C)]
+		struct LayoutOuter {
+			tag: u16,
+			inner: [LayoutIn; 2],
+		}
+	};
+	let out = crate::layout::layout_check(input, Target::new("x86_64", "linux")).unwrap().to_string().replace(' ', "");
+	assert!(out.contains("off=off.next_multiple_of(std::mem::align_of::<[LayoutIn;2]>());"), "{}", out);
+	assert!(out.contains("offset_of!(LayoutOuter,inner)==off"), "{}", out);
+	assert!(out.contains("size_of::<LayoutOuter>()==off.next_multiple_of(align)"), "{}", out);
+
+	let input = quote::quote! {
+		struct NotC { a: i32 }
+	};
+	assert!(crate::layout::layout_check(input, Target::new("x86_64", "linux")).is_err());
+	let input = quote::quote! {
+		#[repr(C)]
+		struct NoLayout { a: Option<i32> }
+	};
+	assert!(crate::layout::layout_check(input, Target::new("x86_64", "linux")).is_err());
+}
+
+#[test]
+fn test_async() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub async fn future_int() -> i32;
+		}
+	};
+	let name = win_posix!("?future_int@@YAXPEAU?$ValuePromise@H@@@Z", "_Z10future_intP12ValuePromiseIiE");
+	// cpp type: void future_int(ValuePromise<int>* ret, ...);
+	let expect = quote::quote! {
+		extern "C"{
+			#[link_name=#name]
+			fn ffi__future_int(addr: usize);
+		}
+		#[inline(never)]
+		pub async fn future_int() -> i32 {
+			let mut fv=FutureValue::<i32>::default();
+			unsafe {
+				let dyn_fv_addr=fv.to_ptr();
+				ffi__future_int(dyn_fv_addr);
+			}
+			fv.await
+		}
+	};
+	let r1s = build_ts(input_ts).unwrap();
+	println!("{}", r1s);
+	assert_eq!(r1s, to_string(expect));
+}
+
+#[test]
+fn test_pod() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn get_logger() -> POD<DynLogger>;
+		}
+	};
+	// returned in registers as C does, no hidden pointer.
+	let link_name = win_posix!("?get_logger@@YA?AUDynLogger@@XZ", "_Z10get_loggerv");
+	let expected = quote::quote! {
+		extern "C"{
+			#[link_name=#link_name]
+			fn ffi__get_logger()->DynLogger;
+		}
+		#[inline(never)]
+		pub fn get_logger()->DynLogger{
+			unsafe{
+				ffi__get_logger()
+			}
+		}
+	};
+	assert_eq!(build_ts(input_ts).unwrap(), to_string(expected));
+}
+
+#[test]
+fn it_works() {
+	let ts = quote::quote!(
+		extern "C++" {
+			pub fn cpp_ptr(xx:i32) -> SharedPtr<CppStruct>;
+		}
+	);
+	#[cfg(windows)]
+	let exptected = quote! {
+		extern "C" {
+			#[link_name = "?cpp_ptr@@YA?AV?$shared_ptr@VCppStruct@@@std@@H@Z"]
+			fn ffi__cpp_ptr (__rto : * mut u8 , xx : i32) ;
+			#[link_name = "??$man_dtor_sp@VCppStruct@@@ffi@@YAXPEAX@Z"]
+			fn ffi__freeSP_CppStruct (__o : * mut usize) ;
+		}
+		impl DropSP for CppStruct {
+			unsafe fn __drop_sp (ptr : * mut [u8;0]) {
+				if ptr as usize != 0 {
+					ffi__freeSP_CppStruct (ptr as * mut usize) ;
+				}
+			}
+		}
+		pub fn cpp_ptr (xx : i32) -> SharedPtr<CppStruct> {
+			let mut __rto = SharedPtr::<CppStruct>::default();
+			unsafe {
+				ffi__cpp_ptr (&mut __rto as * mut SharedPtr<CppStruct> as * mut u8, xx);
+			}
+			__rto
+		}
+	};
+	// x86_64 and aarch64 alike: C++ constructs the value through the pointer C uses to
+	// return a big struct, rdi or x8.
+	#[cfg(not(windows))]
+	let expected = quote::quote! {
+		extern "C"{
+			#[link_name="_Z7cpp_ptri"]
+			fn ffi__cpp_ptr(xx:i32)->__Sret_cpp_ptr;
+			#[link_name="_ZN3ffi11man_dtor_spI9CppStructEEvPv"]
+			fn ffi__freeSP_CppStruct(__o:*mut usize);
+		}
+		#[repr(C)]
+		#[allow(non_camel_case_types)]
+		struct __Sret_cpp_ptr([usize;(std::mem::size_of::<SharedPtr<CppStruct>>()+16)/8+1]);
+		impl DropSP for CppStruct{
+			unsafe fn __drop_sp(ptr:*mut[u8;0]){
+				if ptr as usize != 0 {
+					ffi__freeSP_CppStruct(ptr as*mut usize);
+				}
+			}
+		}
+		#[inline(never)]
+		pub fn cpp_ptr(xx:i32) -> SharedPtr<CppStruct> {
+			let __rta=unsafe{ffi__cpp_ptr(xx)};
+			unsafe{std::ptr::read(&__rta as*const _ as*const SharedPtr<CppStruct>)}
+		}
+	};
+	assert_eq!(build_ts(ts).unwrap(), to_string(expected));
+
+	let ts = quote::quote!(
+		extern "C++" {
+			pub fn on_magic(magic: &mut MagicIn, cs: CPtr<CppStruct>) -> MagicOut;
+		}
+	);
+	#[cfg(windows)]
+	let expect = quote::quote! {
+		extern "C" {
+			#[link_name="?on_magic@@YA?AUMagicOut@@AEAUMagicIn@@PEAVCppStruct@@@Z"]
+			fn ffi__on_magic(__rto:*mut usize,magic:*mut MagicIn,cs:*const u8);
+			#[link_name="??$man_dtor@UMagicOut@@@ffi@@YAXPEAX@Z"]
+			fn ffi__free_MagicOut(__o:*mut usize);
+		}
+		pub fn on_magic(magic:&mut MagicIn,cs:CPtr<CppStruct>)->MagicOut{
+			const SZ:usize=(std::mem::size_of::<MagicOut>()+16)/8;
+			let mut __rta:[usize;SZ]=[0;SZ];
+			unsafe {
+				ffi__on_magic(&mut __rta as*mut usize,magic as*mut MagicIn,cs.addr as*const u8);
+			}
+			unsafe {
+				let __rto=(*(&__rta as*const _ as*const MagicOut)).clone();
+				ffi__free_MagicOut(&mut __rta as*mut _ as*mut usize);
+				__rto
+			}
+		}
+	};
+	#[cfg(not(windows))]
+	let expect = quote::quote! {
+		extern "C"{
+			#[link_name="_Z8on_magicR7MagicInP9CppStruct"]
+			fn ffi__on_magic(magic:*mut MagicIn,cs:*const u8)->__Sret_on_magic;
+			#[link_name="_ZN3ffi8man_dtorI8MagicOutEEvPv"]
+			fn ffi__free_MagicOut(__o:*mut usize);
+		}
+		#[repr(C)]
+		#[allow(non_camel_case_types)]
+		struct __Sret_on_magic([usize;(std::mem::size_of::<MagicOut>()+16)/8+1]);
+		#[inline(never)]
+		pub fn on_magic(magic:&mut MagicIn,cs:CPtr<CppStruct>) -> MagicOut {
+			let mut __rta=unsafe{ffi__on_magic(magic as*mut MagicIn,cs.addr as*const u8)};
+			unsafe {
+				let __rto=(*(&__rta as*const _ as*const MagicOut)).clone();
+				ffi__free_MagicOut(&mut __rta as*mut _ as*mut usize);
+				__rto
+			}
+		}
+	};
+	assert_eq!(build_ts(ts).unwrap(), to_string(expect));
+	let ts = quote::quote!(
+		extern "C++" {
+			#[member_of(CppStruct)]
+			pub fn AddString(str: &String);
+			#[member_of(CppStruct)]
+			pub fn get_order(oid: i32) -> UserOrder;
+			pub fn fooo(xx:i32)->SharedPtr<CppStruct>;
+			pub fn start_ctp(ac: &AccountInfo) -> i32;
+			pub fn set_log_verbose(v: i32);
+			pub fn new_user_order_c(order: &UserOrder, xxx: i32) -> UserOrderResp;
+		}
+	);
+	println!("{}", build_ts(ts).unwrap());
+}
+
+#[test]
+fn test_misc() {
+	let ts = quote::quote!(
+		extern "C++" {
+			pub fn cpp_ptr(xx:&Vec<&str>) -> SharedPtr<CppStruct>;
+			pub fn cxx_rebuild_blob(old: &[u8], ss:&Vec<&str>) -> Vec<u8>;
+		}
+	);
+	match build_ts(ts) {
+		Ok(s) => println!("{}", s),
+		Err(s) => {
+			panic!("\x1b[1;31mERROR\x1b[0m: {}", s);
+		},
+	}
+}
+#[test]
+fn test_catch() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn may_throw(v: i32) -> Result<i32, CppError>;
+		}
+	};
+	// bool ffi::catch_call<ffi_catch::may_throw>(void*, void**, ffi::CppError*)
+	let name = win_posix!("??$catch_call@Umay_throw@ffi_catch@@@ffi@@YA_NPEAXPEAPEAXPEAUCppError@0@@Z",
+		"_ZN3ffi10catch_callIN9ffi_catch9may_throwEEEbPvPS3_PNS_8CppErrorE");
+	let expect = quote::quote! {
+		extern "C" {
+			#[link_name=#name]
+			fn ffi__may_throw(__ret: *mut u8, __args: *const *const u8, __err: *mut CppError) -> bool;
+		}
+		#[inline(never)]
+		pub fn may_throw(v: i32) -> Result<i32, CppError> {
+			let __v0 = v;
+			let __args: [*const u8; 1] = [&__v0 as *const _ as *const u8];
+			let mut __err = CppError::default();
+			let mut __rtv = std::mem::MaybeUninit::<i32>::uninit();
+			if !unsafe { ffi__may_throw(__rtv.as_mut_ptr() as *mut u8, __args.as_ptr(), &mut __err) } {
+				return Err(__err);
+			}
+			Ok(unsafe { __rtv.assume_init() })
+		}
+	};
+	assert_eq!(build_ts(input_ts).unwrap(), to_string(expect));
+
+	let input = quote::quote! {
+		extern "C++" {
+			#[member_of(Proof)]
+			pub fn describe(name: &str) -> Result<String, CppError>;
+			pub fn widget() -> Result<SharedPtr<Widget>, CppError>;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input).unwrap());
+	assert!(os.contains("ffi__free_RustString(&mut __rta as*mut usize);"), "{}", os);
+	assert!(os.contains("let mut __rto=SharedPtr::<Widget>::default();"), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("#include \"rust/rust-catch.h\""), "{}", hdr);
+	assert!(hdr.contains("#define DIRECTCPP_BRIDGE_CATCH() \\\n\
+		\tDIRECTCPP_CATCH(Proof__describe, static_cast<RustString (Proof::*)(const char*, size_t)>(&::Proof::describe)); \\\n\
+		\tDIRECTCPP_CATCH(widget, static_cast<std::shared_ptr<Widget> (*)()>(&::widget))\n"), "{}", hdr);
+
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn bad_err() -> Result<i32, String>;
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+#[test]
+fn test_rustfn() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn on_event(cb: RustFn<(i32, String), ()>);
+		}
+	};
+	let name = win_posix!("?on_event@@YAXAEAU?$RustFn@$$A6AXHAEBURustString@@@Z@@@Z",
+		"_Z8on_eventR6RustFnIFviRK10RustStringEE");
+	let expect = quote::quote! {
+		extern "C" {
+			#[link_name=#name]
+			fn ffi__on_event(cb: *mut u8);
+		}
+		#[inline(never)]
+		pub fn on_event(mut cb: RustFn<(i32,String),()>) {
+			unsafe { ffi__on_event(&mut cb as *mut RustFn<(i32,String),()> as *mut u8) }
+		}
+	};
+	assert_eq!(build_ts(input_ts).unwrap(), to_string(expect));
+
+	let input = quote::quote! {
+		extern "C++" {
+			pub fn apply(v: i32, f: RustFn<(i32,), i32>, g: RustFn<(), bool>) -> i32;
+			pub fn twice(a: RustFn<(String, String), u8>, b: &String) -> i32;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input).unwrap());
+	let name = win_posix!("?apply@@YAHHAEAU?$RustFn@$$A6AHH@Z@@AEAU?$RustFn@$$A6A_NXZ@@@Z", "_Z5applyiR6RustFnIFiiEERS_IFbvEE");
+	assert!(os.contains(name), "{}", os);
+	let name = win_posix!("?twice@@YAHAEAU?$RustFn@$$A6AEAEBURustString@@0@Z@@AEBURustString@@@Z",
+		"_Z5twiceR6RustFnIFhRK10RustStringS2_EES2_");
+	assert!(os.contains(name), "{}", os);
+	assert!(bb.header_code().contains("int apply(int v, RustFn<int(int)>& f, RustFn<bool()>& g);"));
+
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn bad_ret(cb: RustFn<(i32,), String>);
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_methods() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[member_of(Proof)]
+			pub fn AddString(&mut self, s: &String);
+			#[member_of(Proof)]
+			pub fn Count(&self, tag: &str) -> i32;
+		}
+	};
+	let name1 = win_posix!("?AddString@Proof@@QEAAXAEBURustString@@@Z", "_ZN5Proof9AddStringERK10RustString");
+	let name2 = win_posix!("?Count@Proof@@QEBAHPEBD_K@Z", "_ZNK5Proof5CountEPKcm");
+	let expect = quote::quote! {
+		extern "C" {
+			#[link_name=#name1]
+			fn ffi__Proof__AddString(this__: *const u8, s: *const String);
+			#[link_name=#name2]
+			fn ffi__Proof__Count(this__: *const u8, tag: *const u8, tag_len: usize) -> i32;
+		}
+		#[inline(never)]
+		pub fn Proof__AddString(this__: CPtr<Proof>, s: &String) {
+			unsafe { ffi__Proof__AddString(this__.addr as *const u8, s as *const String) }
+		}
+		#[inline(never)]
+		pub fn Proof__Count(this__: CPtr<Proof>, tag: &str) -> i32 {
+			unsafe { ffi__Proof__Count(this__.addr as *const u8, tag.as_ptr(), tag.len()) }
+		}
+		pub trait ProofMethods {
+			fn AddString(&self, s: &String);
+			fn Count(&self, tag: &str) -> i32;
+		}
+		impl ProofMethods for CPtr<Proof> {
+			fn AddString(&self, s: &String) { Proof__AddString(self.clone(), s) }
+			fn Count(&self, tag: &str) -> i32 { Proof__Count(self.clone(), tag) }
+		}
+	};
+	assert_eq!(build_ts(input_ts).unwrap(), to_string(expect));
+
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn not_member(&self);
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_const_fn() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[namespace(ns)]
+			#[member_of(Widget)]
+			#[const_fn]
+			pub fn Size() -> u32;
+			#[namespace(ns)]
+			#[member_of(Widget)]
+			pub fn Name(&self, full: bool) -> String;
+		}
+	};
+	let os = build_ts(input_ts).unwrap();
+	let name = win_posix!("?Size@Widget@ns@@QEBAIXZ", "_ZNK2ns6Widget4SizeEv");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{}", os);
+	let name = win_posix!("?Name@Widget@ns@@QEBA?AURustString@@_N@Z", "_ZNK2ns6Widget4NameEb");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{}", os);
+
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[const_fn]
+			pub fn free_fn();
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[member_of(Widget)]
+			#[const_fn]
+			pub fn Touch(&mut self);
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_static_of() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[static_of(Proof)]
+			pub fn create(v: i32) -> CPtr<Proof>;
+			#[namespace(ns)]
+			#[static_of(Widget)]
+			pub fn Count() -> u32;
+		}
+	};
+	let os = build_ts(input_ts).unwrap();
+	let name = win_posix!("?create@Proof@@SAPEAV1@H@Z", "_ZN5Proof6createEi");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{}", os);
+	let name = win_posix!("?Count@Widget@ns@@SAIXZ", "_ZN2ns6Widget5CountEv");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{}", os);
+	assert!(os.contains("fn ffi__Proof__create(v:i32)->*const u8;"), "{}", os);
+	assert!(os.contains("impl Proof{\npub fn create(v:i32)->CPtr<Proof>{\nProof__create(v)}\n}"), "{}", os);
+	assert!(os.contains("impl Widget{\npub fn Count()->u32{\nWidget__Count()}\n}"), "{}", os);
+
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[static_of(Proof)]
+			pub fn create(&self);
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_nested_scope() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[namespace(a::v2)]
+			#[member_of(Outer::Inner)]
+			pub fn size(&self) -> usize;
+			#[namespace(a)]
+			#[abi_tag("cxx11")]
+			pub fn label(id: i32) -> i32;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?size@Inner@Outer@v2@a@@QEBA_KXZ", "_ZNK1a2v25Outer5Inner4sizeEv");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{}", os);
+	let name = win_posix!("?label@a@@YAHH@Z", "_ZN1a5labelB5cxx11Ei");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{}", os);
+	assert!(os.contains("impl InnerMethods for CPtr<Inner>"), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("namespace a {\n\t[[gnu::abi_tag(\"cxx11\")]] int label(int id);\n}"), "{}", hdr);
+	assert!(hdr.contains("ffi::force_ref<int (*)(int)>(&a::label);"), "{}", hdr);
+	assert!(hdr.contains("// struct a::v2::Outer::Inner {\n// \tsize_t size() const;\n"), "{}", hdr);
+
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[abi_tag(cxx11)]
+			pub fn bad_tag();
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_constructor() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[constructor(Proof, size = 64, align = 8)]
+			pub fn new(count: i32, name: &str) -> CppBox<Proof>;
+			#[constructor(Proof)]
+			pub fn empty() -> CppBox<Proof>;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("??0Proof@@QEAA@HPEBD_K@Z", "_ZN5ProofC1EiPKcm");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__Proof__new(this__:*mut u8,count:i32,name:*const u8,name_len:usize);", name)), "{}", os);
+	let name = win_posix!("??0Proof@@QEAA@XZ", "_ZN5ProofC1Ev");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__Proof__empty(this__:*mut u8);", name)), "{}", os);
+	let name = win_posix!("??1Proof@@QEAA@XZ", "_ZN5ProofD1Ev");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__destruct_Proof(this__:*mut u8);", name)), "{}", os);
+	assert!(os.contains("impl CppClass for Proof{\nconst SIZE:usize=64;\nconst ALIGN:usize=8;"), "{}", os);
+	assert!(os.contains("let __rtp=CppBox::<Proof>::__alloc();\nunsafe{\nffi__Proof__new(__rtp,count,name.as_ptr(),name.len());"), "{}", os);
+	assert!(os.contains("pub fn empty()->CppBox<Proof>{\nProof__empty()}"), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("static_assert(std::is_constructible<Proof, int, const char*, size_t>::value"), "{}", hdr);
+	assert!(hdr.contains("static_assert(sizeof(Proof) <= 64 && alignof(Proof) <= 8"), "{}", hdr);
+
+	// a wrong return type, or a second size.
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[constructor(Proof)]
+			pub fn new() -> SharedPtr<Proof>;
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[constructor(Proof, size = 64, align = 8)]
+			pub fn new() -> CppBox<Proof>;
+			#[constructor(Proof, size = 64, align = 8)]
+			pub fn new2() -> CppBox<Proof>;
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_operator() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[member_of(Proof)]
+			#[operator(==)]
+			pub fn equals(&self, other: &Proof) -> bool;
+			#[member_of(Proof)]
+			#[operator(<)]
+			pub fn less(&self, other: &Proof) -> bool;
+			#[member_of(Proof)]
+			#[operator([])]
+			pub fn at(&self, idx: u64) -> &i32;
+			#[member_of(Proof)]
+			#[operator(())]
+			pub fn call(&mut self, a: i32, b: i32) -> i32;
+			#[operator(-)]
+			pub fn sub(a: &Point, b: &Point) -> Point;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let names = [
+		win_posix!("??8Proof@@QEBA_NAEBV0@@Z", "_ZNK5ProofeqERKS_"),
+		win_posix!("??MProof@@QEBA_NAEBV0@@Z", "_ZNK5ProofltERKS_"),
+		win_posix!("??AProof@@QEBAAEBH_K@Z", "_ZNK5ProofixEm"),
+		win_posix!("??RProof@@QEAAHHH@Z", "_ZN5ProofclEii"),
+		win_posix!("??G@YA?AUPoint@@AEBU0@0@Z", "_ZmiRK5PointS1_"),
+	];
+	for name in names {
+		assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{} {}", name, os);
+	}
+	assert!(os.contains("fn ffi__Proof__at(this__:*const u8,idx:u64)->*const i32;"), "{}", os);
+	assert!(os.contains("fn at(&self,idx:u64)->&i32{\nunsafe{\n&*Proof__at(self.clone(),idx)}\n}"), "{}", os);
+	assert!(os.contains("impl CppEq for Proof{\nfn __eq(a:&CPtr<Self>,b:&CPtr<Self>)->bool{\nProofMethods::equals(a,unsafe{\n&*(b.addr as*const Proof)}\n)}\n}"), "{}", os);
+	assert!(os.contains("impl CppOrd for Proof{"), "{}", os);
+	assert!(os.contains("impl CppIndex<u64>for Proof{\ntype Output=i32;"), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("bool operator==(const Proof& other) const;"), "{}", hdr);
+	assert!(hdr.contains("(&Proof::operator[]);"), "{}", hdr);
+
+	// references are only returned by methods.
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[member_of(Proof)]
+			pub fn at(idx: u64) -> &i32;
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_cpp_name() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[cpp_name("log")]
+			pub fn log_int(v: i32);
+			#[cpp_name("log")]
+			pub fn log_str(v: &CStr);
+			#[namespace(ns)]
+			#[member_of(Widget)]
+			#[cpp_name("get")]
+			pub fn get_at(&self, idx: u64) -> i32;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?log@@YAXH@Z", "_Z3logi");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__log_int(v:i32);", name)), "{}", os);
+	let name = win_posix!("?log@@YAXPEBD@Z", "_Z3logPKc");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__log_str(v:*const i8);", name)), "{}", os);
+	let name = win_posix!("?get@Widget@ns@@QEBAH_K@Z", "_ZNK2ns6Widget3getEm");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__Widget__get_at(", name)), "{}", os);
+	assert!(os.contains("pub fn log_int(v:i32)"), "{}", os);
+	assert!(os.contains("fn get_at(&self,idx:u64)->i32;"), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("void log(int v);") && hdr.contains("void log(const char* v);"), "{}", hdr);
+
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[cpp_name("not a name")]
+			pub fn log_int(v: i32);
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[member_of(Proof)]
+			#[operator(==)]
+			#[cpp_name("equals")]
+			pub fn eq(&self, other: &Proof) -> bool;
+		}
+	};
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_cpp_type() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn put(#[cpp_type("unsigned long long")] n: u64, #[cpp_type("char")] c: i8, #[cpp_type("ns::Color")] color: i32);
+			#[cpp_type("char16_t")]
+			pub fn next_char(#[cpp_type("const ns::Color &")] c: &i32) -> u16;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?put@@YAX_KDW4Color@ns@@@Z", "_Z3putycN2ns5ColorE");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__put(n:u64,c:i8,color:i32);", name)), "{}", os);
+	let name = win_posix!("?next_char@@YA_SAEBW4Color@ns@@@Z", "_Z9next_charRKN2ns5ColorE");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__next_char(", name)), "{}", os);
+	assert!(os.contains("pub fn next_char(c:&i32)->u16"), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("char16_t next_char(const ns::Color & c);"), "{}", hdr);
+	assert!(!hdr.contains("Color;"), "{}", hdr);
+
+	// the rust type still decides the ABI.
+	for input_ts in [
+		quote::quote! { extern "C++" { pub fn put(#[cpp_type("double")] n: i64); } },
+		quote::quote! { extern "C++" { pub fn put(#[cpp_type("short")] n: i32); } },
+		quote::quote! { extern "C++" { pub fn put(#[cpp_type("int&")] n: i32); } },
+		quote::quote! { extern "C++" { pub fn put(#[cpp_type("Foo")] n: f32); } },
+		quote::quote! { extern "C++" { pub fn put(#[cpp_type("int")] n: &str); } },
+		quote::quote! { extern "C++" { #[cpp_type("int")] pub fn put(); } },
+	] {
+		assert!(build_ts(input_ts).is_err());
+	}
+}
+
+#[test]
+fn test_primitives() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn prims(a: isize, b: usize, c: char) -> c_long;
+			pub fn aliases(a: c_char, b: c_schar, c: c_uchar, d: c_short, e: c_ushort, f: c_int, g: c_uint,
+				h: c_long, i: c_ulong, j: c_longlong, k: c_ulonglong, l: c_float, m: c_double) -> c_ulong;
+			pub fn chars(a: char, b: wchar_t, #[cpp_type("char16_t")] c: u16, #[cpp_type("char8_t")] d: u8) -> char;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?prims@@YAJ_J_K_U@Z", "_Z5primslmDi");
+	assert!(os.contains(&format!("#[allow(improper_ctypes)]#[link_name=\"{}\"]fn ffi__prims(a:isize,b:usize,c:char)->c_long;", name)), "{}", os);
+	let name = win_posix!("?aliases@@YAKDCEFGHIJK_J_KMN@Z", "_Z7aliasescahstijlmxyfd");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__aliases(", name)), "{}", os);
+	let name = win_posix!("?chars@@YA_U_U_W_S_Q@Z", "_Z5charsDiwDsDu");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__chars(", name)), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("long prims(ptrdiff_t a, size_t b, char32_t c);"), "{}", hdr);
+
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn wide(a: i128, b: u128) -> u128;
+		}
+	};
+	#[cfg(not(windows))]
+	{
+		let os = build_ts(input_ts).unwrap();
+		assert!(os.contains("#[link_name=\"_Z4wideno\"]fn ffi__wide(a:i128,b:u128)->u128;"), "{}", os);
+	}
+	#[cfg(windows)]
+	assert!(build_ts(input_ts).is_err());
+}
+
+#[test]
+fn test_raw_ptr() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn set_user_data(cb: i32, data: *mut c_void) -> *const c_void;
+			pub fn open_out(out: *mut *mut Handle, name: *const c_char) -> i32;
+			pub fn read_table(rows: *const *mut f64, keys: *mut *const u8);
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?set_user_data@@YAPEBXHPEAX@Z", "_Z13set_user_dataiPv");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__set_user_data(cb:i32,data:*mut c_void)->*const c_void;", name)), "{}", os);
+	assert!(os.contains("pub fn set_user_data(cb:i32,data:*mut c_void)->*const c_void{\nunsafe{\nffi__set_user_data(cb,data)}\n}"), "{}", os);
+	let name = win_posix!("?open_out@@YAHPEAPEAUHandle@@PEBD@Z", "_Z8open_outPP6HandlePKc");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__open_out(out:*mut*mut Handle,name:*const c_char)->i32;", name)), "{}", os);
+	let name = win_posix!("?read_table@@YAXPEBQEANPEAPEBE@Z", "_Z10read_tablePKPdPPKh");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__read_table(", name)), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("const void* set_user_data(int cb, void* data);"), "{}", hdr);
+	assert!(hdr.contains("int open_out(Handle** out, const char* name);"), "{}", hdr);
+	assert!(hdr.contains("void read_table(double* const* rows, const uint8_t** keys);"), "{}", hdr);
+
+	// only thin pointers to named types.
+	for input_ts in [
+		quote::quote! { extern "C++" { pub fn put(data: *const [u8]); } },
+		quote::quote! { extern "C++" { pub fn put(data: *const Vec<u8>); } },
+	] {
+		assert!(build_ts(input_ts).is_err());
+	}
+}
+
+#[test]
+fn test_slices() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn fill(buf: &mut [u8]) -> usize;
+			pub fn mix(samples: &[f32], out: &mut [i16]);
+			pub fn draw(pts: &[Point], moved: &mut [Point]);
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?fill@@YA_KPEAE_K@Z", "_Z4fillPhm");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__fill(buf:*mut u8,buf_len:usize)->usize;", name)), "{}", os);
+	assert!(os.contains("pub fn fill(buf:&mut[u8])->usize{\nunsafe{\nffi__fill(buf.as_mut_ptr(),buf.len())}\n}"), "{}", os);
+	let name = win_posix!("?mix@@YAXPEBM_KPEAF1@Z", "_Z3mixPKfmPsm");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__mix(samples:*const f32,samples_len:usize,out:*mut i16,out_len:usize);", name)), "{}", os);
+	let name = win_posix!("?draw@@YAXPEBUPoint@@_KPEAU1@1@Z", "_Z4drawPK5PointmPS_m");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__draw(pts:*const Point,pts_len:usize,moved:*mut Point,moved_len:usize);", name)), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("size_t fill(uint8_t* buf, size_t buf_len);"), "{}", hdr);
+	assert!(hdr.contains("void draw(const Point* pts, size_t pts_len, Point* moved, size_t moved_len);"), "{}", hdr);
+
+	// elements are passed as they are, no wrappers.
+	for input_ts in [
+		quote::quote! { extern "C++" { pub fn put(data: &[&str]); } },
+		quote::quote! { extern "C++" { pub fn put(data: &[Vec<u8>]); } },
+	] {
+		assert!(build_ts(input_ts).is_err());
+	}
+}
+
+#[test]
+fn test_arrays() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn transform(m: &[f32; 16], out: &mut [f32; 16]);
+			pub fn hash(digest: &[u8; 32]) -> u32;
+			pub fn corners(pts: &[Quad; 4]);
+			pub fn det(#[cpp_type("const float*")] m: &[f32; 9]) -> f32;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?transform@@YAXAEAY0BA@$$CBMAEAY0BA@M@Z", "_Z9transformRA16_KfRA16_f");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__transform(m:*const f32,out:*mut f32);", name)), "{}", os);
+	assert!(os.contains("pub fn transform(m:&[f32;16],out:&mut[f32;16]){\nunsafe{\nffi__transform(m.as_ptr(),out.as_mut_ptr())}\n}"), "{}", os);
+	let name = win_posix!("?hash@@YAIAEAY0CA@$$CBE@Z", "_Z4hashRA32_Kh");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__hash(digest:*const u8)->u32;", name)), "{}", os);
+	let name = win_posix!("?corners@@YAXAEAY03$$CBUQuad@@@Z", "_Z7cornersRA4_K4Quad");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__corners(pts:*const Quad);", name)), "{}", os);
+	let name = win_posix!("?det@@YAMPEBM@Z", "_Z3detPKf");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__det(m:*const f32)->f32;", name)), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("void transform(const float (&m)[16], float (&out)[16]);"), "{}", hdr);
+	assert!(hdr.contains("void corners(const Quad (&pts)[4]);"), "{}", hdr);
+	assert!(hdr.contains("struct Quad;"), "{}", hdr);
+	assert!(hdr.contains("float det(const float* m);"), "{}", hdr);
+
+	// arrays are only borrowed, and their length must be a literal.
+	for input_ts in [
+		quote::quote! { extern "C++" { pub fn put(data: [u8; 4]); } },
+		quote::quote! { extern "C++" { pub fn put(data: &[u8; N]); } },
+		quote::quote! { extern "C++" { pub fn put(data: &[Vec<u8>; 2]); } },
+		quote::quote! { extern "C++" { pub fn get() -> &'static [u8; 4]; } },
+		quote::quote! { extern "C++" { pub fn put(#[cpp_type("const double*")] data: &[f32; 4]); } },
+	] {
+		assert!(build_ts(input_ts).is_err());
+	}
+}
+
+#[test]
+fn test_template() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[template(f32)]
+			#[cpp_name("clamp_value")]
+			pub fn clamp_f32(v: f32, lo: f32, hi: f32) -> f32;
+			#[template(Tile, 4usize)]
+			pub fn fill_tiles(p: &mut Tile, v: &Tile);
+			#[template(-2, true)]
+			#[namespace(grid)]
+			pub fn offset() -> i64;
+			#[template(i32)]
+			#[member_of(Board)]
+			pub fn get(&self, idx: u32) -> i32;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("??$clamp_value@M@@YAMMMM@Z", "_Z11clamp_valueIfET_S0_S0_S0_");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__clamp_f32(v:f32,lo:f32,hi:f32)->f32;", name)), "{}", os);
+	let name = win_posix!("??$fill_tiles@UTile@@$03@@YAXAEAUTile@@AEBU0@@Z", "_Z10fill_tilesI4TileLm4EEvRT_RKS1_");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__fill_tiles(", name)), "{}", os);
+	let name = win_posix!("??$offset@$0?1$00@grid@@YA_JXZ", "_ZN4grid6offsetILin2ELb1EEElv");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__offset()->i64;", name)), "{}", os);
+	let name = win_posix!("??$get@H@Board@@QEBAHI@Z", "_ZNK5Board3getIiEET_j");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__Board__get(", name)), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("#define DIRECTCPP_BRIDGE_INSTANTIATE() \\\n\ttemplate float clamp_value<float>(float, float, float); \\\n"), "{}", hdr);
+	assert!(hdr.contains("template void fill_tiles<Tile, (size_t)4>(Tile&, const Tile&); \\\n"), "{}", hdr);
+	assert!(hdr.contains("template int64_t grid::offset<-2, true>(); \\\n"), "{}", hdr);
+	assert!(hdr.contains("\ttemplate int Board::get<int>(uint32_t) const\n"), "{}", hdr);
+	assert!(hdr.contains("ffi::force_ref<float (*)(float, float, float)>(&clamp_value<float>);"), "{}", hdr);
+	assert!(hdr.contains("ffi::force_ref<int (Board::*)(uint32_t) const>(&Board::get<int>);"), "{}", hdr);
+	assert!(!hdr.contains("offset()"), "{}", hdr);
+
+	for input_ts in [
+		quote::quote! { extern "C++" { #[template] pub fn put(v: i32); } },
+		quote::quote! { extern "C++" { #[template(Vec<u8>)] pub fn put(v: i32); } },
+		quote::quote! { extern "C++" { #[template(1.5)] pub fn put(v: i32); } },
+		quote::quote! { extern "C++" { #[template(-1u8)] pub fn put(v: i32); } },
+		quote::quote! { extern "C" { #[template(i32)] pub fn put(v: i32); } },
+	] {
+		assert!(build_ts(input_ts).is_err());
+	}
+}
+
+#[test]
+fn test_pod_arg() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn norm(p: POD<Vec2>) -> f32;
+			pub fn lerp(a: POD<Vec2>, b: POD<Vec2>, t: f32) -> POD<Vec2>;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?norm@@YAMUVec2@@@Z", "_Z4norm4Vec2");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__norm(p:Vec2)->f32;", name)), "{}", os);
+	assert!(os.contains("pub fn norm(p:Vec2)->f32{\nunsafe{\nffi__norm(p)}\n}"), "{}", os);
+	let name = win_posix!("?lerp@@YA?AUVec2@@U1@0M@Z", "_Z4lerp4Vec2S_f");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__lerp(", name)), "{}", os);
+	assert!(os.contains("a:Vec2,b:Vec2,t:f32)->Vec2;"), "{}", os);
+	assert!(os.contains("pub fn lerp(a:Vec2,b:Vec2,t:f32)->Vec2{"), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("float norm(Vec2 p);"), "{}", hdr);
+	assert_eq!(hdr.matches("ffi::by_value<Vec2>();").count(), 1, "{}", hdr);
+	assert_eq!(hdr.matches("ffi::by_value_ret<Vec2>();").count(), 1, "{}", hdr);
+
+	// MSVC returns a class from a member function through a hidden pointer.
+	let input_ts = quote::quote! {
+		extern "C++" {
+			#[member_of(Shape)]
+			pub fn center(&self) -> POD<Vec2>;
+		}
+	};
+	let os = build_ts(input_ts).unwrap();
+	let decl = win_posix!("fn ffi__Shape__center(this__:*const u8,__rto:*mut usize);", "fn ffi__Shape__center(this__:*const u8)->Vec2;");
+	assert!(os.contains(decl), "{}", os);
+
+	for input_ts in [
+		// by value without POD<T>, or of a primitive.
+		quote::quote! { extern "C++" { pub fn norm(p: Vec2) -> f32; } },
+		quote::quote! { extern "C++" { pub fn norm(p: POD<f32>) -> f32; } },
+		// a type with a destructor can't be POD<T> too.
+		quote::quote! { extern "C++" { pub fn norm(p: POD<Vec2>) -> f32; pub fn get() -> Vec2; } },
+	] {
+		assert!(build_ts(input_ts).is_err());
+	}
+}
+
+#[test]
+fn test_cxx_types() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn greeting_len(s: &CxxString) -> usize;
+			pub fn make_greeting(name: &str) -> UniquePtr<CxxString>;
+			pub fn sum(v: &CxxVector<i32>, s: &CxxStringView) -> i64;
+			#[member_of(Proof)]
+			pub fn Lengths(&self) -> &CxxVector<i32>;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?sum@@YA_JAEBV?$vector@HV?$allocator@H@std@@@std@@AEBV?$basic_string_view@DU?$char_traits@D@std@@@2@@Z",
+		"_Z3sumRKSt6vectorIiSaIiEERKSt17basic_string_viewIcSt11char_traitsIcEE");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__sum(v:*const CxxVector<i32>,s:*const CxxStringView)->i64;", name)), "{}", os);
+	assert!(os.contains("ffi__sum(v as*const CxxVector<i32>,s as*const CxxStringView)"), "{}", os);
+	// g++ tags a function returning std::string with [abi:cxx11].
+	let name = win_posix!("?make_greeting@@YA?AV?$unique_ptr@V?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@U?$default_delete@V?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@@2@@std@@PEBD_K@Z",
+		"_Z13make_greetingB5cxx11PKcm");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]", name)), "{}", os);
+	assert!(os.contains("fn Lengths(&self)->&CxxVector<i32>{"), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("size_t greeting_len(const std::string& s);"), "{}", hdr);
+	assert!(hdr.contains("int64_t sum(const std::vector<int>& v, const std::string_view& s);"), "{}", hdr);
+	assert!(hdr.contains("// \tconst std::vector<int>& Lengths() const;"), "{}", hdr);
+	assert_eq!(hdr.matches("ffi::enable_cxx_string();").count(), 1, "{}", hdr);
+	assert_eq!(hdr.matches("ffi::enable_cxx_vector<int>();").count(), 1, "{}", hdr);
+
+	for input_ts in [
+		// only by reference, or a std::string owned by UniquePtr.
+		quote::quote! { extern "C++" { pub fn len(s: CxxString) -> usize; } },
+		quote::quote! { extern "C++" { pub fn name() -> CxxString; } },
+		quote::quote! { extern "C++" { pub fn view() -> UniquePtr<CxxStringView>; } },
+		// elements are limited to numbers.
+		quote::quote! { extern "C++" { pub fn sum(v: &CxxVector<String>) -> i64; } },
+		quote::quote! { extern "C++" { pub fn sum(v: CxxVector<i32>) -> i64; } },
+	] {
+		assert!(build_ts(input_ts).is_err());
+	}
+}
+
+#[test]
+fn test_owned_args() {
+	let input_ts = quote::quote! {
+		extern "C++" {
+			pub fn keep_string(s: String) -> usize;
+			pub fn sum_owned(v: Vec<i32>) -> i64;
+		}
+	};
+	let mut bb = FFIBuilder::new(true);
+	let os = to_string(bb.build_bridge_code(input_ts).unwrap());
+	let name = win_posix!("?keep_string@@YA_K$$QEAURustString@@@Z", "_Z11keep_stringO10RustString");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__keep_string(s:*mut String)->usize;", name)), "{}", os);
+	// C++ moves out what it keeps, the rest drops in rust.
+	assert!(os.contains("pub fn keep_string(mut s:String)->usize{\nunsafe{\nffi__keep_string(&mut s as*mut String)}\n}"), "{}", os);
+	let name = win_posix!("?sum_owned@@YA_JU?$RustVec@H@@@Z", "_Z9sum_owned7RustVecIiE");
+	assert!(os.contains(&format!("#[link_name=\"{}\"]fn ffi__sum_owned(v:*mut Vec<i32>)->i64;", name)), "{}", os);
+	// the MSVC callee destroys a by-value argument, elsewhere the caller does.
+	let call = win_posix!("ffi__sum_owned(&mut*std::mem::ManuallyDrop::new(v)as*mut Vec<i32>)", "ffi__sum_owned(&mut v as*mut Vec<i32>)");
+	assert!(os.contains(call), "{}", os);
+	let hdr = bb.header_code();
+	assert!(hdr.contains("size_t keep_string(RustString&& s);"), "{}", hdr);
+	assert!(hdr.contains("int64_t sum_owned(RustVec<int> v);"), "{}", hdr);
+}
+
+#[test]
+fn test_targets() {
+	// the target comes from CARGO_CFG_TARGET_*, so every one can be checked on any host.
+	let build = |arch: &str, os: &str| {
+		let input_ts = quote::quote! {
+			extern "C++" {
+				pub fn cpp_ptr(xx: i64, n: usize) -> SharedPtr<CppStruct>;
+				pub fn merge(v: Vec<u8>);
+				#[member_of(Shape)]
+				pub fn center(&self) -> POD<Vec2>;
+			}
+		};
+		let mut bb = FFIBuilder::new(true);
+		bb.set_target(Target::new(arch, os));
+		to_string(bb.build_bridge_code(input_ts).unwrap())
+	};
+	// x86_64 and aarch64 alike: the sret struct, a Vec by address, a POD in registers.
+	let linux = build("x86_64", "linux");
+	assert_eq!(build("aarch64", "linux"), linux);
+	assert!(linux.contains("#[link_name=\"_Z7cpp_ptrlm\"]fn ffi__cpp_ptr(xx:i64,n:usize)->__Sret_cpp_ptr;"), "{}", linux);
+	assert!(linux.contains("pub fn merge(mut v:Vec<u8>){\nunsafe{\nffi__merge(&mut v as*mut Vec<u8>)}"), "{}", linux);
+	assert!(linux.contains("fn ffi__Shape__center(this__:*const u8)->Vec2;"), "{}", linux);
+	// int64_t is long long on macOS.
+	let macos = build("aarch64", "macos");
+	assert_eq!(build("x86_64", "macos"), macos);
+	assert_eq!(macos, linux.replace("_Z7cpp_ptrlm", "_Z7cpp_ptrxm"));
+	let x86 = build("x86", "linux");
+	assert!(x86.contains("#[link_name=\"_Z7cpp_ptrxj\"]"), "{}", x86);
+	// MSVC: the return pointer after `this`, the callee destroys a Vec.
+	let win = build("x86_64", "windows");
+	assert!(win.contains("#[link_name=\"?cpp_ptr@@YA?AV?$shared_ptr@VCppStruct@@@std@@_J_K@Z\"]fn ffi__cpp_ptr(__rto:*mut u8,xx:i64,n:usize);"), "{}", win);
+	assert!(win.contains("ffi__merge(&mut*std::mem::ManuallyDrop::new(v)as*mut Vec<u8>)"), "{}", win);
+	assert!(win.contains("fn ffi__Shape__center(this__:*const u8,__rto:*mut usize);"), "{}", win);
+
+	let layout = |arch: &str, os: &str| {
+		let input = quote::quote! {
+			#[repr(C)]
+			struct WithLong { a: u8, b: c_long }
+		};
+		crate::layout::layout_check(input, Target::new(arch, os)).unwrap().to_string().replace(' ', "")
+	};
+	assert!(layout("x86_64", "linux").contains("size_of::<WithLong>()==16"));
+	assert!(layout("aarch64", "macos").contains("size_of::<WithLong>()==16"));
+	assert!(layout("x86_64", "windows").contains("size_of::<WithLong>()==8"));
+	assert!(layout("x86", "linux").contains("align_of::<WithLong>()==4"));
+	let layout64 = |arch: &str, os: &str| {
+		let input = quote::quote! {
+			#[repr(C)]
+			struct With64 { a: u8, b: i64, c: f64 }
+		};
+		crate::layout::layout_check(input, Target::new(arch, os)).unwrap().to_string().replace(' ', "")
+	};
+	assert!(layout64("x86", "linux").contains("size_of::<With64>()==20"));
+	assert!(layout64("x86", "linux").contains("align_of::<With64>()==4"));
+	assert!(layout64("x86", "windows").contains("size_of::<With64>()==24"));
+	assert!(layout64("arm", "linux").contains("align_of::<With64>()==8"));
+	assert!(layout64("aarch64", "linux").contains("align_of::<With64>()==8"));
+
+	// libc++ on macOS, libstdc++ on linux unless DIRECTCPP_STDLIB says otherwise.
+	let string_len = |target: Target| {
+		let input_ts = quote::quote! {
+			extern "C++" {
+				pub fn greeting_len(s: &CxxString) -> usize;
+			}
+		};
+		let mut bb = FFIBuilder::new(true);
+		bb.set_target(target);
+		let code = to_string(bb.build_bridge_code(input_ts).unwrap());
+		code.split("#[link_name=\"").nth(1).and_then(|x| x.split('"').next()).unwrap().to_string()
+	};
+	let libcxx = "_Z12greeting_lenRKNSt3__112basic_stringIcNS_11char_traitsIcEENS_9allocatorIcEEEE";
+	assert_eq!(string_len(Target::new("x86_64", "linux")), "_Z12greeting_lenRKNSt7__cxx1112basic_stringIcSt11char_traitsIcESaIcEEE");
+	assert_eq!(string_len(Target::new("aarch64", "macos")), libcxx);
+	assert_eq!(string_len(Target{stdlib: StdLib::Libcxx, ..Target::new("x86_64", "linux")}), libcxx);
+
+	// a guessed target fails to compile where it isn't the real one.
+	if !Target::is_forwarded() {
+		let check = to_string(Target::new("x86", "windows").guess_check());
+		assert!(check.contains("#[cfg(not(all(target_os=\"windows\",target_pointer_width=\"32\",target_arch=\"x86\")))]compile_error!"), "{}", check);
+		let check = to_string(Target::new("aarch64", "linux").guess_check());
+		assert!(check.contains("not(any(target_os=\"windows\",target_os=\"macos\")),target_pointer_width=\"64\",not(target_arch=\"x86\")"), "{}", check);
+	}
+}
@@ -1,10 +1,5 @@
-mod mangle;
-mod parse;
-mod util;
-mod tests;
-mod buildcode;
-
-use crate::buildcode::FFIBuilder;
+use directcpp_codegen::buildcode::FFIBuilder;
+use directcpp_codegen::{header, layout, target};
 use std::collections::HashSet;
 use proc_macro::{TokenStream as TS0, TokenTree};
 use std::env;
@@ -16,18 +11,81 @@ extern "C" {
 	fn enable_msvc_debug_c();
 }
 
-#[proc_macro_attribute]
-pub fn bridge(args: TS0, input: TS0) -> TS0 {
+/// Split the attribute arguments into flags and the optional `header = "path"`;
+/// a bare `header` gives an empty path.
+fn parse_flags(args: TS0) -> Result<(HashSet<String>, Option<String>), String> {
 	let mut flags = HashSet::new();
+	let mut header = None;
+	let mut last_flag = String::new();
 	for tt in args.into_iter() {
-		if let TokenTree::Ident(val) = tt {
-			flags.insert(val.to_string());
+		match tt {
+			TokenTree::Ident(val) => {
+				last_flag = val.to_string();
+				if last_flag == "header" {
+					header = Some(String::new());
+				}
+				flags.insert(last_flag.clone());
+			},
+			// header = "path/to/file.h"
+			TokenTree::Literal(val) if last_flag == "header" => {
+				match syn::parse_str::<syn::LitStr>(&val.to_string()) {
+					Ok(s) => header = Some(s.value()),
+					Err(e) => return Err(e.to_string()),
+				}
+			},
+			_ => {}
 		}
 	}
+	Ok((flags, header))
+}
+
+fn error_ts(e: &str) -> TS0 {
+	syn::Error::new(proc_macro2::Span::call_site(), e).to_compile_error().into()
+}
+
+#[proc_macro_attribute]
+pub fn bridge(args: TS0, input: TS0) -> TS0 {
+	let (flags, header) = match parse_flags(args) {
+		Ok(x) => x,
+		Err(e) => return error_ts(&e),
+	};
+	let target = match target::Target::current() {
+		Ok(x) => x,
+		Err(e) => return error_ts(&e),
+	};
 	let mut bb = FFIBuilder::new(! flags.contains("goon") );
-	match bb.build_bridge_code(input.into()) {
-		Ok(code) => code.into(),
-		Err(e) => syn::Error::new(proc_macro2::Span::call_site(), e).to_compile_error().into()
+	bb.set_target(target);
+	let mut code = match bb.build_bridge_code(input.into()) {
+		Ok(code) => code,
+		Err(e) => return error_ts(e),
+	};
+	// only this block: a header for several blocks comes from directcpp_build::Bridge2H.
+	if let Some(path) = header {
+		if let Err(e) = header::header_path(&path).and_then(|x| header::write_header(&x, bb.cpp_header())) {
+			return error_ts(&e);
+		}
+	}
+	code.extend(target.guess_check());
+	code.into()
+}
+
+#[proc_macro_attribute]
+pub fn layout_check(args: TS0, input: TS0) -> TS0 {
+	match parse_flags(args) {
+		Ok((_, Some(_))) => return error_ts("`header` is not an argument of layout_check, the static_asserts are written by directcpp_build::Bridge2H"),
+		Ok(_) => {},
+		Err(e) => return error_ts(&e),
+	}
+	let target = match target::Target::current() {
+		Ok(x) => x,
+		Err(e) => return error_ts(&e),
+	};
+	match layout::layout_check(input.into(), target) {
+		Ok(mut code) => {
+			code.extend(target.guess_check());
+			code.into()
+		},
+		Err(e) => error_ts(&e),
 	}
 }
 
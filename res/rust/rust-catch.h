@@ -0,0 +1,90 @@
+#pragma once
+#include <cstring>
+#include <exception>
+#include <functional>
+#include <new>
+#include <tuple>
+#include <type_traits>
+#include <utility>
+#include "rust-spt.h"
+
+// rust functions declared as returning `Result<T, CppError>` don't call the c++ function directly,
+// they call ffi::catch_call<ffi_catch::NAME>, which calls it inside try/catch so that no exception
+// unwinds into rust frames. NAME is the name of the rust function, e.g. `get_message` or `Proof__foo`.
+// Instantiate it once for each such function, after the function is declared. Refer to the function
+// with a fully qualified name, `ffi_catch::NAME` hides a global function of the same name:
+//     DIRECTCPP_CATCH(get_message, &::myns::get_message);
+//     DIRECTCPP_CATCH(Proof__foo, &::Proof::foo);
+// directcpp_build::Bridge2H and #[bridge(header)] generate these lines as the {GUARD}_CATCH() macro,
+// e.g. PROVE_FFI_CATCH() for prove_ffi.h.
+#define DIRECTCPP_CATCH(name, ...) \
+	namespace ffi_catch { struct name { static constexpr auto func = __VA_ARGS__; }; } \
+	template bool ffi::catch_call<ffi_catch::name>(void* ret, void** args, ffi::CppError* err)
+
+namespace ffi
+{
+	// the same layout as rust struct directcpp::CppError, c++ only fills the message.
+	struct CppError {
+		void (*f_set_what)(CppError* self, const char* what, size_t len);
+
+		void set_what(const char* what) {
+			f_set_what(this, what, strlen(what));
+		}
+	};
+
+	template <class F> struct fn_traits;
+	template <class R, class... A>
+	struct fn_traits<R(*)(A...)> {
+		typedef R ret_t;
+		typedef std::tuple<A...> args_t;
+	};
+	template <class R, class C, class... A>
+	struct fn_traits<R(C::*)(A...)> {
+		typedef R ret_t;
+		typedef std::tuple<C*, A...> args_t;
+	};
+	template <class R, class C, class... A>
+	struct fn_traits<R(C::*)(A...) const> {
+		typedef R ret_t;
+		typedef std::tuple<const C*, A...> args_t;
+	};
+
+	// each argument is passed by the address of the value rust would pass directly,
+	// references are passed as pointers.
+	template <class P>
+	P arg_of(void* ptr) {
+		if constexpr (std::is_reference_v<P>) {
+			return static_cast<P>(**(std::remove_reference_t<P>**)ptr);
+		} else {
+			return *(P*)ptr;
+		}
+	}
+
+	template <class Tag, class Traits, size_t... I>
+	void call_with(void* ret, void** args, std::index_sequence<I...>) {
+		typedef typename Traits::ret_t R;
+		typedef typename Traits::args_t A;
+		if constexpr (std::is_void_v<R>) {
+			std::invoke(Tag::func, arg_of<std::tuple_element_t<I, A>>(args[I])...);
+		} else {
+			new (ret) R(std::invoke(Tag::func, arg_of<std::tuple_element_t<I, A>>(args[I])...));
+		}
+	}
+
+	// returns false with the message set in `err` if the function throws.
+	// the return value is constructed in `ret` only if it returns true.
+	template <class Tag>
+	bool catch_call(void* ret, void** args, CppError* err) noexcept {
+		typedef fn_traits<std::remove_cv_t<decltype(Tag::func)>> Traits;
+		constexpr size_t N = std::tuple_size_v<typename Traits::args_t>;
+		try {
+			call_with<Tag, Traits>(ret, args, std::make_index_sequence<N>());
+			return true;
+		} catch (const std::exception& e) {
+			err->set_what(e.what());
+		} catch (...) {
+			err->set_what("unknown C++ exception");
+		}
+		return false;
+	}
+}
@@ -128,10 +128,21 @@ public:
 static_assert(sizeof(RustOption<uint32_t>) == 8, "sz req");
 static_assert(sizeof(RustOption<RustString>) == 0x18, "sz req");
 
+// the old names, rustc no longer exports __rust_alloc and friends unmangled. kept for the
+// code calling them, static so they don't clash with a rust that still does.
 extern "C" {
-	extern void* __rust_alloc(size_t nbytes, size_t align);
-	extern void __rust_dealloc(void* ptr, size_t size, size_t align);
-	extern void* __rust_realloc(void * ptr, size_t old_size, size_t align, size_t new_size);
+	[[deprecated("use directcpp_rust_alloc")]]
+	static inline void* __rust_alloc(size_t nbytes, size_t align) {
+		return directcpp_rust_alloc(nbytes, align);
+	}
+	[[deprecated("use directcpp_rust_dealloc")]]
+	static inline void __rust_dealloc(void* ptr, size_t size, size_t align) {
+		directcpp_rust_dealloc(ptr, size, align);
+	}
+	[[deprecated("use directcpp_rust_realloc")]]
+	static inline void* __rust_realloc(void * ptr, size_t old_size, size_t align, size_t new_size) {
+		return directcpp_rust_realloc(ptr, old_size, align, new_size);
+	}
 }
 
 #ifndef let
@@ -142,7 +153,7 @@ extern "C" {
 #endif
 
 struct tmp_rust_string : RustString {
-	tmp_rust_string(const string & ss) {
+	tmp_rust_string(const std::string& ss) {
 		data = (char*)ss.c_str();
 		len = cap = ss.size();
 	}
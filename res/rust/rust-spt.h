@@ -3,7 +3,11 @@
 #include <cstring>
 #include <cassert>
 #include <string>
+#include <string_view>
+#include <vector>
 #include <memory>
+#include <functional>
+#include <type_traits>
 
 // NOTE: this should be checked for versions and hosts, in real product this macro can be generated by
 // build script. at least for current version and hosts, this macro is correct.
@@ -22,9 +26,15 @@ struct rust_refstr_t {
 	size_t len;
 };
 
+// the global allocator of rust, exported by the directcpp crate. a buffer handed to or
+// taken from rust must come from here, rust may not use malloc.
+extern "C" void* directcpp_rust_alloc(size_t size, size_t align);
+extern "C" void directcpp_rust_dealloc(void* ptr, size_t size, size_t align);
+extern "C" void* directcpp_rust_realloc(void* ptr, size_t old_size, size_t align, size_t new_size);
+
 // we reimplement the RustVec and RustString in c++ side keeping the same memory layout.
-// so that c++ can readonly process rust structure and vise-versa.
-// note write operations are not allowed because they may using different memory management.
+// so that c++ can process rust structure and vise-versa.
+// both sides share the memory management: the buffers come from rust's global allocator.
 template <typename T>
 struct RustVec {
 	RUST_VEC_CONTENT(T);
@@ -57,12 +67,7 @@ struct RustVec {
 	}
 	void reserve(size_t newcap) {
 		if (cap >= newcap) return;
-		if (void* old_dp = data_ptr(); old_dp) {
-			data = (T*)realloc(old_dp, sizeof(T) * newcap);
-		} else {
-			data = (T*)malloc(sizeof(T) * newcap);
-		}
-		cap = newcap;
+		__grow(newcap);
 	}
 	void resize(size_t newsz) {
 		reserve(newsz);
@@ -105,7 +110,7 @@ struct RustVec {
 		}
 		len = 0;
 		if (free_) {
-			if (data_ptr()) free(data);
+			__free();
 			data = RUST_NULLPTR(T);
 			cap = 0;
 		}
@@ -115,9 +120,7 @@ struct RustVec {
 			// we always assume T is trivially movable. that means moving it from one place to another
 			// never need to call the move constructor, just memcpy is enough.
 			// this is not always true for some types like std::string of gcc libstdc++.
-			cap = std::max<uintptr_t>(cap * 2, 32u);
-			void* old_dp = data_ptr();
-			data = (T*)realloc(old_dp, sizeof(T) * cap);
+			__grow(std::max<uintptr_t>(cap * 2, 32u));
 		}
 		new (data + len) T(t);
 		++len;
@@ -127,12 +130,24 @@ struct RustVec {
 	size_t size() const { return len; }
 	bool empty() const { return len == 0; }
 protected:
+	// the buffer is rust's, `cap` elements of T.
+	void __free() {
+		if (data_ptr() && cap) directcpp_rust_dealloc(data, sizeof(T) * cap, alignof(T));
+	}
+	void __grow(size_t newcap) {
+		if (void* old_dp = data_ptr(); old_dp && cap) {
+			data = (T*)directcpp_rust_realloc(old_dp, sizeof(T) * cap, alignof(T), sizeof(T) * newcap);
+		} else {
+			data = (T*)directcpp_rust_alloc(sizeof(T) * newcap, alignof(T));
+		}
+		cap = newcap;
+	}
 	void __set_from(const T* data0, size_t len0)
 	{
 		// predicate: len is 0.
 		if (cap < len0) {
-			if (data_ptr()) free(data);
-			data = (T*)malloc(sizeof(T) * len0);
+			__free();
+			data = (T*)directcpp_rust_alloc(sizeof(T) * len0, alignof(T));
 			cap = len0;
 		}
 		len = len0;
@@ -148,7 +163,7 @@ protected:
 
 struct RustString : RustVec<char>
 {
-	~RustString() {	if (data_ptr()) free(data); data=0;cap=len=0; }
+	~RustString() {	__free(); data=0;cap=len=0; }
 	RustString() = default;
 	RustString(const RustString& ano) : RustVec<char>() {
 		__set_from(ano.data_ptr(), ano.len);
@@ -179,7 +194,7 @@ struct RustString : RustVec<char>
 		__set_from(ptr, cnt);
 	}
 	RustString& operator=(RustString&& ano) noexcept {
-		if (data_ptr()) free(data);
+		__free();
 		cap = len = 0; data = 0;
 		RustVec<char>::operator=(std::move(ano));
 		return *this;
@@ -217,10 +232,11 @@ struct RustString : RustVec<char>
 protected:
 	void __set_from(const char* data0, size_t len0)
 	{
-		if (cap < len0) {
-			if (data_ptr()) free(data);
-			data = (char*) malloc(len0+1);
-			cap = len0;
+		// one more for the null terminator, which rust counts in the capacity.
+		if (cap <= len0) {
+			__free();
+			data = (char*)directcpp_rust_alloc(len0 + 1, 1);
+			cap = len0 + 1;
 		}
 		len = len0;
 		if (data_ptr()) {
@@ -264,8 +280,66 @@ namespace ffi
 	void enable_class_sp() {
 		force_ref<void(*)(void*)>(&man_dtor_sp<T>);
 	}
+
+	// a POD<T> argument is passed as C passes a struct, which is what C++ does only
+	// for trivially copyable types.
+	template <class T>
+	void by_value() {
+		static_assert(std::is_trivially_copyable<T>::value, "POD<T> arguments must be trivially copyable");
+	}
+	// a returned POD<T> also must have no user-declared constructor, private members, bases or
+	// virtual functions: MSVC returns any other class through a hidden pointer.
+	template <class T>
+	void by_value_ret() {
+		static_assert(std::is_trivially_copyable<T>::value, "POD<T> return values must be trivially copyable");
+		static_assert(std::is_aggregate<T>::value && std::is_standard_layout<T>::value, "POD<T> return values must be C structs");
+	}
 }
 
+// the accessors of CxxString, CxxStringView and CxxVector<T> in rust. they are inline, so
+// ffi::enable_cxx_string() and ffi::enable_cxx_vector<T>() of the generated header emit them.
+extern "C" {
+	inline const char* directcpp_string_data(const std::string* s) { return s->data(); }
+	inline size_t directcpp_string_size(const std::string* s) { return s->size(); }
+	inline void directcpp_string_delete(std::string* s) { delete s; }
+	inline const char* directcpp_string_view_data(const std::string_view* s) { return s->data(); }
+	inline size_t directcpp_string_view_size(const std::string_view* s) { return s->size(); }
+}
+
+namespace ffi
+{
+	inline void enable_cxx_string() {
+		force_ref(&directcpp_string_data);
+		force_ref(&directcpp_string_size);
+		force_ref(&directcpp_string_delete);
+		force_ref(&directcpp_string_view_data);
+		force_ref(&directcpp_string_view_size);
+	}
+	template <class T>
+	void enable_cxx_vector() {
+		static_assert(sizeof(T) == 0, "CxxVector<T> only supports the rust number types");
+	}
+}
+
+#define DIRECTCPP_VECTOR_HELPERS(T, name) \
+	extern "C" inline const T* directcpp_vector_##name##_data(const std::vector<T>* v) { return v->data(); } \
+	extern "C" inline size_t directcpp_vector_##name##_size(const std::vector<T>* v) { return v->size(); } \
+	template <> inline void ffi::enable_cxx_vector<T>() { \
+		ffi::force_ref(&directcpp_vector_##name##_data); \
+		ffi::force_ref(&directcpp_vector_##name##_size); \
+	}
+DIRECTCPP_VECTOR_HELPERS(int8_t, i8)
+DIRECTCPP_VECTOR_HELPERS(uint8_t, u8)
+DIRECTCPP_VECTOR_HELPERS(int16_t, i16)
+DIRECTCPP_VECTOR_HELPERS(uint16_t, u16)
+DIRECTCPP_VECTOR_HELPERS(int, i32)
+DIRECTCPP_VECTOR_HELPERS(uint32_t, u32)
+DIRECTCPP_VECTOR_HELPERS(int64_t, i64)
+DIRECTCPP_VECTOR_HELPERS(uint64_t, u64)
+DIRECTCPP_VECTOR_HELPERS(float, f32)
+DIRECTCPP_VECTOR_HELPERS(double, f64)
+#undef DIRECTCPP_VECTOR_HELPERS
+
 template <class T>
 struct ValuePromise {
 	size_t strong_refc;
@@ -276,3 +350,55 @@ struct ValuePromise {
 		f_set_value(this, &v);
 	}
 };
+
+// a rust closure passed as `RustFn<(A0, A1), R>`, c++ receives it as `RustFn<R(A0, A1)>&`.
+// it owns the closure: move it out to keep it, rust frees what is left when the call returns.
+// arguments are passed by address and cloned by rust; R must be a primitive type or void.
+// calls are not synchronized, don't call the same object from several threads at once.
+template <class Sig> struct RustFn;
+template <class R, class... A>
+struct RustFn<R(A...)> {
+	void* data = nullptr;
+	void (*f_call)(void* data, void* ret, void** args) = nullptr;
+	void (*f_drop)(void* data) = nullptr;
+
+	RustFn() = default;
+	RustFn(const RustFn&) = delete;
+	RustFn(RustFn&& ano) noexcept : data(ano.data), f_call(ano.f_call), f_drop(ano.f_drop) {
+		ano.data = nullptr;
+	}
+	RustFn& operator=(RustFn&& ano) noexcept {
+		if (this != &ano) {
+			reset();
+			data = ano.data;
+			f_call = ano.f_call;
+			f_drop = ano.f_drop;
+			ano.data = nullptr;
+		}
+		return *this;
+	}
+	~RustFn() { reset(); }
+	void reset() {
+		if (data) {
+			f_drop(data);
+			data = nullptr;
+		}
+	}
+	explicit operator bool() const { return data != nullptr; }
+
+	R operator()(A... a) const {
+		void* args[] = { const_cast<void*>(static_cast<const void*>(&a))..., nullptr };
+		if constexpr (std::is_void_v<R>) {
+			f_call(data, nullptr, args);
+		} else {
+			R ret{};
+			f_call(data, &ret, args);
+			return ret;
+		}
+	}
+	// std::function needs a copyable target, so the closure is shared by the copies.
+	operator std::function<R(A...)>() && {
+		auto sp = std::make_shared<RustFn>(std::move(*this));
+		return [sp](A... a) -> R { return (*sp)(a...); };
+	}
+};
@@ -1,5 +1,9 @@
+use std::alloc::{alloc, dealloc, handle_alloc_error, realloc, Layout};
+use std::borrow::Cow;
 use std::future::Future;
-use std::marker::PhantomData;
+use std::marker::{PhantomData, PhantomPinned};
+use std::cmp::Ordering as CmpOrdering;
+use std::ops::{Deref, Index};
 use std::pin::Pin;
 use std::sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}};
 use std::task::{Context, Poll};
@@ -9,7 +13,7 @@ use std::task::{Context, Poll};
 /// use directcpp::bridge;
 /// #[bridge]
 /// extern "C++" {
-/// 	pub fn on_start();
+///     pub fn on_start();
 /// }
 /// ```
 /// This generates the following code:
@@ -34,6 +38,35 @@ pub use directcpp_macro::bridge;
 ///
 pub use directcpp_macro::enable_msvc_debug;
 
+/// Assert the layout of a `#[repr(C)]` struct shared with C++.
+/// # Examples
+/// ```
+/// #[directcpp::layout_check]
+/// #[repr(C)]
+/// struct MagicIn {
+///     ivalue: i32,
+///     fvalue: f32,
+///     svalue: String,
+/// }
+/// ```
+/// The size, alignment and field offsets are asserted at compile time. `directcpp_build::Bridge2H`
+/// writes the same numbers to its header as `static_assert`s, collected in the
+/// `{GUARD}_LAYOUT_CHECK()` macro named after the header file:
+/// ```ignore
+/// // build.rs
+/// let out_dir = std::env::var("OUT_DIR").unwrap();
+/// directcpp_build::Bridge2H::new()
+///     .file("src/main.rs")
+///     .write_to(format!("{out_dir}/ffi.h"))
+///     .unwrap();
+/// ```
+/// ```cpp
+/// #include "ffi.h"
+/// struct MagicIn { int ivalue; float fvalue; RustString svalue; };
+/// FFI_LAYOUT_CHECK();
+/// ```
+pub use directcpp_macro::layout_check;
+
 // implies the value is POD so no dtor is needed, it's just copied.
 #[doc(hidden)]
 pub struct POD<T>(T);
@@ -84,6 +117,12 @@ impl<T> Default for UniquePtr<T> where T: ManDtor {
 		}
 	}
 }
+impl<T> UniquePtr<T> where T: ManDtor {
+	/// The owned object, `None` for a null pointer.
+	pub fn get(&self) -> Option<&T> {
+		unsafe { (self.val1 as *const T).as_ref() }
+	}
+}
 impl<T> Drop for UniquePtr<T> where T: ManDtor {
 	fn drop(&mut self) {
 		unsafe {
@@ -94,13 +133,244 @@ impl<T> Drop for UniquePtr<T> where T: ManDtor {
 	}
 }
 
+/// A C++ `std::string`, only seen behind a reference or in a `UniquePtr`.
+/// Bridged as `&CxxString` (`const std::string&`) and `UniquePtr<CxxString>`.
+#[repr(C)]
+pub struct CxxString {
+	_private: [u8; 0],
+	_pinned: PhantomData<PhantomPinned>,
+}
+
+/// A C++ `std::string_view`, bridged as `&CxxStringView`.
+#[repr(C)]
+pub struct CxxStringView {
+	_private: [u8; 0],
+	_pinned: PhantomData<PhantomPinned>,
+}
+
+/// A C++ `std::vector<T>` of numbers, bridged as `&CxxVector<T>`.
+#[repr(C)]
+pub struct CxxVector<T> where T: CxxVectorElement {
+	_private: [u8; 0],
+	_phantom: PhantomData<(T, PhantomPinned)>,
+}
+
+// the helpers of rust-spt.h, emitted by `ffi::enable_cxx_string()` of the generated header.
+extern "C" {
+	fn directcpp_string_data(s: *const CxxString) -> *const u8;
+	fn directcpp_string_size(s: *const CxxString) -> usize;
+	fn directcpp_string_delete(s: *mut CxxString);
+	fn directcpp_string_view_data(s: *const CxxStringView) -> *const u8;
+	fn directcpp_string_view_size(s: *const CxxStringView) -> usize;
+}
+
+// `RustVec` of rust-spt.h allocates and frees here, with the global allocator of Rust:
+// rustc no longer exports `__rust_alloc` and friends by those names.
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn directcpp_rust_alloc(size: usize, align: usize) -> *mut u8 {
+	let layout = Layout::from_size_align_unchecked(size, align);
+	let ptr = alloc(layout);
+	if ptr.is_null() {
+		handle_alloc_error(layout);
+	}
+	ptr
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn directcpp_rust_dealloc(ptr: *mut u8, size: usize, align: usize) {
+	dealloc(ptr, Layout::from_size_align_unchecked(size, align));
+}
+
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn directcpp_rust_realloc(ptr: *mut u8, old_size: usize, align: usize, new_size: usize) -> *mut u8 {
+	let ptr = realloc(ptr, Layout::from_size_align_unchecked(old_size, align), new_size);
+	if ptr.is_null() {
+		handle_alloc_error(Layout::from_size_align_unchecked(new_size, align));
+	}
+	ptr
+}
+
+/// rust wants a non-null pointer even for an empty slice, C++ may give null.
+unsafe fn cxx_slice<'a, T>(data: *const T, len: usize) -> &'a [T] {
+	if len == 0 {
+		&[]
+	} else {
+		std::slice::from_raw_parts(data, len)
+	}
+}
+
+macro_rules! impl_cxx_string {
+	($tp:ident, $data:ident, $size:ident) => {
+		impl $tp {
+			#[inline]
+			pub fn len(&self) -> usize {
+				unsafe { $size(self) }
+			}
+			#[inline]
+			pub fn is_empty(&self) -> bool {
+				self.len() == 0
+			}
+			#[inline]
+			pub fn as_bytes(&self) -> &[u8] {
+				unsafe { cxx_slice($data(self), $size(self)) }
+			}
+			#[inline]
+			pub fn to_str(&self) -> Result<&str, std::str::Utf8Error> {
+				std::str::from_utf8(self.as_bytes())
+			}
+			#[inline]
+			pub fn to_string_lossy(&self) -> Cow<'_, str> {
+				String::from_utf8_lossy(self.as_bytes())
+			}
+		}
+		impl std::fmt::Display for $tp {
+			#[inline]
+			fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+				f.write_str(&self.to_string_lossy())
+			}
+		}
+		impl std::fmt::Debug for $tp {
+			#[inline]
+			fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+				std::fmt::Debug::fmt(&*self.to_string_lossy(), f)
+			}
+		}
+	};
+}
+impl_cxx_string!(CxxString, directcpp_string_data, directcpp_string_size);
+impl_cxx_string!(CxxStringView, directcpp_string_view_data, directcpp_string_view_size);
+
+impl ManDtor for CxxString {
+	#[inline]
+	unsafe fn __dtor(ptr: *mut [u8;0]) {
+		directcpp_string_delete(ptr as *mut CxxString);
+	}
+}
+
+/// The element types of `CxxVector<T>`, each has its helpers in rust-spt.h.
+#[doc(hidden)]
+pub trait CxxVectorElement: Sized {
+	unsafe fn __data(v: *const CxxVector<Self>) -> *const Self;
+	unsafe fn __size(v: *const CxxVector<Self>) -> usize;
+}
+
+macro_rules! impl_cxx_vector {
+	($($t:ident $data:ident $size:ident),*) => {
+		extern "C" {
+			$(
+				fn $data(v: *const CxxVector<$t>) -> *const $t;
+				fn $size(v: *const CxxVector<$t>) -> usize;
+			)*
+		}
+		$(
+			impl CxxVectorElement for $t {
+				#[inline]
+				unsafe fn __data(v: *const CxxVector<Self>) -> *const Self { $data(v) }
+				#[inline]
+				unsafe fn __size(v: *const CxxVector<Self>) -> usize { $size(v) }
+			}
+		)*
+	};
+}
+impl_cxx_vector!(
+	i8 directcpp_vector_i8_data directcpp_vector_i8_size,
+	u8 directcpp_vector_u8_data directcpp_vector_u8_size,
+	i16 directcpp_vector_i16_data directcpp_vector_i16_size,
+	u16 directcpp_vector_u16_data directcpp_vector_u16_size,
+	i32 directcpp_vector_i32_data directcpp_vector_i32_size,
+	u32 directcpp_vector_u32_data directcpp_vector_u32_size,
+	i64 directcpp_vector_i64_data directcpp_vector_i64_size,
+	u64 directcpp_vector_u64_data directcpp_vector_u64_size,
+	f32 directcpp_vector_f32_data directcpp_vector_f32_size,
+	f64 directcpp_vector_f64_data directcpp_vector_f64_size
+);
+
+impl<T> CxxVector<T> where T: CxxVectorElement {
+	#[inline]
+	pub fn len(&self) -> usize {
+		unsafe { T::__size(self) }
+	}
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+	#[inline]
+	pub fn as_slice(&self) -> &[T] {
+		unsafe { cxx_slice(T::__data(self), T::__size(self)) }
+	}
+}
+
+impl<T> Deref for CxxVector<T> where T: CxxVectorElement {
+	type Target = [T];
+	fn deref(&self) -> &[T] {
+		self.as_slice()
+	}
+}
+
+/// A C++ class with `#[constructor(Class, size = N, align = M)]` functions: the storage
+/// of a `CppBox` and its destructor.
+#[doc(hidden)]
+pub trait CppClass {
+	const SIZE: usize;
+	const ALIGN: usize;
+	unsafe fn __destruct(ptr: *mut [u8;0]);
+}
+
+/// An owned C++ object constructed in rust allocated storage by a `#[constructor]` function.
+/// The destructor runs and the storage is freed on drop.
+#[repr(C)]
+pub struct CppBox<T> where T: CppClass {
+	val1: usize,
+	_phantom: PhantomData<T>,
+}
+impl<T> CppBox<T> where T: CppClass {
+	fn layout() -> Layout {
+		Layout::from_size_align(T::SIZE, T::ALIGN).expect("bad CppClass size or align")
+	}
+	/// Uninitialized storage for a constructor.
+	#[doc(hidden)]
+	pub fn __alloc() -> *mut u8 {
+		let layout = Self::layout();
+		let ptr = unsafe { alloc(layout) };
+		if ptr.is_null() {
+			handle_alloc_error(layout);
+		}
+		ptr
+	}
+	/// Take over the storage from `__alloc` once the constructor has run.
+	#[doc(hidden)]
+	pub unsafe fn __from_raw(ptr: *mut u8) -> Self {
+		Self { val1: ptr as usize, _phantom: PhantomData }
+	}
+}
+impl<T> Drop for CppBox<T> where T: CppClass {
+	fn drop(&mut self) {
+		unsafe {
+			if self.val1 != 0 {
+				T::__destruct(self.val1 as *mut [u8; 0]);
+				dealloc(self.val1 as *mut u8, Self::layout());
+			}
+		}
+	}
+}
+
 #[repr(C)]
-#[derive(Clone, Debug, Default)]
+#[derive(Debug, Default)]
 pub struct CPtr<T> {
 	pub addr: usize,
 	_phantom: PhantomData<T>,
 }
 
+// a derived Clone would require `T: Clone`, though only the address is copied.
+impl<T> Clone for CPtr<T> {
+	fn clone(&self) -> Self {
+		Self { addr: self.addr, _phantom: PhantomData }
+	}
+}
+
 pub trait AsCPtr<T> {
 	fn as_cptr(&self) -> CPtr<T>;
 }
@@ -127,6 +397,91 @@ impl<T> AsCPtr<T> for UniquePtr<T>
 	}
 }
 
+impl<T> AsCPtr<T> for CppBox<T>
+	where T: CppClass
+{
+	fn as_cptr(&self) -> CPtr<T> {
+		CPtr::<T> {
+			addr: self.val1,
+			_phantom: PhantomData
+		}
+	}
+}
+
+// the pointer is the first field of all of them, so they can be viewed as a CPtr, which makes
+// the `{Class}Methods` traits of `#[member_of]` functions work on them too.
+// There is no DerefMut: `*ptr = other_cptr` would swap the owned address under the owner.
+impl<T> Deref for SharedPtr<T> where T: DropSP {
+	type Target = CPtr<T>;
+	fn deref(&self) -> &CPtr<T> {
+		unsafe { &*(self as *const Self as *const CPtr<T>) }
+	}
+}
+impl<T> Deref for UniquePtr<T> where T: ManDtor {
+	type Target = CPtr<T>;
+	fn deref(&self) -> &CPtr<T> {
+		unsafe { &*(self as *const Self as *const CPtr<T>) }
+	}
+}
+impl<T> Deref for CppBox<T> where T: CppClass {
+	type Target = CPtr<T>;
+	fn deref(&self) -> &CPtr<T> {
+		unsafe { &*(self as *const Self as *const CPtr<T>) }
+	}
+}
+
+// C++ operators of a class from `#[operator]` member functions, implemented by the bridge
+// for the class itself, since the std traits can't be implemented for CPtr<T> outside this crate.
+#[doc(hidden)]
+pub trait CppEq: Sized {
+	fn __eq(a: &CPtr<Self>, b: &CPtr<Self>) -> bool;
+}
+#[doc(hidden)]
+pub trait CppOrd: CppEq {
+	fn __lt(a: &CPtr<Self>, b: &CPtr<Self>) -> bool;
+}
+#[doc(hidden)]
+pub trait CppIndex<Idx>: Sized {
+	type Output: ?Sized;
+	fn __index(a: &CPtr<Self>, idx: Idx) -> &Self::Output;
+}
+
+/// `operator==` of the C++ class.
+impl<T> PartialEq for CPtr<T> where T: CppEq {
+	fn eq(&self, other: &Self) -> bool {
+		T::__eq(self, other)
+	}
+}
+/// `operator<` and `operator==` of the C++ class.
+impl<T> PartialOrd for CPtr<T> where T: CppOrd {
+	fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+		if T::__eq(self, other) {
+			Some(CmpOrdering::Equal)
+		} else if T::__lt(self, other) {
+			Some(CmpOrdering::Less)
+		} else {
+			Some(CmpOrdering::Greater)
+		}
+	}
+}
+/// `operator[]` of the C++ class.
+impl<T, Idx> Index<Idx> for CPtr<T> where T: CppIndex<Idx> {
+	type Output = T::Output;
+	fn index(&self, idx: Idx) -> &T::Output {
+		T::__index(self, idx)
+	}
+}
+impl<T> PartialEq for CppBox<T> where T: CppClass + CppEq {
+	fn eq(&self, other: &Self) -> bool {
+		T::__eq(self, other)
+	}
+}
+impl<T> PartialOrd for CppBox<T> where T: CppClass + CppOrd {
+	fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+		(**self).partial_cmp(&**other)
+	}
+}
+
 #[repr(C)]
 struct FutureValueInner<T> {
 	f_set_value: fn(usize, &T),
@@ -163,6 +518,8 @@ impl<T> Default for FutureValue<T> where T:Clone {
 }
 
 impl<T> FutureValue<T> where T: Clone {
+	/// # Safety
+	/// The returned address holds a strong reference; it must be handed to `set_value` exactly once.
 	pub unsafe fn to_ptr(&mut self) -> usize {
 		let p1 = self as *mut Self as *mut *mut AtomicUsize;
 		(**p1).fetch_add(1, Ordering::Relaxed);
@@ -187,3 +544,111 @@ impl<T> FutureValue<T> where T: Clone {
 		drop(value_clone);
 	}
 }
+
+/// A C++ exception caught by the trampoline in `rust-catch.h`, returned by bridged
+/// functions declared as `-> Result<T, CppError>`.
+#[repr(C)]
+#[derive(Clone)]
+pub struct CppError {
+	f_set_what: extern "C" fn(*mut CppError, *const u8, usize),
+	what: String,
+}
+
+impl Default for CppError {
+	fn default() -> Self {
+		Self {
+			f_set_what: Self::set_what,
+			what: String::new(),
+		}
+	}
+}
+
+impl CppError {
+	/// The `what()` of the exception, or "unknown C++ exception" if it was not a `std::exception`.
+	pub fn what(&self) -> &str {
+		&self.what
+	}
+	extern "C" fn set_what(this: *mut CppError, what: *const u8, len: usize) {
+		unsafe {
+			let what = std::slice::from_raw_parts(what, len);
+			(*this).what = String::from_utf8_lossy(what).into_owned();
+		}
+	}
+}
+
+impl std::fmt::Debug for CppError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("CppError").field("what", &self.what).finish()
+	}
+}
+
+impl std::fmt::Display for CppError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "C++ exception: {}", self.what)
+	}
+}
+
+impl std::error::Error for CppError {}
+
+#[doc(hidden)]
+pub trait FnArgs<A, R> {
+	/// # Safety
+	/// `args` must hold a valid pointer for every argument type of `A`.
+	unsafe fn call_with(&mut self, args: *const *const u8) -> R;
+}
+
+macro_rules! impl_fn_args {
+	($($t:ident $i:tt),*) => {
+		impl<F, R, $($t: Clone),*> FnArgs<($($t,)*), R> for F where F: FnMut($($t),*) -> R {
+			unsafe fn call_with(&mut self, _args: *const *const u8) -> R {
+				self($((*(*_args.add($i) as *const $t)).clone()),*)
+			}
+		}
+	};
+}
+impl_fn_args!();
+impl_fn_args!(A0 0);
+impl_fn_args!(A0 0, A1 1);
+impl_fn_args!(A0 0, A1 1, A2 2);
+impl_fn_args!(A0 0, A1 1, A2 2, A3 3);
+impl_fn_args!(A0 0, A1 1, A2 2, A3 3, A4 4);
+impl_fn_args!(A0 0, A1 1, A2 2, A3 3, A4 4, A5 5);
+
+/// A rust closure handed to C++, where it is a `RustFn<R(A...)>` from `rust-spt.h`.
+/// `A` is the tuple of argument types. C++ can move it away to keep it, the closure is
+/// dropped when C++ destroys it, or after the call if C++ did not take it.
+#[repr(C)]
+pub struct RustFn<A, R> {
+	data: *mut u8,
+	f_call: unsafe extern "C" fn(*mut u8, *mut u8, *const *const u8),
+	f_drop: unsafe extern "C" fn(*mut u8),
+	_phantom: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R> RustFn<A, R> {
+	pub fn new<F>(f: F) -> Self where F: FnArgs<A, R> + Send + 'static {
+		Self {
+			data: Box::into_raw(Box::new(f)) as *mut u8,
+			f_call: Self::call_thunk::<F>,
+			f_drop: Self::drop_thunk::<F>,
+			_phantom: PhantomData,
+		}
+	}
+	unsafe extern "C" fn call_thunk<F: FnArgs<A, R>>(data: *mut u8, ret: *mut u8, args: *const *const u8) {
+		let r = (*(data as *mut F)).call_with(args);
+		if !ret.is_null() {
+			std::ptr::write(ret as *mut R, r);
+		}
+	}
+	unsafe extern "C" fn drop_thunk<F>(data: *mut u8) {
+		drop(Box::from_raw(data as *mut F));
+	}
+}
+
+impl<A, R> Drop for RustFn<A, R> {
+	fn drop(&mut self) {
+		if !self.data.is_null() {
+			unsafe { (self.f_drop)(self.data) };
+		}
+	}
+}
@@ -5,6 +5,14 @@ fn main() {
 	let projname = "test_cpp";
     println!("cargo:rerun-if-changed=cpp/prove.cpp");
 	let res_path = std::env::var("DEP_DIRECTCPP_RES_MPATH").unwrap();
+	// the bridge is lowered for the target, not for the host running the macros.
+	directcpp_build::forward_target();
+	// the structs shared with c++ are generated from their rust definitions, into OUT_DIR.
+	println!("cargo:rerun-if-changed=src/main.rs");
+	let out_dir = env::var("OUT_DIR").unwrap();
+	directcpp_build::Rust2H::new().file("src/main.rs").write_to(format!("{}/prove_types.h", out_dir)).unwrap();
+	// and the declarations the #[bridge] blocks expect, checked by PROVE_FFI_ENABLE() in prove.cpp.
+	directcpp_build::Bridge2H::new().file("src/main.rs").write_to(format!("{}/prove_ffi.h", out_dir)).unwrap();
     let from_vs = env::var("VisualStudioDir").map(|x| !x.is_empty()).unwrap_or(false);
     let is_debug = env::var("PROFILE").map(|x| x == "debug").unwrap_or(false);
     if from_vs {
@@ -18,7 +26,7 @@ fn main() {
     } else {
         let mut cxxb = cc::Build::new();
 		cxxb.cpp(true).std("c++20");
-		cxxb.includes(&[res_path]);
+		cxxb.includes(&[res_path, out_dir]);
         if cfg!(target_os = "windows") {
             env::set_var("VSLANG", "1033");
             cxxb.flag("/EHsc").flag("/utf-8")
@@ -6,34 +6,31 @@
 #include <vector>
 #include <iostream>
 #include <memory>
+#include <functional>
 #include <thread>
 #include <chrono>
+#include <stdexcept>
 #include "rust/rust-spt.h"
+// enums named by #[cpp_type] can't be forward declared by the generated header.
+enum class Fruit : int { Apple, Pear };
+#include "prove_ffi.h"
 #ifdef _WIN32
 #define WIN32_LEAN_AND_MEAN
 #include <Windows.h>
 #endif
 #define extc extern "C"
 
-// this is the two structs that we have in rust side.
-// for real world usage, you can generate this from rust side and include it here.
-struct MagicIn {
-	int ivalue;
-	float fvalue;
-	RustString svalue;
-};
+// this is the two structs that we have in rust side, generated by directcpp-build in build.rs.
+#include "prove_types.h"
 
-struct MagicOut {
-	int64_t ivalue;
-	double fvalue;
-	RustString sa;
-	RustString sb;
-};
+// fails to compile if the structs drift from the #[layout_check] ones in rust.
+PROVE_FFI_LAYOUT_CHECK();
 
 class Proof
 {
 	std::string name = "haystack";
 	std::vector<std::string> dummy;
+	std::vector<int> lengths;
 
 public:
 	~Proof() {
@@ -41,20 +38,81 @@ public:
 	}
 	void AddString(const RustString& str) {
 		dummy.push_back(str.str());
+		lengths.push_back((int)str.size());
+	}
+	const std::string& Name() const {
+		return name;
+	}
+	const std::vector<int>& Lengths() const {
+		return lengths;
+	}
+	void Print() const;
+	static std::shared_ptr<Proof> Create(int count) {
+		auto ret = std::make_shared<Proof>();
+		for (int i = 0; i < count; ++i) {
+			ret->dummy.push_back("item #" + std::to_string(i));
+		}
+		return ret;
+	}
+	int Size() const {
+		return (int)dummy.size();
 	}
-	void Print();
 	void foo() {
 		std::cout << "foo " << name << std::endl;
 	}
+	RustString Describe(const char* tag, size_t tag_len) const {
+		if (tag_len == 0) throw 42;  // not a std::exception
+		return RustString((name + ":" + std::string(tag, tag_len)).c_str());
+	}
 };
 
-void Proof::Print()
+void Proof::Print() const
 {
 	for (auto& s : dummy) {
 		std::cout << "cxx: got str: " << s << std::endl;
 	}
 }
 
+// a value type held by rust in a CppBox<Counter>, constructed and destructed in place.
+// the constructor and destructor are out of line, so their symbols are always emitted.
+class Counter
+{
+	std::string name;
+	int value;
+	std::vector<int> steps;
+
+public:
+	Counter(const char* name, size_t name_len, int start);
+	~Counter();
+	int Add(int delta) {
+		steps.push_back(delta);
+		return value += delta;
+	}
+	bool operator==(const Counter& other) const {
+		return value == other.value;
+	}
+	bool operator<(const Counter& other) const {
+		return value < other.value;
+	}
+	const int& operator[](size_t idx) const {
+		return steps.at(idx);
+	}
+	// returned through the hidden pointer, which comes before `this` except on MSVC.
+	RustString Name() const {
+		return RustString(name.data(), name.size());
+	}
+};
+
+Counter::Counter(const char* name, size_t name_len, int start)
+	: name(name, name_len), value(start)
+{
+}
+
+Counter::~Counter()
+{
+	std::cout << "Counter " << name << " destructed at " << value << std::endl;
+}
+
 void on_start() {
 #ifdef _WIN32
 	SetConsoleCP(CP_UTF8);
@@ -107,12 +165,178 @@ RustString join_strings(const RustVec<rust_refstr_t>& parts) {
 	return RustString(out.data(), out.size());
 }
 
+// an owned rust String is moved in, the one kept before is freed by rust's allocator.
+static RustString kept;
+size_t keep_string(RustString&& s)
+{
+	kept = std::move(s);
+	return kept.len;
+}
+
+RustString kept_string()
+{
+	return kept;
+}
+
+int64_t sum_owned(RustVec<int> v)
+{
+	int64_t sum = 0;
+	for (int x : v) sum += x;
+	return sum;
+}
+
+// overloads, rust binds them with #[cpp_name("scale")].
+int scale(int v)
+{
+	return v * 2;
+}
+
+double scale(double v)
+{
+	return v * 2.5;
+}
+
+// `long long` and `char` are not what rust would guess for i64 and i8.
+long long fruit_weight(Fruit f, char grade)
+{
+	return (f == Fruit::Pear ? 200 : 150) + (grade == 'A' ? 20 : 0);
+}
+
+// rust `char` is a char32_t, `isize` a ptrdiff_t.
+char32_t shift_char(char32_t c, ptrdiff_t by)
+{
+	return (char32_t)(c + by);
+}
+
+// legacy style: user data as void*, results through out-pointers.
+static void* g_user_data = nullptr;
+void* swap_user_data(void* data)
+{
+	void* old = g_user_data;
+	g_user_data = data;
+	return old;
+}
+
+bool min_max(const int* vals, size_t n, int* lo, int* hi)
+{
+	if (n == 0) return false;
+	*lo = *hi = vals[0];
+	for (size_t i = 1; i < n; ++i) {
+		if (vals[i] < *lo) *lo = vals[i];
+		if (vals[i] > *hi) *hi = vals[i];
+	}
+	return true;
+}
+
+// slices are (pointer, length) pairs, C++ fills a rust buffer in place.
+void fill_ramp(uint8_t* buf, size_t buf_len, uint8_t start)
+{
+	for (size_t i = 0; i < buf_len; ++i)
+		buf[i] = (uint8_t)(start + i);
+}
+
+int total_ivalue(const MagicIn* items, size_t items_len)
+{
+	int sum = 0;
+	for (size_t i = 0; i < items_len; ++i)
+		sum += items[i].ivalue;
+	return sum;
+}
+
+Vec2 vec2_add(Vec2 a, Vec2 b)
+{
+	return Vec2{a.x + b.x, a.y + b.y};
+}
+
+float vec2_dot(Vec2 a, Vec2 b)
+{
+	return a.x * b.x + a.y * b.y;
+}
+
+// arrays keep their length in the type.
+void scale_vec4(const float (&v)[4], float by, float (&out)[4])
+{
+	for (int i = 0; i < 4; ++i)
+		out[i] = v[i] * by;
+}
+
+uint32_t checksum(const uint8_t* digest)
+{
+	uint32_t sum = 0;
+	for (int i = 0; i < 32; ++i)
+		sum += digest[i];
+	return sum;
+}
+
+template<class T>
+T clamp_value(T v, T lo, T hi)
+{
+	return v < lo ? lo : (v > hi ? hi : v);
+}
+
+template<class T, int N>
+T sum_first(const T* vals, size_t n)
+{
+	T sum = 0;
+	for (size_t i = 0; i < n && i < N; ++i)
+		sum += vals[i];
+	return sum;
+}
+
+// the instances rust links to, `template float clamp_value<float>(float, float, float);`...
+PROVE_FFI_INSTANTIATE();
+
+int checked_div(int a, int b)
+{
+	if (b == 0) throw std::invalid_argument("checked_div: division by zero");
+	return a / b;
+}
+
+static std::vector<std::function<void(int, const RustString&)>> event_handlers;
+
+void on_event(RustFn<void(int, const RustString&)>& cb)
+{
+	event_handlers.push_back(std::move(cb));
+}
+
+int fire_events(int count)
+{
+	for (int i = 1; i <= count; ++i) {
+		RustString what(("event #" + std::to_string(i)).c_str());
+		for (auto& h : event_handlers) {
+			h(i, what);
+		}
+	}
+	return (int)event_handlers.size();
+}
+
+void clear_events()
+{
+	event_handlers.clear();
+}
+
 namespace myns {
 	RustString get_message() {
 		return "message from c++";
 	}
 }
 
+std::unique_ptr<std::string> make_greeting(const char* name, size_t name_len)
+{
+	return std::make_unique<std::string>("hello, " + std::string(name, name_len));
+}
+
+size_t greeting_len(const std::string& s)
+{
+	return s.size();
+}
+
+namespace myns::v2 {
+	[[gnu::abi_tag("tagged")]] int version() {
+		return 2;
+	}
+}
+
 void slow_tostr(ValuePromise<RustString> * res, int arg)
 {
 	std::thread th([=](){
@@ -127,17 +351,12 @@ void slow_tostr(ValuePromise<RustString> * res, int arg)
 // the only costs in c++ side is to enable some classes and structures for interop.
 // only those type used in return values need this. Those used in arguments do not need this.
 // you can put the forced references in a separate function that is never called to avoid runtime cost.
+// PROVE_FFI_ENABLE() comes from prove_ffi.h generated by directcpp_build::Bridge2H: it registers every
+// return type and takes the address of every bridged function with the exact signature rust expects,
+// so a mismatch fails here at compile time rather than at link time.
 void unused_function(volatile void** ptr) {
-	ffi::enable_class_sp<Proof>();
-	ffi::enable_class<MagicOut>();
-	ffi::enable_class<RustString>();
-	ffi::enable_class<RustVec<uint8_t>>();
-
-	// if your functions are inlined, you should force reference them to have a function body so that rust can find them.
-	// non-inline functions are not required, leave them as is.
-	// no need to call: force_ref(&Proof::Print);
-	// So, normally you should not use inline(including auto inlined functions in class declaration) functions for interop.
-	// I'm just putting them here for an example, not for real world usage.
-	ffi::force_ref(&Proof::foo);
-	ffi::force_ref(&Proof::AddString);
+	PROVE_FFI_ENABLE();
 }
+
+// instantiates the exception catching trampolines of the Result<T, CppError> functions.
+PROVE_FFI_CATCH();
@@ -1,11 +1,45 @@
-use directcpp::{SharedPtr, DropSP, CPtr, AsCPtr, FutureValue};
-use std::ffi::{CStr, CString};
+use directcpp::{SharedPtr, UniquePtr, DropSP, CPtr, AsCPtr, CxxString, CxxVector, FutureValue, CppError, RustFn, CppBox, CppClass, CppEq, CppOrd, CppIndex};
+use std::sync::{Arc, atomic::{AtomicI32, Ordering}};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ffi::{CStr, CString, c_void};
 use std::thread::sleep;
 use std::time::Duration;
 use tokio::runtime::Runtime;
 use tokio::sync::oneshot;
 use log;
 
+// not compatible with malloc on purpose: each block follows a header of its size and a tag,
+// so a String or Vec made by malloc in C++, or freed with a wrong layout, aborts here.
+struct TaggedAlloc;
+const ALLOC_TAG: usize = 0x7a99_ed;
+
+unsafe impl GlobalAlloc for TaggedAlloc {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		let pad = layout.align().max(16);
+		let ptr = System.alloc(Layout::from_size_align_unchecked(layout.size() + pad, pad));
+		if ptr.is_null() {
+			return ptr;
+		}
+		let ptr = ptr.add(pad);
+		*(ptr.sub(16) as *mut usize) = layout.size();
+		*(ptr.sub(8) as *mut usize) = ALLOC_TAG;
+		ptr
+	}
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		let pad = layout.align().max(16);
+		if *(ptr.sub(8) as *const usize) != ALLOC_TAG || *(ptr.sub(16) as *const usize) != layout.size() {
+			eprintln!("dealloc of a block not from the rust allocator: {:p} {:?}", ptr, layout);
+			std::process::abort();
+		}
+		*(ptr.sub(8) as *mut usize) = 0;
+		System.dealloc(ptr.sub(pad), Layout::from_size_align_unchecked(layout.size() + pad, pad));
+	}
+}
+
+#[global_allocator]
+static GLOBAL: TaggedAlloc = TaggedAlloc;
+
+#[directcpp::layout_check]
 #[repr(C)]
 struct MagicIn{
 	ivalue: i32,
@@ -13,6 +47,7 @@ struct MagicIn{
 	svalue: String,
 }
 
+#[directcpp::layout_check]
 #[repr(C)]
 #[derive(Clone, Debug)]
 struct MagicOut{
@@ -22,7 +57,17 @@ struct MagicOut{
 	sb: String,
 }
 
+// trivially copyable, passed by value as POD<Vec2>.
+#[directcpp::layout_check]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Vec2{
+	x: f32,
+	y: f32,
+}
+
 struct Proof;
+struct Counter;
 
 #[directcpp::bridge]
 extern "C++" {
@@ -36,6 +81,9 @@ extern "C++" {
 	pub fn on_magic(magic: &MagicIn) -> MagicOut;
 	#[namespace(myns)]
 	pub fn get_message()->String;
+	#[namespace(myns::v2)]
+	#[abi_tag("tagged")]
+	pub fn version() -> i32;
 
 	// for complex object can only be handled at c++ side.
 	// rust will keep a reference to the shared_ptr
@@ -46,19 +94,100 @@ extern "C++" {
 	// newly-supported: pass a Rust Vec by reference to C++ (received as const RustVec<T>&).
 	pub fn join_strings(parts: &Vec<&str>) -> String;
 
+	// an owned String or Vec moves into C++, received as RustString&& and RustVec<T>.
+	pub fn keep_string(s: String) -> usize;
+	pub fn kept_string() -> String;
+	pub fn sum_owned(v: Vec<i32>) -> i64;
+
 	pub async fn slow_tostr(val: i32) -> String;
 
+	// a c++ exception must not unwind into rust. declared as Result<T, CppError>, the call goes
+	// through a trampoline catching it, instantiated by PROVE_FFI_CATCH() on the c++ side.
+	pub fn checked_div(a: i32, b: i32) -> Result<i32, CppError>;
+
+	// C++ overloads share a name, rust can't. #[cpp_name] gives the C++ name.
+	#[cpp_name("scale")]
+	pub fn scale_int(v: i32) -> i32;
+	#[cpp_name("scale")]
+	pub fn scale_f64(v: f64) -> f64;
+
+	// the C++ types differ from the guessed ones, the rust ones still decide the ABI.
+	#[cpp_type("long long")]
+	pub fn fruit_weight(#[cpp_type("Fruit")] f: i32, #[cpp_type("char")] grade: i8) -> i64;
+	pub fn shift_char(c: char, by: isize) -> char;
+
+	// raw pointers are passed as they are: `void*`, `const int*`, `int*`.
+	pub fn swap_user_data(data: *mut c_void) -> *mut c_void;
+	pub fn min_max(vals: *const i32, n: usize, lo: *mut i32, hi: *mut i32) -> bool;
+	pub fn fill_ramp(buf: &mut [u8], start: u8);
+	pub fn total_ivalue(items: &[MagicIn]) -> i32;
+	// small structs are passed in registers, the wrappers take and return `Vec2`.
+	pub fn vec2_add(a: POD<Vec2>, b: POD<Vec2>) -> POD<Vec2>;
+	pub fn vec2_dot(a: POD<Vec2>, b: POD<Vec2>) -> f32;
+	// arrays are passed by reference, `const float (&)[4]`, or decayed to a pointer.
+	pub fn scale_vec4(v: &[f32; 4], by: f32, out: &mut [f32; 4]);
+	pub fn checksum(#[cpp_type("const uint8_t*")] digest: &[u8; 32]) -> u32;
+	// function templates are bridged one instance at a time, `T clamp_value<float>(T, T, T)`.
+	#[template(f32)]
+	#[cpp_name("clamp_value")]
+	pub fn clamp_f32(v: f32, lo: f32, hi: f32) -> f32;
+	#[template(i32, 3)]
+	pub fn sum_first(vals: &[i32]) -> i32;
+
+	// a rust closure reaches c++ as `RustFn<void(int, const RustString&)>&`, c++ keeps it as
+	// a std::function. the closure is dropped when c++ destroys it.
+	pub fn on_event(cb: RustFn<(i32, String), ()>);
+	pub fn fire_events(count: i32) -> i32;
+	pub fn clear_events();
+
 	// for complex objects that can only be handled at rust side,
 	// we can always pass its address to cpp side via void* aka *const u8.
 	// so there is nothing special to do here.
 
-	// for member functions, we have another kind of magic.
+	// for member functions, we have another kind of magic. with a `&self`/`&mut self` receiver
+	// they are also methods of the ProofMethods trait on CPtr<Proof>, so they can be called on
+	// CPtr<Proof>, SharedPtr<Proof> and UniquePtr<Proof>. `&self` is a const member function.
 	#[member_of(Proof)]
 	pub fn foo();
 	#[member_of(Proof)]
-	pub fn AddString(str: &String);
+	pub fn AddString(&mut self, str: &String);
+	#[member_of(Proof)]
+	pub fn Print(&self);
+	#[member_of(Proof)]
+	#[const_fn]
+	pub fn Size() -> i32;
+	#[member_of(Proof)]
+	pub fn Describe(&self, tag: &str) -> Result<String, CppError>;
+	// std types stay in C++, rust reads them through CxxString and CxxVector<T>.
 	#[member_of(Proof)]
-	pub fn Print();
+	pub fn Name(&self) -> &CxxString;
+	#[member_of(Proof)]
+	pub fn Lengths(&self) -> &CxxVector<i32>;
+	pub fn make_greeting(name: &str) -> UniquePtr<CxxString>;
+	pub fn greeting_len(s: &CxxString) -> usize;
+	// a static member function is an associated function of the rust struct: Proof::Create(2).
+	#[static_of(Proof)]
+	pub fn Create(count: i32) -> SharedPtr<Proof>;
+
+	// a c++ value type in rust allocated storage, no factory function needed. the size and
+	// align are checked by PROVE_FFI_ENABLE(), the destructor runs when the CppBox drops.
+	#[constructor(Counter, size = 64, align = 8)]
+	pub fn new(name: &str, start: i32) -> CppBox<Counter>;
+	#[member_of(Counter)]
+	pub fn Add(&mut self, delta: i32) -> i32;
+	// operators: CPtr<Counter> and CppBox<Counter> get PartialEq from ==, PartialOrd from <,
+	// and Index from [].
+	#[member_of(Counter)]
+	#[operator(==)]
+	pub fn Equals(&self, other: &Counter) -> bool;
+	#[member_of(Counter)]
+	#[operator(<)]
+	pub fn Less(&self, other: &Counter) -> bool;
+	#[member_of(Counter)]
+	#[operator([])]
+	pub fn Step(&self, idx: u64) -> &i32;
+	#[member_of(Counter)]
+	pub fn Name(&self) -> String;
 }
 
 // for msvc-friendly we should link the debug library in the debug mode
@@ -91,6 +220,7 @@ fn main()
 	// let's call the on_start function in CPP!
 	on_start();
 	println!("{}", get_message());
+	assert_eq!(version(), 2);
 
 	/*
 	let mut x: u64 = 4;
@@ -111,11 +241,70 @@ fn main()
 	let xx = cpp_ptr(42, "Hello from Rust!", &CString::new("Hello from Rust!").unwrap(), &[1,2,3,4,5]);
 	println!("\x1b[1;34mRust: got shared_ptr, will call member!\x1b[0m");
 	Proof__foo(xx.as_cptr());
-	Proof__AddString(xx.as_cptr(), &"Hello from Rust!".to_string());
-	Proof__Print(xx.as_cptr());
+	xx.AddString(&"Hello from Rust!".to_string());
+	xx.Print();
+	assert_eq!(Proof__Size(xx.as_cptr()), 1);
+	println!("Rust: Describe = {:?}", xx.Describe("tagged"));
+	let err = Proof__Describe(xx.as_cptr(), "").unwrap_err();
+	assert_eq!(err.what(), "unknown C++ exception");
+	assert_eq!(xx.Name().to_str(), Ok("haystack"));
+	assert_eq!(xx.Lengths().as_slice(), &[16]);
+	let greeting = make_greeting("rust");
+	let greeting = greeting.get().unwrap();
+	assert_eq!(greeting.to_string(), "hello, rust");
+	assert_eq!(greeting_len(greeting), greeting.len());
 	println!("\x1b[1;34mdropping the shared_ptr in rust!\x1b[0m");
 	drop(xx);
 
+	let yy = Proof::Create(2);
+	assert_eq!(Proof__Size(yy.as_cptr()), 2);
+	yy.Print();
+
+	let counter = Counter::new("apples", 40);
+	assert_eq!(counter.Add(2), 42);
+	let pears = Counter::new("pears", 30);
+	pears.Add(10);
+	pears.Add(2);
+	assert!(counter == pears);
+	pears.Add(1);
+	assert!(counter < pears);
+	assert_eq!((pears[0], pears[1], pears[2]), (10, 2, 1));
+	assert_eq!(pears.Name(), "pears");
+	drop(counter);
+
+	assert_eq!(keep_string("moved to c++".to_string()), 12);
+	assert_eq!(keep_string("again".to_string()), 5);
+	assert_eq!(kept_string(), "again");
+	assert_eq!(sum_owned(vec![1, 2, 3, 4]), 10);
+
+	assert_eq!(scale_int(21), 42);
+	assert_eq!(scale_f64(2.0), 5.0);
+	assert_eq!(fruit_weight(1, b'A' as i8), 220);
+	assert_eq!(shift_char('a', 2), 'c');
+	let mut user_data = 7;
+	assert!(swap_user_data(&mut user_data as *mut i32 as *mut c_void).is_null());
+	assert_eq!(swap_user_data(std::ptr::null_mut()), &mut user_data as *mut i32 as *mut c_void);
+	let vals = [4, -2, 9];
+	let (mut lo, mut hi) = (0, 0);
+	assert!(min_max(vals.as_ptr(), vals.len(), &mut lo, &mut hi));
+	assert_eq!((lo, hi), (-2, 9));
+	assert_eq!(checked_div(42, 2).unwrap(), 21);
+	match checked_div(42, 0) {
+		Ok(v) => panic!("expect an exception, got {}", v),
+		Err(e) => println!("Rust: caught {}", e),
+	}
+
+	let hits = Arc::new(AtomicI32::new(0));
+	let hits2 = hits.clone();
+	on_event(RustFn::new(move |id: i32, what: String| {
+		println!("Rust: event {} from c++: {}", id, what);
+		hits2.fetch_add(id, Ordering::Relaxed);
+	}));
+	assert_eq!(fire_events(3), 1);
+	assert_eq!(hits.load(Ordering::Relaxed), 1 + 2 + 3);
+	clear_events();
+	assert_eq!(Arc::strong_count(&hits), 1);
+
 	let bin = get_bin();
 	let bin = bin.iter().map(|x| format!("{:02x}", x)).collect::<Vec<String>>().join("");
 	println!("Rust: got bin: {}", bin);
@@ -132,6 +321,24 @@ fn main()
 		svalue: "Bonjour!".to_string(),
 	};
 
+	let mut ramp = [0u8; 4];
+	fill_ramp(&mut ramp, 7);
+	assert_eq!(ramp, [7, 8, 9, 10]);
+	let items = [
+		MagicIn{ ivalue: 8, fvalue: 0.0, svalue: String::new() },
+		MagicIn{ ivalue: 34, fvalue: 0.0, svalue: String::new() },
+	];
+	assert_eq!(total_ivalue(&items), 42);
+	let (a, b) = (Vec2{ x: 1.0, y: 2.0 }, Vec2{ x: 3.0, y: -1.0 });
+	assert_eq!(vec2_add(a, b), Vec2{ x: 4.0, y: 1.0 });
+	assert_eq!(vec2_dot(a, b), 1.0);
+	let mut scaled = [0f32; 4];
+	scale_vec4(&[1.0, 2.0, 3.0, 4.0], 0.5, &mut scaled);
+	assert_eq!(scaled, [0.5, 1.0, 1.5, 2.0]);
+	assert_eq!(checksum(&[3u8; 32]), 96);
+	assert_eq!(clamp_f32(7.5, 0.0, 5.0), 5.0);
+	assert_eq!(sum_first(&[1, 2, 3, 4]), 6);
+
 	println!("\x1b[1;34mLets do magic IO!\x1b[0m");
 	let mgo = on_magic(&mut msgin);
 	println!("Rust: got magic: {:?}", mgo);
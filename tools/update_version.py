@@ -48,6 +48,7 @@ def main():
     files_to_update = [
         "Cargo.toml",
         "macro/Cargo.toml",
+        "build/Cargo.toml",
         "test_proj/Cargo.toml"
     ]
 